@@ -0,0 +1,131 @@
+//! Replays access traces against a cache of fixed-size buffers, across
+//! policies and `max_heat` settings.
+//!
+//! Each access `get`s its key; on a miss, a buffer is `take`n (evicting an
+//! entry once the pool is exhausted) and `insert`ed under the key. Hit
+//! rates are printed alongside the timings.
+//!
+//! Besides the generated traces, any file in `benches/traces/` is replayed
+//! too: one decimal key per line.
+
+use std::fs;
+use std::hint::black_box;
+
+use criterion::{criterion_group,criterion_main,BenchmarkId,Criterion};
+use poolcache::{PoolCache,Policy};
+
+// buffers in the pool, i.e. the number of keys the cache can hold.
+const CAPACITY: usize = 1000;
+const ACCESSES: usize = 100_000;
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Keys drawn from a zipfian distribution over `keys` keys.
+fn zipfian(keys: usize, skew: f64) -> Vec<u64> {
+    let mut cdf: Vec<f64> = Vec::with_capacity(keys);
+    let mut total = 0.0;
+    for rank in 1..=keys {
+        total += 1.0 / (rank as f64).powf(skew);
+        cdf.push(total);
+    }
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    (0..ACCESSES).map(|_| {
+        let target = rng.next() * total;
+        cdf.partition_point(|&weight| weight < target) as u64
+    }).collect()
+}
+
+// A hot zipfian working set, interrupted by one-off scans of cold keys.
+fn scan(keys: usize) -> Vec<u64> {
+    let hot = zipfian(keys, 1.0);
+    let mut trace = Vec::with_capacity(ACCESSES);
+    let mut cold = keys as u64;
+    for chunk in hot.chunks(10 * CAPACITY) {
+        trace.extend_from_slice(&chunk[..chunk.len() / 2]);
+        for _ in 0..(chunk.len() - chunk.len() / 2) {
+            trace.push(cold);
+            cold += 1;
+        }
+    }
+    trace
+}
+
+// Cycling over slightly more keys than fit in the cache.
+fn looping(keys: usize) -> Vec<u64> {
+    (0..ACCESSES).map(|idx| (idx % keys) as u64).collect()
+}
+
+fn traces() -> Vec<(String, Vec<u64>)> {
+    let mut traces = vec![
+        (String::from("zipfian"), zipfian(10 * CAPACITY, 0.9)),
+        (String::from("scan"), scan(10 * CAPACITY)),
+        (String::from("loop"), looping(CAPACITY + CAPACITY / 10)),
+    ];
+    if let Ok(entries) = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/benches/traces")) {
+        for entry in entries.filter_map(Result::ok) {
+            let contents = fs::read_to_string(entry.path()).unwrap();
+            let trace = contents.lines().filter_map(|line| line.trim().parse().ok()).collect();
+            traces.push((entry.file_name().to_string_lossy().into_owned(), trace));
+        }
+    }
+    traces
+}
+
+fn configs() -> Vec<(String, u64, Policy)> {
+    let mut configs: Vec<(String, u64, Policy)> = [1, 4, 16].iter().map(|&max_heat| {
+        (format!("clock-{}", max_heat), max_heat, Policy::Clock)
+    }).collect();
+    configs.push((String::from("lfu"), 0, Policy::Lfu { period: 10 * CAPACITY as u64 }));
+    configs.push((String::from("clock-pro"), 0, Policy::ClockPro));
+    configs.push((String::from("2q"), 4, Policy::TwoQ));
+    configs.push((String::from("sampled-5"), 4, Policy::Sampled { samples: 5 }));
+    configs.push((String::from("bucketed-16"), 16, Policy::Bucketed));
+    configs.push((String::from("lazy-clock-16"), 16, Policy::LazyClock));
+    configs.push((String::from("gdsf"), 4, Policy::Gdsf));
+    configs.push((String::from("weighted-random"), 4, Policy::WeightedRandom));
+    configs
+}
+
+// Returns the number of hits.
+fn replay(trace: &[u64], max_heat: u64, policy: Policy) -> usize {
+    let mut cache: PoolCache<u64, Vec<u8>> = PoolCache::with_policy(max_heat, policy);
+    for _ in 0..CAPACITY {
+        cache.put(vec![0; 64]);
+    }
+    let mut hits = 0;
+    for &key in trace {
+        if cache.get(&key).is_some() {
+            hits += 1;
+        } else {
+            let buf = cache.take().unwrap();
+            cache.insert(key, buf);
+        }
+    }
+    hits
+}
+
+fn bench(c: &mut Criterion) {
+    for (name, trace) in traces() {
+        let mut group = c.benchmark_group(name.as_str());
+        for (config, max_heat, policy) in configs() {
+            let hits = replay(&trace, max_heat, policy);
+            println!("{}/{}: hit rate {:.3}", name, config, hits as f64 / trace.len() as f64);
+            group.bench_with_input(BenchmarkId::from_parameter(&config), &trace, |b, trace| {
+                b.iter(|| black_box(replay(trace, max_heat, policy)))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
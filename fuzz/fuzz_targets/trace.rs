@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use poolcache::{Policy,PoolCache,Trace};
+
+fuzz_target!(|input: (u8, u8, Trace<u8>)| {
+    let (policy, max_heat, trace) = input;
+    let policy = match policy % 6 {
+        0 => Policy::Clock,
+        1 => Policy::Lfu { period: 16 },
+        2 => Policy::ClockPro,
+        3 => Policy::TwoQ,
+        4 => Policy::Sampled { samples: 3 },
+        _ => Policy::Bucketed,
+    };
+    poolcache::fuzz::check(PoolCache::with_policy(u64::from(max_heat % 8) + 1, policy), &trace);
+});
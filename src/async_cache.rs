@@ -0,0 +1,252 @@
+//! A PoolCache for use from async code, locked with `tokio::sync`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc,Mutex};
+use std::task::{Context,Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::sync::{mpsc,oneshot,Notify,RwLock,RwLockReadGuard,RwLockWriteGuard};
+use tokio::{task,time};
+
+use super::{PoolCache,RemovalCause,Take};
+
+type Subscribers<Key> = Arc<Mutex<Vec<mpsc::UnboundedSender<(Key, RemovalCause)>>>>;
+
+// Loads the values for a batch of keys, in order.
+type BatchLoad<Key, Value> =
+    dyn Fn(Vec<Key>) -> Pin<Box<dyn Future<Output = Vec<Value>> + Send>> + Send + Sync;
+
+// Keyed entries `take` inspects before yielding to the runtime.
+const SCAN_BUDGET: usize = 64;
+
+/// A PoolCache that can be shared between async tasks.
+///
+/// Like `SyncPoolCache`, reads only take a read lock and writes take the
+/// write lock, but locking never blocks the executor. A `take` that has to
+/// scan many keyed entries to find one to evict releases the lock and
+/// yields to the runtime periodically, rather than stalling other tasks.
+pub struct AsyncPoolCache<Key, Value> {
+    cache: RwLock<PoolCache<Key, Value>>,
+    // receivers of `evictions`, once the cache has been hooked up to them.
+    subscribers: Mutex<Option<Subscribers<Key>>>,
+    batching: Option<Batching<Key, Value>>,
+}
+
+// The state of `with_batch_loader`.
+struct Batching<Key, Value> {
+    load: Box<BatchLoad<Key, Value>>,
+    window: Duration,
+    max_keys: usize,
+    // the batch collecting misses, if one is open.
+    open: Mutex<Option<Batch<Key, Value>>>,
+}
+
+// Misses waiting to be loaded together, and the callers waiting on each.
+struct Batch<Key, Value> {
+    waiters: BTreeMap<Key, Vec<oneshot::Sender<Value>>>,
+    // wakes the caller that opened the batch once it is full.
+    full: Arc<Notify>,
+}
+
+// Closes the batch its caller opened if the caller is dropped before
+// taking it, so that others don't wait on it forever.
+struct Opened<'a, Key, Value> {
+    open: &'a Mutex<Option<Batch<Key, Value>>>,
+    full: Arc<Notify>,
+}
+
+impl<'a, Key, Value> Drop for Opened<'a, Key, Value> {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().unwrap();
+        if open.as_ref().is_some_and(|batch| Arc::ptr_eq(&batch.full, &self.full)) {
+            // dropping the senders fails the waiting calls.
+            *open = None;
+        }
+    }
+}
+
+/// A stream of the keys leaving an `AsyncPoolCache`, returned by
+/// `evictions`.
+pub struct Evictions<Key> {
+    receiver: mpsc::UnboundedReceiver<(Key, RemovalCause)>,
+}
+
+impl<Key> Stream for Evictions<Key> {
+    type Item = (Key, RemovalCause);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+impl<Key, Value> AsyncPoolCache<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Wrap `cache` for sharing between tasks.
+        pub fn new(cache: PoolCache<Key, Value>) -> AsyncPoolCache<Key, Value> {
+            AsyncPoolCache{cache: RwLock::new(cache), subscribers: Mutex::new(None), batching: None}
+        }
+
+        /// Set the function `load` calls to fetch the values of missing keys
+        /// in batches. Misses are collected for up to `window` after the
+        /// first one, or until `max_keys` different keys are waiting, and
+        /// then loaded with a single call, which returns their values in the
+        /// order of the keys it was given.
+        pub fn with_batch_loader<F, Fut>(mut self, window: Duration, max_keys: usize, load: F)
+                                         -> AsyncPoolCache<Key, Value>
+            where F: Fn(Vec<Key>) -> Fut + Send + Sync + 'static,
+                  Fut: Future<Output = Vec<Value>> + Send + 'static {
+            self.batching = Some(Batching{
+                load: Box::new(move |keys| Box::pin(load(keys))),
+                window,
+                max_keys,
+                open: Mutex::new(None)});
+            self
+        }
+
+        /// Returns a clone of the value associated with `key`, loading it
+        /// with the batch loader if it isn't cached, together with the other
+        /// keys missed around the same time, and inserting it. The first
+        /// call to miss in a window waits out the window and makes the
+        /// batched call; if it is dropped first, the others in its batch
+        /// fail. Returns `None` if no batch loader was set, or the key's
+        /// batch failed, or the loader returned no value for it.
+        pub async fn load(&self, key: Key) -> Option<Value>
+            where Key: Clone,
+                  Value: Clone {
+            if let Some(val) = self.get_cloned(&key).await {
+                return Some(val);
+            }
+            let batching = self.batching.as_ref()?;
+            let (sender, receiver) = oneshot::channel();
+            let opened = {
+                let mut open = batching.open.lock().unwrap();
+                let opening = open.is_none();
+                let batch = open.get_or_insert_with(|| {
+                    Batch{waiters: BTreeMap::new(), full: Arc::new(Notify::new())}
+                });
+                batch.waiters.entry(key).or_default().push(sender);
+                if batch.waiters.len() >= batching.max_keys {
+                    batch.full.notify_one();
+                }
+                if opening {
+                    Some(Opened{open: &batching.open, full: batch.full.clone()})
+                } else {
+                    None
+                }
+            };
+            if let Some(opened) = opened {
+                let _ = time::timeout(batching.window, opened.full.notified()).await;
+                let batch = batching.open.lock().unwrap().take();
+                if let Some(batch) = batch {
+                    let keys: Vec<Key> = batch.waiters.keys().cloned().collect();
+                    let vals = (batching.load)(keys).await;
+                    let mut cache = self.write().await;
+                    for ((key, senders), val) in batch.waiters.into_iter().zip(vals) {
+                        for sender in senders {
+                            let _ = sender.send(val.clone());
+                        }
+                        cache.insert(key, val);
+                    }
+                }
+            }
+            receiver.await.ok()
+        }
+
+        /// Returns `true` if the given key is present in the cache.
+        pub async fn contains_key(&self, key: &Key) -> bool {
+            self.read().await.contains_key(key)
+        }
+
+        /// Calls `f` with the value associated with `key`, returning its
+        /// result, or `None` if the key is not present in the cache.
+        pub async fn with<F, R>(&self, key: &Key, f: F) -> Option<R>
+            where F: FnOnce(&Value) -> R {
+            self.read().await.get(key).map(f)
+        }
+
+        /// Returns a clone of the value associated with `key`, or `None`
+        /// if the key is not present in the cache.
+        pub async fn get_cloned(&self, key: &Key) -> Option<Value>
+            where Value: Clone {
+            self.read().await.get_cloned(key)
+        }
+
+        /// Counts a use of the entry for `key` without reading its value.
+        /// Returns `true` if the key was present in the cache.
+        pub async fn touch(&self, key: &Key) -> bool {
+            self.read().await.touch(key)
+        }
+
+        /// Add a new object to the pool, not associated with any key.
+        pub async fn put(&self, val: Value) {
+            self.write().await.put(val)
+        }
+
+        /// Insert `val` into the cache associated with `key`, replacing any
+        /// previous entry.
+        pub async fn insert(&self, key: Key, val: Value) {
+            self.write().await.insert(key, val)
+        }
+
+        /// Returns an object from the pool, evicting the least-used cached
+        /// key if necessary. Returns `None` if there is nothing `take` could
+        /// hand out, such as when only protected keys are cached.
+        pub async fn take(&self) -> Option<Value> {
+            loop {
+                let mut cache = self.write().await;
+                match cache.try_take(SCAN_BUDGET) {
+                    Take::Taken(val) => return Some(val),
+                    Take::Nothing => return None,
+                    Take::GaveUp => {}
+                }
+                // heat drained so far is kept, so the next attempt picks up
+                // where this one left off.
+                drop(cache);
+                task::yield_now().await;
+            }
+        }
+
+        /// Returns a stream of the keys that leave the cache from now on,
+        /// and why, so that a background task can persist or replicate
+        /// them. Any listener already set on the cache keeps being called.
+        /// The stream ends when the cache is dropped.
+        pub async fn evictions(&self) -> Evictions<Key>
+            where Key: Clone + Send + 'static,
+                  Value: 'static {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let mut cache = self.write().await;
+            let subscribers = self.subscribers.lock().unwrap().get_or_insert_with(|| {
+                // hook the cache up on first use.
+                let subscribers: Subscribers<Key> = Arc::new(Mutex::new(Vec::new()));
+                let forward = subscribers.clone();
+                let previous = cache.listener.take();
+                cache.listener = Some(Box::new(move |key: &Key, val: &Value, cause| {
+                    if let Some(ref previous) = previous {
+                        previous(key, val, cause);
+                    }
+                    forward.lock().unwrap().retain(|sender| {
+                        sender.send((key.clone(), cause)).is_ok()
+                    });
+                }));
+                subscribers
+            }).clone();
+            subscribers.lock().unwrap().push(sender);
+            Evictions{receiver}
+        }
+
+        /// Lock the cache for reading, for access to the rest of the
+        /// read-only PoolCache API.
+        pub async fn read(&self) -> RwLockReadGuard<'_, PoolCache<Key, Value>> {
+            self.cache.read().await
+        }
+
+        /// Lock the cache for writing, for access to the rest of the
+        /// PoolCache API.
+        pub async fn write(&self) -> RwLockWriteGuard<'_, PoolCache<Key, Value>> {
+            self.cache.write().await
+        }
+}
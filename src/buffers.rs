@@ -0,0 +1,58 @@
+//! Pooling `BytesMut` buffers by capacity, for the common case of a pool
+//! of byte buffers.
+
+use bytes::BytesMut;
+
+use super::PoolCache;
+
+// The tag prefix of pooled buffers, followed by their capacity class.
+const TAG: &str = "bytes/";
+// How many classes above the one asked for `take_with_capacity` looks in,
+// so that small requests don't use up much larger buffers.
+const MAX_OVERSIZE: u32 = 2;
+
+// The capacity class of a buffer that can hold `capacity` bytes: the
+// largest power of two it can hold, as its exponent.
+fn class_of(capacity: usize) -> u32 {
+    capacity.max(1).ilog2()
+}
+
+impl<Key, Meta> PoolCache<Key, BytesMut, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Return a buffer to the pool for `take_with_capacity`. It is
+        /// cleared and put under a tag for its capacity class (`bytes/12`
+        /// for 4096 to 8191 bytes, say), so that it is only handed out for
+        /// requests it can hold without growing.
+        pub fn put_buffer(&mut self, mut buf: BytesMut) {
+            buf.clear();
+            let tag = format!("{}{}", TAG, class_of(buf.capacity()));
+            self.put_tagged(&tag, buf);
+        }
+
+        /// Returns an empty buffer that can hold at least `capacity` bytes:
+        /// one returned with `put_buffer` in the smallest class that fits,
+        /// up to four times the capacity asked for, or failing that one
+        /// from `take` (an unkeyed object or the value of an evicted
+        /// entry), cleared and grown as needed, or failing that a new one.
+        pub fn take_with_capacity(&mut self, capacity: usize) -> BytesMut {
+            // the smallest class whose buffers all hold `capacity`.
+            let class = class_of(capacity) + !capacity.is_power_of_two() as u32;
+            for class in class..=class + MAX_OVERSIZE {
+                let tag = format!("{}{}", TAG, class);
+                if self.tagged_len(&tag) > 0 {
+                    if let Some(buf) = self.take_tagged(&tag) {
+                        return buf;
+                    }
+                }
+            }
+            match self.take() {
+                Some(mut buf) => {
+                    buf.clear();
+                    buf.reserve(capacity);
+                    buf
+                }
+                None => BytesMut::with_capacity(capacity),
+            }
+        }
+}
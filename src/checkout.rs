@@ -0,0 +1,89 @@
+//! Borrowing a keyed value for exclusive use, and returning it under its
+//! key without losing the entry's history.
+
+use std::sync::Arc;
+
+use super::{CacheEntry,PoolCache,RemovalCause};
+
+// What is kept of a checked out entry, to restore on `checkin`.
+pub struct Checkout<Key, Meta> {
+    heat: u64,
+    hot: bool,
+    test: bool,
+    max_heat: Option<u64>,
+    meta: Option<Meta>,
+    ns: Option<Arc<str>>,
+    groups: Vec<Arc<str>>,
+    deps: Vec<Arc<Key>>,
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Remove the value cached under `key` for exclusive use, counting a
+        /// use of the entry, until it is returned with `checkin`. The entry
+        /// leaves the cache (notifying the listener with
+        /// `RemovalCause::Invalidated`), but its heat, metadata, namespace,
+        /// tags and dependencies are kept for its return, and the entries
+        /// depending on it aren't invalidated. Returns `None` if the key is
+        /// not present.
+        pub fn checkout(&mut self, key: &Key) -> Option<Value> {
+            let entry = self.live(key)?.1;
+            self.hit(entry);
+            let deps = self.dependencies_of(key);
+            let entry = self.cache.get_mut(key).unwrap();
+            let kept = Checkout{
+                heat: entry.heat(),
+                hot: entry.hot,
+                test: entry.test,
+                max_heat: entry.max_heat,
+                meta: entry.meta.take(),
+                ns: entry.ns.clone(),
+                groups: entry.groups.clone(),
+                deps};
+            let (key, val) = self.remove_entry(key, RemovalCause::Invalidated)?;
+            self.checked_out.insert(key, kept);
+            Some(val)
+        }
+
+        /// Return `val` under `key` after a `checkout`, restoring the heat,
+        /// metadata, namespace, tags and dependencies the entry had, and
+        /// the queue it was in, so that borrowing a value doesn't make it
+        /// look unused or new. If `key` wasn't checked out, `val` is
+        /// inserted as a new entry, as by `insert`. Either way, an entry
+        /// inserted under `key` meanwhile is replaced.
+        pub fn checkin(&mut self, key: &Key, val: Value)
+            where Key: Clone {
+            let (stored, kept) = match self.checked_out.remove_entry(key) {
+                Some(checkout) => checkout,
+                None => return self.insert(key.clone(), val),
+            };
+            let mut entry = CacheEntry::new(val);
+            entry.set_heat(kept.heat);
+            entry.hot = kept.hot;
+            entry.test = kept.test;
+            entry.max_heat = kept.max_heat;
+            entry.meta = kept.meta;
+            entry.ns = kept.ns;
+            entry.groups = kept.groups;
+            let stored = Arc::try_unwrap(stored).unwrap_or_else(|key| (*key).clone());
+            // the entry returns to the queue it left, rather than being
+            // admitted afresh.
+            self.insert_entry_with(stored, entry, PoolCache::readmit);
+            if let Some((stored, _)) = self.cache.get_key_value(key) {
+                let stored = stored.clone();
+                self.depend(&stored, kept.deps);
+            }
+        }
+
+        /// Returns `true` if `key` was checked out and not yet returned.
+        pub fn is_checked_out(&self, key: &Key) -> bool {
+            self.checked_out.contains_key(key)
+        }
+
+        /// Forget that `key` was checked out, so that returning a value
+        /// under it inserts a new entry. Returns `true` if it was.
+        pub fn forget_checkout(&mut self, key: &Key) -> bool {
+            self.checked_out.remove(key).is_some()
+        }
+}
@@ -0,0 +1,144 @@
+//! A stripped-down Clock cache whose `max_heat` is fixed at compile time,
+//! for hot paths where the general PoolCache does too much per call.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8,Ordering};
+
+/// A Clock-LFU cache and object pool with `max_heat` fixed to `MAX_HEAT`.
+///
+/// With the limit a constant, the compiler can fold the clamp on every use
+/// and the decrement on every sweep, and heat is packed into a byte per
+/// entry, in an array of its own that `take` sweeps without touching the
+/// keys or values. In exchange it offers only the core of `PoolCache`
+/// with `Policy::Clock`: `insert`, `get`, `put` and `take`, without
+/// metadata, expiry, listeners or statistics. A `MAX_HEAT` of 0 leaves
+/// every entry cold, so that `take` evicts whichever entry is in the next
+/// slot the clock hand reaches. Slots emptied by eviction are reused, so
+/// that is insertion order only until the first eviction.
+pub struct CompactPoolCache<Key, Value, const MAX_HEAT: u8> {
+    // the slot of each key.
+    index: BTreeMap<Arc<Key>, usize>,
+    slots: Vec<Option<(Arc<Key>, Value)>>,
+    // the heat of each slot, alongside `slots`.
+    heats: Vec<AtomicU8>,
+    // slots emptied by eviction, for reuse.
+    holes: Vec<usize>,
+    freelist: Vec<Value>,
+    // the next slot the clock hand looks at.
+    hand: usize,
+}
+
+impl<Key, Value, const MAX_HEAT: u8> CompactPoolCache<Key, Value, MAX_HEAT>
+    where Key: PartialOrd + Ord {
+
+        /// Create an empty CompactPoolCache.
+        pub fn new() -> CompactPoolCache<Key, Value, MAX_HEAT> {
+            CompactPoolCache{
+                index: BTreeMap::new(),
+                slots: Vec::new(),
+                heats: Vec::new(),
+                holes: Vec::new(),
+                freelist: Vec::new(),
+                hand: 0}
+        }
+
+        /// Returns `true` if the given key is present in the cache.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.index.contains_key(key)
+        }
+
+        /// Returns a reference to the value associated with `key`, counting
+        /// a use of the entry, or `None` if the key is not present.
+        pub fn get(&self, key: &Key) -> Option<&Value> {
+            let idx = *self.index.get(key)?;
+            let heat = &self.heats[idx];
+            let hotter = cmp::min(heat.load(Ordering::Relaxed).saturating_add(1), MAX_HEAT);
+            heat.store(hotter, Ordering::Relaxed);
+            self.slots[idx].as_ref().map(|(_, val)| val)
+        }
+
+        /// Insert `val` under `key`. If the key was already cached, its old
+        /// value is moved to the pool.
+        pub fn insert(&mut self, key: Key, val: Value) {
+            if let Some(&idx) = self.index.get(&key) {
+                if let Some((_, ref mut old)) = self.slots[idx] {
+                    let old = mem::replace(old, val);
+                    self.freelist.push(old);
+                }
+                return;
+            }
+            let key = Arc::new(key);
+            let idx = match self.holes.pop() {
+                Some(idx) => {
+                    self.slots[idx] = Some((key.clone(), val));
+                    idx
+                }
+                None => {
+                    self.slots.push(Some((key.clone(), val)));
+                    self.heats.push(AtomicU8::new(0));
+                    self.slots.len() - 1
+                }
+            };
+            self.heats[idx].store(cmp::min(1, MAX_HEAT), Ordering::Relaxed);
+            self.index.insert(key, idx);
+        }
+
+        /// Add an object to the pool, not associated with any key.
+        pub fn put(&mut self, val: Value) {
+            self.freelist.push(val);
+        }
+
+        /// Returns an object from the pool, evicting the least-used cached
+        /// key if there are no unkeyed objects. Returns `None` only if the
+        /// cache holds no objects.
+        pub fn take(&mut self) -> Option<Value> {
+            if let Some(val) = self.freelist.pop() {
+                return Some(val);
+            }
+            if self.index.is_empty() {
+                return None;
+            }
+            loop {
+                let idx = self.hand;
+                self.hand = (self.hand + 1) % self.slots.len();
+                if self.slots[idx].is_none() {
+                    continue;
+                }
+                let heat = &self.heats[idx];
+                let cooler = heat.load(Ordering::Relaxed).saturating_sub(1);
+                heat.store(cooler, Ordering::Relaxed);
+                if cooler == 0 {
+                    let (key, val) = self.slots[idx].take().unwrap();
+                    self.index.remove(&key);
+                    self.holes.push(idx);
+                    return Some(val);
+                }
+            }
+        }
+
+        /// Returns the number of keyed entries.
+        pub fn len(&self) -> usize {
+            self.index.len()
+        }
+
+        /// Returns `true` if no keys are cached.
+        pub fn is_empty(&self) -> bool {
+            self.index.is_empty()
+        }
+
+        /// Returns the number of unkeyed objects that `take` can hand out.
+        pub fn pool_len(&self) -> usize {
+            self.freelist.len()
+        }
+}
+
+impl<Key, Value, const MAX_HEAT: u8> Default for CompactPoolCache<Key, Value, MAX_HEAT>
+    where Key: PartialOrd + Ord {
+
+        fn default() -> CompactPoolCache<Key, Value, MAX_HEAT> {
+            CompactPoolCache::new()
+        }
+}
@@ -0,0 +1,59 @@
+//! Comparing PoolCaches by what they hold, mainly for test assertions.
+
+use super::PoolCache;
+
+/// Two PoolCaches are equal if they map the same keys to equal values.
+/// Only live entries are compared: heat, eviction order, metadata and
+/// namespaces are ignored, as are unkeyed objects. To compare the pooled
+/// objects as well, use `eq_with_pool`.
+impl<Key, Value, Meta> PartialEq for PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord,
+          Value: PartialEq {
+
+        fn eq(&self, other: &PoolCache<Key, Value, Meta>) -> bool {
+            // both are in key order.
+            self.live_entries().eq(other.live_entries())
+        }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        // Returns the key and value of each unexpired, valid keyed entry,
+        // in key order.
+        pub(crate) fn live_entries(&self) -> impl Iterator<Item = (&Key, &Value)> {
+            self.cache.iter().filter(move |(key, entry)| !self.stale(key, entry))
+                .map(|(key, entry)| (&**key, &entry.val))
+        }
+
+        /// Returns `true` if the caches are equal (see `PartialEq`), and also
+        /// hold equal unkeyed objects, in any order, both untagged and under
+        /// each tag. Takes time quadratic in the number of unkeyed objects.
+        pub fn eq_with_pool(&self, other: &PoolCache<Key, Value, Meta>) -> bool
+            where Value: PartialEq {
+            if self != other || !same_objects(self.freelist.iter(), other.freelist.iter()) {
+                return false;
+            }
+            let tags = self.tagged.keys().chain(other.tagged.keys());
+            tags.into_iter().all(|tag| {
+                same_objects(self.tagged.get(tag).into_iter().flatten(),
+                             other.tagged.get(tag).into_iter().flatten())
+            })
+        }
+}
+
+// Returns `true` if `left` and `right` hold equal values, counting
+// duplicates, in any order.
+fn same_objects<'a, Value, L, R>(left: L, right: R) -> bool
+    where Value: PartialEq + 'a,
+          L: Iterator<Item = &'a Value>,
+          R: Iterator<Item = &'a Value> {
+    let mut unmatched: Vec<&Value> = right.collect();
+    for val in left {
+        match unmatched.iter().position(|other| *other == val) {
+            Some(idx) => { unmatched.swap_remove(idx); },
+            None => return false,
+        }
+    }
+    unmatched.is_empty()
+}
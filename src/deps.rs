@@ -0,0 +1,86 @@
+//! Dependencies between keyed entries, so that values derived from others
+//! are invalidated along with them.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::PoolCache;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Like `insert`, but makes the new entry depend on the entries for
+        /// `deps`, cached or not: when any of them is replaced (by `insert`
+        /// or `swap`) or invalidated (e.g. by `invalidate_key`), the new
+        /// entry is invalidated too, moving its value to the pool, and so on
+        /// through the entries that depend on it. Evicting an entry doesn't
+        /// affect its dependents, as its value hasn't changed. The
+        /// dependencies last as long as the entry, until it is replaced.
+        pub fn insert_with_deps(&mut self, key: Key, val: Value, deps: &[Key])
+            where Key: Clone {
+            self.insert(key.clone(), val);
+            let stored = match self.cache.get_key_value(&key) {
+                Some((stored, _)) => stored.clone(),
+                None => return,
+            };
+            let deps = deps.iter().map(|dep| match self.dependents.get_key_value(dep) {
+                Some((dep, _)) => dep.clone(),
+                None => Arc::new(dep.clone()),
+            }).collect();
+            self.depend(&stored, deps);
+        }
+
+        /// Remove the entry for `key`, moving its value to the pool, and
+        /// invalidate the entries that depend on it (see
+        /// `insert_with_deps`), whether or not `key` is cached. Returns
+        /// `true` if it was.
+        pub fn invalidate_key(&mut self, key: &Key) -> bool {
+            let stored = self.cache.get_key_value(key).map(|(stored, _)| stored.clone());
+            match stored {
+                Some(stored) => {
+                    self.invalidate(BTreeSet::from([stored]));
+                    true
+                }
+                None => {
+                    self.invalidate_dependents(key);
+                    false
+                }
+            }
+        }
+
+        // Returns the keys the entry for `key` depends on.
+        pub(crate) fn dependencies_of(&self, key: &Key) -> Vec<Arc<Key>> {
+            self.dependencies.get(key).cloned().unwrap_or_default()
+        }
+
+        // Record that the entry for `key` depends on the entries for `deps`.
+        pub(crate) fn depend(&mut self, key: &Arc<Key>, deps: Vec<Arc<Key>>) {
+            if deps.is_empty() {
+                return;
+            }
+            for dep in &deps {
+                self.dependents.entry(dep.clone()).or_default().insert(key.clone());
+            }
+            self.dependencies.entry(key.clone()).or_default().extend(deps);
+        }
+
+        // Forget what the entry for `key` depends on, as it leaves the cache
+        // or is replaced.
+        pub(crate) fn forget_dependencies(&mut self, key: &Key) {
+            for dep in self.dependencies.remove(key).into_iter().flatten() {
+                if let Some(keys) = self.dependents.get_mut(&dep) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.dependents.remove(&dep);
+                    }
+                }
+            }
+        }
+
+        // Invalidate the entries that depend on `key`, which has changed.
+        pub(crate) fn invalidate_dependents(&mut self, key: &Key) {
+            if let Some(keys) = self.dependents.remove(key) {
+                self.invalidate(keys);
+            }
+        }
+}
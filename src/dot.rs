@@ -0,0 +1,65 @@
+//! Drawing the eviction queues of a PoolCache as a Graphviz graph.
+
+use std::collections::VecDeque;
+use std::fmt::{Debug,Write};
+use std::iter;
+use std::sync::Arc;
+
+use super::PoolCache;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a Graphviz DOT graph of the eviction queues, each drawn as
+        /// a ring of entries in the order `take` visits them, labelled with
+        /// their keys and heat. Hot entries are drawn bold, and expired ones
+        /// dashed. The unkeyed objects are drawn as a single node giving
+        /// their number. Render it with e.g. `dot -Tsvg`.
+        pub fn to_dot(&self) -> String
+            where Key: Debug {
+            let mut dot = String::from("digraph poolcache {\n    node [shape=box];\n");
+            let queues = iter::once(("clock", &self.clock)).chain(iter::once(("hot", &self.hot)))
+                .chain(self.buckets.iter().map(|bucket| ("bucket", bucket)));
+            for (idx, (name, queue)) in queues.enumerate() {
+                if !queue.is_empty() {
+                    self.dot_queue(&mut dot, idx, name, queue);
+                }
+            }
+            let _ = writeln!(dot, "    freelist [shape=ellipse, label=\"freelist: {}\\ntagged: {}\"];",
+                             self.freelist.len(), self.tagged_total());
+            dot.push_str("}\n");
+            dot
+        }
+
+        // Write `queue` as a cluster of nodes named `q<idx>_<position>`,
+        // with an edge from each to the next, and from the last back to the
+        // first.
+        fn dot_queue(&self, dot: &mut String, idx: usize, name: &str, queue: &VecDeque<Arc<Key>>)
+            where Key: Debug {
+            let _ = writeln!(dot, "    subgraph cluster_{} {{", idx);
+            let _ = writeln!(dot, "        label=\"{}\";", name);
+            for (pos, key) in queue.iter().enumerate() {
+                let entry = &self.cache[key];
+                let mut style = Vec::new();
+                if entry.hot {
+                    style.push("bold");
+                }
+                if entry.expired() {
+                    style.push("dashed");
+                }
+                let label = escape(&format!("{:?}", key));
+                let _ = writeln!(dot, "        q{}_{} [label=\"{}\\nheat {}\", style=\"{}\"];",
+                                 idx, pos, label, entry.heat(), style.join(","));
+            }
+            for pos in 0..queue.len() {
+                let next = (pos + 1) % queue.len();
+                let _ = writeln!(dot, "        q{}_{} -> q{}_{};", idx, pos, idx, next);
+            }
+            dot.push_str("    }\n");
+        }
+}
+
+// Escape backslashes and quotes, for use in a quoted DOT string.
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
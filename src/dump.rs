@@ -0,0 +1,66 @@
+//! Human-readable dumps of the structure of a PoolCache, for attaching to
+//! bug reports.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::{json,Map,Value as Json};
+
+use super::PoolCache;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a JSON description of the cache: its policy and
+        /// `max_heat`, each eviction queue in order with the key, heat and
+        /// flags of its entries, and the number of unkeyed objects. Values
+        /// are left out; see `dump_json_with_values`.
+        pub fn dump_json(&self) -> serde_json::Result<String>
+            where Key: Serialize {
+            self.dump(|_| Ok(None))
+        }
+
+        /// Like `dump_json`, but includes the value of each entry.
+        pub fn dump_json_with_values(&self) -> serde_json::Result<String>
+            where Key: Serialize,
+                  Value: Serialize {
+            self.dump(|val| serde_json::to_value(val).map(Some))
+        }
+
+        fn dump<F>(&self, val: F) -> serde_json::Result<String>
+            where Key: Serialize,
+                  F: Fn(&Value) -> serde_json::Result<Option<Json>> {
+            let dump_queue = |queue: &VecDeque<Arc<Key>>| {
+                queue.iter().map(|key| {
+                    let entry = &self.cache[key];
+                    let mut dumped = Map::new();
+                    dumped.insert(String::from("key"), serde_json::to_value(&**key)?);
+                    dumped.insert(String::from("heat"), json!(entry.heat()));
+                    dumped.insert(String::from("hot"), json!(entry.hot));
+                    dumped.insert(String::from("test"), json!(entry.test));
+                    dumped.insert(String::from("expired"), json!(entry.expired()));
+                    if let Some(val) = val(&entry.val)? {
+                        dumped.insert(String::from("val"), val);
+                    }
+                    Ok(Json::Object(dumped))
+                }).collect::<serde_json::Result<Vec<_>>>()
+            };
+            let buckets = self.buckets.iter().map(&dump_queue)
+                .collect::<serde_json::Result<Vec<_>>>()?;
+            let tagged: Map<String, Json> = self.tagged.iter()
+                .map(|(tag, free)| (tag.clone(), json!(free.len())))
+                .collect();
+            let dumped = json!({
+                "policy": format!("{:?}", self.policy),
+                "max_heat": self.max_heat,
+                "entries": self.cache.len(),
+                "clock": dump_queue(&self.clock)?,
+                "hot": dump_queue(&self.hot)?,
+                "buckets": buckets,
+                "freelist": self.freelist.len(),
+                "tagged": tagged,
+            });
+            serde_json::to_string_pretty(&dumped)
+        }
+}
@@ -0,0 +1,137 @@
+//! A thread-safe PoolCache with a lock-free read path.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc,Mutex,Weak};
+use std::sync::atomic::{AtomicU64,Ordering};
+use std::time::Instant;
+
+use crossbeam_epoch::{self as epoch,Atomic,Owned};
+
+use super::SharedPoolCache;
+
+// A reader's view of one keyed entry.
+struct Slot<Value> {
+    val: Weak<Value>,
+    // uses recorded by readers, not yet applied to the cache.
+    hits: AtomicU64,
+    // when the entry expires, after which readers don't see it.
+    expires: Option<Instant>,
+}
+
+type View<Key, Value> = BTreeMap<Arc<Key>, Slot<Value>>;
+
+/// A PoolCache that can be shared between threads, where `get` and `peek`
+/// never take a lock.
+///
+/// Writers (`insert`, `put`, `take`) serialize on a mutex, and each write
+/// publishes a fresh read-only view of the live keyed entries, which readers
+/// load through epoch-based reclamation. Uses observed by readers are
+/// applied to the cache by the next writer (a use that races with a write
+/// may be lost). Publishing a view costs time linear in the number of
+/// keyed entries, so this suits caches where reads vastly outnumber
+/// writes.
+///
+/// Values are handed out as `Arc`s, so readers can keep them beyond
+/// eviction; `take` only reuses values that no reader still holds.
+pub struct EpochPoolCache<Key, Value> {
+    cache: Mutex<SharedPoolCache<Key, Value>>,
+    view: Atomic<View<Key, Value>>,
+}
+
+impl<Key, Value> EpochPoolCache<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Wrap `cache` for sharing between threads.
+        pub fn new(cache: SharedPoolCache<Key, Value>) -> EpochPoolCache<Key, Value> {
+            let shared = EpochPoolCache{cache: Mutex::new(cache), view: Atomic::null()};
+            shared.write(|_| ());
+            shared
+        }
+
+        /// Returns `true` if the given key is present in the cache.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.read(key, |slot| slot.val.strong_count() > 0).unwrap_or(false)
+        }
+
+        /// Returns a shared handle to the value associated with `key`, or
+        /// `None` if the key is not present in the cache.
+        pub fn get(&self, key: &Key) -> Option<Arc<Value>> {
+            self.read(key, |slot| {
+                slot.hits.fetch_add(1, Ordering::Relaxed);
+                slot.val.upgrade()
+            }).and_then(|val| val)
+        }
+
+        /// Like `get`, but doesn't count as a use of the entry.
+        pub fn peek(&self, key: &Key) -> Option<Arc<Value>> {
+            self.read(key, |slot| slot.val.upgrade()).and_then(|val| val)
+        }
+
+        /// Add a new object to the pool, not associated with any key.
+        pub fn put(&self, val: Value) {
+            self.write(|cache| cache.put(Arc::new(val)))
+        }
+
+        /// Insert `val` into the cache associated with `key`, replacing any
+        /// previous entry.
+        pub fn insert(&self, key: Key, val: Value) {
+            self.write(|cache| cache.insert(key, Arc::new(val)))
+        }
+
+        /// Returns an object from the pool, evicting cached keys if
+        /// necessary. Values still held by readers can't be reused, and are
+        /// skipped. Returns `None` once no uniquely-owned items remain.
+        pub fn take(&self) -> Option<Value> {
+            self.write(|cache| cache.take_unique())
+        }
+
+        fn read<F, R>(&self, key: &Key, f: F) -> Option<R>
+            where F: FnOnce(&Slot<Value>) -> R {
+            let guard = epoch::pin();
+            let view = self.view.load(Ordering::Acquire, &guard);
+            // views are only reclaimed once no pinned reader can see them.
+            let slot = unsafe { view.as_ref() }.and_then(|view| view.get(key));
+            slot.filter(|slot| slot.expires.is_none_or(|expires| Instant::now() < expires)).map(f)
+        }
+
+        fn write<F, R>(&self, f: F) -> R
+            where F: FnOnce(&mut SharedPoolCache<Key, Value>) -> R {
+            let mut cache = self.cache.lock().unwrap();
+            let guard = epoch::pin();
+            // apply the uses recorded against the current view.
+            if let Some(view) = unsafe { self.view.load(Ordering::Acquire, &guard).as_ref() } {
+                for (key, slot) in view {
+                    let hits = slot.hits.swap(0, Ordering::Relaxed);
+                    if let Some((_, entry)) = cache.live(key) {
+                        cache.hit_n(entry, hits);
+                    }
+                }
+            }
+            let result = f(&mut cache);
+            let live = cache.cache.iter().filter(|(key, entry)| !cache.stale(key, entry));
+            let view: View<Key, Value> = live.map(|(key, entry)| {
+                let slot = Slot{
+                    val: Arc::downgrade(&entry.val),
+                    hits: AtomicU64::new(0),
+                    expires: entry.expires};
+                (key.clone(), slot)
+            }).collect();
+            let old = self.view.swap(Owned::new(view), Ordering::AcqRel, &guard);
+            if !old.is_null() {
+                unsafe { guard.defer_destroy(old) };
+            }
+            result
+        }
+}
+
+impl<Key, Value> Drop for EpochPoolCache<Key, Value> {
+    fn drop(&mut self) {
+        // no readers can remain once we have `&mut self`.
+        unsafe {
+            let view = self.view.load(Ordering::Relaxed, epoch::unprotected());
+            if !view.is_null() {
+                drop(view.into_owned());
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Notifications about entries leaving the cache.
+
+use std::collections::VecDeque;
+
+use super::PoolCache;
+
+/// Why a keyed entry left the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RemovalCause {
+    /// The entry was evicted so that its value could be reused by `take`.
+    Evicted,
+    /// A new value was inserted under the same key.
+    Replaced,
+    /// The entry was dropped to release memory.
+    Shed,
+    /// The entry was removed on request, e.g. with `clear_namespace`.
+    Invalidated,
+    /// The entry had expired, and was removed by `drain_expired`.
+    Expired,
+    /// The value of a `WeakPoolCache` entry was no longer referenced
+    /// outside of the cache.
+    Released,
+}
+
+/// A recent eviction, as kept by `with_eviction_log`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eviction<Key> {
+    /// The key of the evicted entry.
+    pub key: Key,
+    /// The heat of the entry when it was evicted.
+    pub heat: u64,
+    /// How many entries the policy looked at before choosing this one,
+    /// including it.
+    pub scanned: u64,
+    /// `Evicted` if the entry's value was reused by `take`, or `Shed` if it
+    /// was dropped to release memory.
+    pub cause: RemovalCause,
+}
+
+// The state of `with_eviction_log`.
+pub struct EvictionLog<Key> {
+    evictions: VecDeque<Eviction<Key>>,
+    capacity: usize,
+    copy: fn(&Key) -> Key,
+}
+
+// Called with each keyed entry as it leaves the cache.
+pub type Listener<Key, Value> = dyn Fn(&Key, &Value, RemovalCause) + Send + Sync;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Set a function to be called with each keyed entry as it leaves
+        /// the cache, before its value is reused or dropped.
+        pub fn with_listener<F>(mut self, listener: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&Key, &Value, RemovalCause) + Send + Sync + 'static {
+            self.listener = Some(Box::new(listener));
+            self
+        }
+
+        pub(crate) fn notify(&self, key: &Key, val: &Value, cause: RemovalCause) {
+            self.counters.removed(cause);
+            self.shadow_remove(key, cause);
+            if let Some(ref listener) = self.listener {
+                listener(key, val, cause);
+            }
+        }
+
+        /// Keep a record of the last `capacity` evictions (see
+        /// `recent_evictions`), to explain why an entry left the cache.
+        pub fn with_eviction_log(mut self, capacity: usize) -> PoolCache<Key, Value, Meta>
+            where Key: Clone {
+            self.eviction_log = Some(EvictionLog{
+                evictions: VecDeque::with_capacity(capacity),
+                capacity,
+                copy: Key::clone});
+            self
+        }
+
+        /// Returns the evictions recorded since `with_eviction_log`, oldest
+        /// first, up to the capacity it was given. Entries removed for other
+        /// reasons, such as expiry or replacement, aren't recorded.
+        pub fn recent_evictions(&self) -> impl Iterator<Item = &Eviction<Key>> {
+            self.eviction_log.iter().flat_map(|log| log.evictions.iter())
+        }
+
+        // Record an eviction, if keeping a log.
+        pub(crate) fn log_eviction(&mut self, key: &Key, heat: u64, scanned: u64,
+                                   cause: RemovalCause) {
+            if let Some(ref mut log) = self.eviction_log {
+                if log.capacity == 0 {
+                    return;
+                }
+                if log.evictions.len() == log.capacity {
+                    log.evictions.pop_front();
+                }
+                log.evictions.push_back(Eviction{key: (log.copy)(key), heat, scanned, cause});
+            }
+        }
+}
@@ -0,0 +1,99 @@
+//! Expiring keyed entries after a time to live.
+//!
+//! An expired entry is treated as absent by lookups, and is cooled when a
+//! lookup finds it, so that `take` reclaims its value soon. Expired entries
+//! can also be removed all at once with `drain_expired`.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::{Duration,Instant};
+
+use super::{CacheEntry,PoolCache,RemovalCause};
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Expire entries `ttl` after they are inserted, unless they are
+        /// inserted with an expiry of their own.
+        pub fn with_ttl(mut self, ttl: Duration) -> PoolCache<Key, Value, Meta> {
+            self.ttl = Some(ttl);
+            self
+        }
+
+        /// Like `insert`, but the new entry expires `ttl` from now, instead
+        /// of after the cache's default time to live.
+        pub fn insert_with_ttl(&mut self, key: Key, val: Value, ttl: Duration) {
+            let mut entry = CacheEntry::new(val);
+            entry.expires = Some(Instant::now() + ttl);
+            entry.ttl = Some(ttl);
+            self.insert_entry(key, entry)
+        }
+
+        /// Like `insert`, but the new entry expires at `deadline`, e.g. one
+        /// handed down by the source of the value.
+        pub fn insert_expiring_at(&mut self, key: Key, val: Value, deadline: Instant) {
+            let mut entry = CacheEntry::new(val);
+            entry.expires = Some(deadline);
+            self.insert_entry(key, entry)
+        }
+
+        /// Push the expiry of the entry for `key` back by `extra`, without
+        /// reinserting it. Entries that don't expire are left alone.
+        /// Returns `false` if the key isn't present or has already expired.
+        /// This doesn't count as a use of the entry.
+        pub fn extend_ttl(&mut self, key: &Key, extra: Duration) -> bool {
+            match self.live_mut(key) {
+                Some(entry) => {
+                    entry.expires = entry.expires.map(|expires| expires + extra);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Restart the time to live of the entry for `key` from now, without
+        /// reinserting it: it next expires after the TTL it was inserted
+        /// with, or the cache's default if it wasn't given one. Returns
+        /// `false` if the key isn't present or has already expired. This
+        /// doesn't count as a use of the entry.
+        pub fn refresh_expiry(&mut self, key: &Key) -> bool {
+            let default = self.ttl;
+            match self.live_mut(key) {
+                Some(entry) => {
+                    if let Some(ttl) = entry.ttl.or(default) {
+                        entry.expires = Some(Instant::now() + ttl);
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Remove every expired entry, returning an iterator over their keys
+        /// and values, so that the values can be handed back to the pool
+        /// with `put` or persisted rather than dropped. The entries are
+        /// removed even if the iterator isn't consumed. Takes time linear
+        /// in the number of keyed entries.
+        pub fn drain_expired(&mut self) -> impl Iterator<Item = (Key, Value)>
+            where Key: Clone {
+            let expired: BTreeSet<Arc<Key>> = self.cache.iter()
+                .filter(|(_, entry)| entry.expired())
+                .map(|(key, _)| key.clone())
+                .collect();
+            let removed = self.remove_entries(expired, RemovalCause::Expired);
+            #[cfg(feature = "log")]
+            log::debug!("poolcache: drained {} expired entries", removed.len());
+            removed.into_iter().map(|(key, val)| {
+                // the key is still shared if a `Frozen` snapshot holds it.
+                let key = Arc::try_unwrap(key).unwrap_or_else(|key| (*key).clone());
+                (key, val)
+            })
+        }
+
+        fn live_mut(&mut self, key: &Key) -> Option<&mut CacheEntry<Value, Meta>> {
+            if self.stale(key, self.cache.get(key)?) {
+                return None;
+            }
+            self.cache.get_mut(key)
+        }
+}
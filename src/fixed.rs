@@ -0,0 +1,155 @@
+//! A PoolCache of fixed capacity, held inline in an array, that never
+//! allocates.
+
+use std::array;
+use std::cmp;
+use std::mem;
+use std::sync::atomic::{AtomicU8,Ordering};
+
+/// A Clock-LFU cache and object pool holding up to `N` objects, keyed or
+/// not, in an array, for targets without a heap.
+///
+/// It behaves like a `PoolCache` with `Policy::Clock`, but never allocates:
+/// every object lives in one of `N` slots, which `insert` and `put` fail to
+/// add to once all are in use, handing the object back. Lookups scan the
+/// slots, so it is meant for small `N`. Heat is held in a byte, so
+/// `max_heat` is at most 255.
+pub struct StaticPoolCache<Key, Value, const N: usize> {
+    slots: [Slot<Key, Value>; N],
+    // the next slot the clock hand looks at.
+    hand: usize,
+    max_heat: u8,
+}
+
+enum Slot<Key, Value> {
+    Empty,
+    Free(Value),
+    Keyed{key: Key, val: Value, heat: AtomicU8},
+}
+
+impl<Key, Value, const N: usize> StaticPoolCache<Key, Value, N>
+    where Key: Eq {
+
+        /// Create an empty StaticPoolCache where the maximum heat of a value
+        /// is limited to `max_heat`.
+        pub fn new(max_heat: u8) -> StaticPoolCache<Key, Value, N> {
+            StaticPoolCache{slots: array::from_fn(|_| Slot::Empty), hand: 0, max_heat}
+        }
+
+        /// Returns `true` if the given key is present in the cache.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.find(key).is_some()
+        }
+
+        /// Returns a reference to the value associated with `key`, counting
+        /// a use of the entry, or `None` if the key is not present.
+        pub fn get(&self, key: &Key) -> Option<&Value> {
+            match self.slots[self.find(key)?] {
+                Slot::Keyed{ref val, ref heat, ..} => {
+                    let hotter = heat.load(Ordering::Relaxed).saturating_add(1);
+                    heat.store(cmp::min(hotter, self.max_heat), Ordering::Relaxed);
+                    Some(val)
+                }
+                _ => None,
+            }
+        }
+
+        /// Insert `val` under `key`. If the key was already cached, its
+        /// value is replaced and returned, for the caller to `put` back or
+        /// drop. If every slot is in use, the key and value are handed back
+        /// instead.
+        pub fn insert(&mut self, key: Key, val: Value) -> Result<Option<Value>, (Key, Value)> {
+            if let Some(idx) = self.find(&key) {
+                if let Slot::Keyed{val: ref mut old, ..} = self.slots[idx] {
+                    return Ok(Some(mem::replace(old, val)));
+                }
+            }
+            match self.empty() {
+                Some(idx) => {
+                    self.slots[idx] = Slot::Keyed{key, val, heat: AtomicU8::new(1)};
+                    Ok(None)
+                }
+                None => Err((key, val)),
+            }
+        }
+
+        /// Add an object to the pool, not associated with any key, or hand
+        /// it back if every slot is in use.
+        pub fn put(&mut self, val: Value) -> Result<(), Value> {
+            match self.empty() {
+                Some(idx) => {
+                    self.slots[idx] = Slot::Free(val);
+                    Ok(())
+                }
+                None => Err(val),
+            }
+        }
+
+        /// Returns an object from the pool, evicting the least-used cached
+        /// key if there are no unkeyed objects, and freeing its slot.
+        /// Returns `None` only if the cache holds no objects.
+        pub fn take(&mut self) -> Option<Value> {
+            if let Some(idx) = self.slots.iter().position(|slot| matches!(slot, Slot::Free(_))) {
+                return self.free(idx);
+            }
+            let idx = self.sweep()?;
+            self.free(idx)
+        }
+
+        /// Returns the number of keyed entries.
+        pub fn len(&self) -> usize {
+            self.slots.iter().filter(|slot| matches!(slot, Slot::Keyed{..})).count()
+        }
+
+        /// Returns `true` if no keys are cached.
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Returns the number of unkeyed objects that `take` can hand out.
+        pub fn pool_len(&self) -> usize {
+            self.slots.iter().filter(|slot| matches!(slot, Slot::Free(_))).count()
+        }
+
+        /// Returns the number of objects the cache can hold, `N`.
+        pub fn capacity(&self) -> usize {
+            N
+        }
+
+        fn find(&self, key: &Key) -> Option<usize> {
+            self.slots.iter().position(|slot| match slot {
+                Slot::Keyed{key: ref stored, ..} => stored == key,
+                _ => false,
+            })
+        }
+
+        fn empty(&self) -> Option<usize> {
+            self.slots.iter().position(|slot| matches!(slot, Slot::Empty))
+        }
+
+        fn free(&mut self, idx: usize) -> Option<Value> {
+            match mem::replace(&mut self.slots[idx], Slot::Empty) {
+                Slot::Free(val) | Slot::Keyed{val, ..} => Some(val),
+                Slot::Empty => None,
+            }
+        }
+
+        // Move the clock hand over the keyed slots, decrementing heat until
+        // one goes cold, as `Policy::Clock` does.
+        fn sweep(&mut self) -> Option<usize> {
+            if self.is_empty() {
+                return None;
+            }
+            loop {
+                let idx = self.hand;
+                self.hand = (self.hand + 1) % N;
+                if let Slot::Keyed{ref heat, ..} = self.slots[idx] {
+                    let cooler = heat.load(Ordering::Relaxed).saturating_sub(1);
+                    heat.store(cooler, Ordering::Relaxed);
+                    if cooler == 0 {
+                        return Some(idx);
+                    }
+                }
+            }
+        }
+}
@@ -0,0 +1,165 @@
+//! Trying out changes to a PoolCache before committing or discarding them.
+
+use std::collections::{BTreeMap,BTreeSet};
+use std::mem;
+
+use super::PoolCache;
+
+/// A speculative view of a PoolCache, as returned by `fork`. Rather than
+/// copying the cache, a fork records only the entries inserted and removed
+/// through it and the objects put into it, and reads everything else from
+/// the cache underneath. `commit` applies the inserts and removals to the
+/// cache; `discard`, or dropping the fork, leaves the cache's entries as
+/// they were, and returns the values inserted through the fork to the pool
+/// as unkeyed objects instead, so they can be reused.
+///
+/// `take` hands out objects put into the fork, or replaced or removed
+/// within it, and then copies of the cache's unkeyed objects, whose
+/// originals the fork holds until `commit` drops them or `discard` returns
+/// them to the pool. A fork never evicts the cache's keyed entries, as that
+/// couldn't be undone. Reads through a fork don't count as uses of the
+/// cache's entries.
+pub struct Fork<'a, Key, Value, Meta = ()>
+    where Key: PartialOrd + Ord {
+    base: &'a mut PoolCache<Key, Value, Meta>,
+    inserted: BTreeMap<Key, Value>,
+    // keys of the cache's entries removed within the fork.
+    removed: BTreeSet<Key>,
+    // objects put into the fork, and values replaced or removed within it,
+    // to be returned to the pool.
+    spare: Vec<Value>,
+    // the cache's unkeyed objects copied by `take`, in the order they were
+    // taken from the pool.
+    taken: Vec<Value>,
+}
+
+impl<'a, Key, Value, Meta> Fork<'a, Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a reference to the value associated with `key` in the
+        /// fork: the one inserted through the fork, if any, or else the
+        /// cache's, unless it was removed through the fork.
+        pub fn get(&self, key: &Key) -> Option<&Value> {
+            if self.removed.contains(key) {
+                return None;
+            }
+            self.inserted.get(key)
+                .or_else(|| self.base.live(key).map(|(_, entry)| &entry.val))
+        }
+
+        /// Returns `true` if the given key is present in the fork.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.get(key).is_some()
+        }
+
+        /// Insert `val` associated with `key` in the fork, leaving the
+        /// cache underneath unchanged until `commit`.
+        pub fn insert(&mut self, key: Key, val: Value) {
+            self.removed.remove(&key);
+            if let Some(old) = self.inserted.insert(key, val) {
+                self.spare.push(old);
+            }
+        }
+
+        /// Remove the entry for `key` from the fork, leaving the cache
+        /// underneath unchanged until `commit`, which removes it as
+        /// `invalidate_key` does. Returns `true` if the fork held an entry
+        /// for `key`.
+        pub fn remove(&mut self, key: &Key) -> bool
+            where Key: Clone {
+            let present = self.contains_key(key);
+            if let Some(val) = self.inserted.remove(key) {
+                self.spare.push(val);
+            }
+            if self.base.live(key).is_some() {
+                self.removed.insert(key.clone());
+            }
+            present
+        }
+
+        /// Add a new object to the fork's pool, not associated with any key.
+        /// It goes to the cache's pool when the fork is committed or
+        /// discarded.
+        pub fn put(&mut self, val: Value) {
+            self.spare.push(val);
+        }
+
+        /// Returns an unkeyed object: the one last put into the fork, or
+        /// replaced or removed within it, or else a copy of the cache's next
+        /// one, which the fork keeps so that `discard` can return it.
+        /// Returns `None` once there are none; keyed entries are never
+        /// evicted.
+        pub fn take(&mut self) -> Option<Value>
+            where Value: Clone {
+            if let Some(val) = self.spare.pop() {
+                return Some(val);
+            }
+            let val = self.base.freelist.pop_front()?;
+            let copy = val.clone();
+            self.taken.push(val);
+            Some(copy)
+        }
+
+        /// Returns the number of entries inserted through the fork.
+        pub fn len(&self) -> usize {
+            self.inserted.len()
+        }
+
+        /// Returns `true` if nothing has been inserted through the fork.
+        pub fn is_empty(&self) -> bool {
+            self.inserted.is_empty()
+        }
+
+        /// Apply the fork's removals and then its inserts to the cache, in
+        /// key order, and drop the originals of the objects it copied.
+        pub fn commit(mut self) {
+            for val in mem::take(&mut self.taken) {
+                self.base.discard(val);
+            }
+            for key in mem::take(&mut self.removed) {
+                self.base.invalidate_key(&key);
+            }
+            for (key, val) in mem::take(&mut self.inserted) {
+                self.base.insert(key, val);
+            }
+        }
+
+        /// Drop the fork's inserts and removals, returning the values
+        /// inserted through it to the pool, and the objects it copied to
+        /// where they were.
+        pub fn discard(self) {}
+}
+
+impl<'a, Key, Value, Meta> Drop for Fork<'a, Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        fn drop(&mut self) {
+            for val in self.taken.drain(..).rev() {
+                self.base.freelist.push_front(val);
+            }
+            let inserted = mem::take(&mut self.inserted);
+            for val in inserted.into_values().chain(self.spare.drain(..)) {
+                self.base.put(val);
+            }
+        }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a fork of the cache, for trying a batch of inserts and
+        /// removals and then committing or discarding them. Forking is
+        /// cheap: only the changes made through the fork are stored apart
+        /// from the cache. A fork is an exclusive overlay on the cache, not
+        /// a clone of it: it borrows the cache mutably, so there can be only
+        /// one fork at a time, and the cache can't be used until the fork
+        /// is committed or discarded.
+        pub fn fork(&mut self) -> Fork<'_, Key, Value, Meta> {
+            Fork{
+                base: self,
+                inserted: BTreeMap::new(),
+                removed: BTreeSet::new(),
+                spare: Vec::new(),
+                taken: Vec::new()}
+        }
+}
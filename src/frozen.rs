@@ -0,0 +1,70 @@
+//! Immutable snapshots of the keyed contents of a PoolCache, for readers on
+//! other threads.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::PoolCache;
+
+/// An immutable copy of the keyed contents of a PoolCache, as returned by
+/// `freeze`. Cloning one is cheap, and clones share the same contents, so
+/// they can be handed to other threads to read while the cache itself
+/// keeps changing.
+#[derive(Debug)]
+pub struct Frozen<Key, Value> {
+    entries: Arc<BTreeMap<Arc<Key>, Value>>,
+}
+
+impl<Key, Value> Clone for Frozen<Key, Value> {
+    fn clone(&self) -> Frozen<Key, Value> {
+        Frozen{entries: self.entries.clone()}
+    }
+}
+
+impl<Key, Value> Frozen<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a reference to the value associated with `key` when the
+        /// snapshot was taken, or `None` if there wasn't one.
+        pub fn get(&self, key: &Key) -> Option<&Value> {
+            self.entries.get(key)
+        }
+
+        /// Returns `true` if `key` was present when the snapshot was taken.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.entries.contains_key(key)
+        }
+
+        /// Returns an iterator over the keys and values in the snapshot, in
+        /// key order.
+        pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+            self.entries.iter().map(|(key, val)| (&**key, val))
+        }
+
+        /// Returns the number of entries in the snapshot.
+        pub fn len(&self) -> usize {
+            self.entries.len()
+        }
+
+        /// Returns `true` if the snapshot holds no entries.
+        pub fn is_empty(&self) -> bool {
+            self.entries.is_empty()
+        }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns an immutable snapshot of the unexpired keyed entries,
+        /// which can be shared between threads. Keys are shared with the
+        /// cache rather than copied, but values are cloned, so caches of
+        /// large values should hold them in an `Arc` to make this cheap.
+        /// This doesn't count as a use of any entry.
+        pub fn freeze(&self) -> Frozen<Key, Value>
+            where Value: Clone {
+            let entries = self.cache.iter().filter(|(key, entry)| !self.stale(key, entry))
+                .map(|(key, entry)| (key.clone(), entry.val.clone()))
+                .collect();
+            Frozen{entries: Arc::new(entries)}
+        }
+}
@@ -0,0 +1,71 @@
+//! A driver for fuzzing a PoolCache with arbitrary traces of operations.
+
+use std::collections::BTreeMap;
+
+use super::{Op,PoolCache,Trace};
+
+/// Apply the operations in `trace` to `cache`, checking after each one that
+/// no object has been lost or duplicated and that every keyed entry is on
+/// the eviction clock exactly once. Panics if a check fails.
+///
+/// Objects are numbered as they are created. The objects returned by
+/// `take` and `swap` are held by the driver, and handed back by later
+/// `insert`s and `put`s before any new ones are created. `cache` should be
+/// empty, and not shed entries under memory pressure.
+pub fn check<Key>(mut cache: PoolCache<Key, u64>, trace: &Trace<Key>)
+    where Key: PartialOrd + Ord + Clone {
+    let mut created = 0;
+    let mut held = Vec::new();
+    let mut object = |held: &mut Vec<u64>| held.pop().unwrap_or_else(|| {
+        created += 1;
+        created - 1
+    });
+    for op in trace.ops() {
+        match *op {
+            Op::Get(ref key) => { cache.get(key); },
+            Op::Insert(ref key) => {
+                let val = object(&mut held);
+                cache.insert(key.clone(), val);
+            },
+            Op::Swap(ref key) => {
+                let val = object(&mut held);
+                held.extend(cache.swap(key, val));
+            },
+            Op::Put => {
+                let val = object(&mut held);
+                cache.put(val);
+            },
+            Op::Take => held.extend(cache.take()),
+        }
+        cache.check(&held);
+    }
+}
+
+impl<Key> PoolCache<Key, u64>
+    where Key: PartialOrd + Ord {
+
+        // Panic unless the cache and `held` together own each object
+        // numbered below the highest exactly once, and each keyed entry
+        // is on the clock exactly once.
+        fn check(&self, held: &[u64]) {
+            let mut objects: Vec<u64> = self.cache.values().map(|entry| entry.val)
+                .chain(self.freelist.iter().cloned())
+                .chain(held.iter().cloned())
+                .collect();
+            objects.sort_unstable();
+            for (idx, &val) in objects.iter().enumerate() {
+                assert_eq!(idx as u64, val, "object lost or duplicated");
+            }
+
+            let mut clocked = BTreeMap::new();
+            let queues = self.clock.iter().chain(self.hot.iter()).chain(self.buckets.iter().flatten());
+            for key in queues {
+                *clocked.entry(key).or_insert(0) += 1;
+            }
+            for (key, &count) in &clocked {
+                assert!(self.cache.contains_key(*key), "clock holds a key not in the cache");
+                assert_eq!(1, count, "key is on the clock more than once");
+            }
+            assert_eq!(self.cache.len(), clocked.len(), "keyed entry missing from the clock");
+        }
+}
@@ -0,0 +1,51 @@
+//! Handles to keyed entries that notice when the entry they refer to has
+//! been replaced, so that long-lived references to the cache stay safe.
+
+use super::{Op,PoolCache};
+
+/// Refers to the value a keyed entry held when the handle was made. See
+/// `PoolCache::get_handle`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Handle<Key> {
+    key: Key,
+    generation: u64,
+}
+
+impl<Key> Handle<Key> {
+    /// Returns the key the handle refers to.
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a handle to the entry for `key`, or `None` if the key is
+        /// not present in the cache. The handle resolves to the entry's
+        /// value until the value is replaced by `insert` or `swap`, or the
+        /// entry is removed, even if the key is later inserted again.
+        pub fn get_handle(&self, key: &Key) -> Option<Handle<Key>>
+            where Key: Clone {
+            self.live(key).map(|(_, entry)| Handle{key: key.clone(), generation: entry.generation})
+        }
+
+        /// Returns a reference to the value `handle` refers to, or `None` if
+        /// it has since been replaced or removed. Like `get`, this counts as
+        /// a use of the entry.
+        pub fn resolve(&self, handle: &Handle<Key>) -> Option<&Value> {
+            self.record(|copy| Op::Get(copy(&handle.key)));
+            let entry = self.live(&handle.key).map(|(_, entry)| entry)
+                .filter(|entry| entry.generation == handle.generation);
+            self.looked_up(&handle.key, entry.is_some());
+            let entry = entry?;
+            self.hit(entry);
+            Some(&entry.val)
+        }
+
+        // Returns a generation that no entry has had yet.
+        pub(crate) fn next_generation(&mut self) -> u64 {
+            self.generation += 1;
+            self.generation
+        }
+}
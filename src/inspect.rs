@@ -0,0 +1,61 @@
+//! Read-only views of the keyed entries and their heat, and of the unkeyed
+//! objects in the pool, for monitoring and for layers built on top of the
+//! cache. None of these count as a use of any entry.
+
+use std::cmp::Reverse;
+
+use super::PoolCache;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns the key, heat and value of the entry with the highest
+        /// heat, or `None` if no keys are cached. Ties go to the smallest
+        /// key. Takes time linear in the number of keyed entries.
+        pub fn hottest(&self) -> Option<(&Key, u64, &Value)> {
+            // `min_by_key` picks the first of equal entries.
+            self.heats().min_by_key(|&(_, heat, _)| Reverse(heat))
+        }
+
+        /// Returns the key, heat and value of the entry with the lowest
+        /// heat, or `None` if no keys are cached. Ties go to the smallest
+        /// key. Takes time linear in the number of keyed entries.
+        pub fn coldest(&self) -> Option<(&Key, u64, &Value)> {
+            self.heats().min_by_key(|&(_, heat, _)| heat)
+        }
+
+        /// Returns an iterator over the key, heat and value of each keyed
+        /// entry, hottest first, and in key order among entries of equal
+        /// heat. Sorting takes time `O(n log n)` in the number of keyed
+        /// entries, before the first item is returned.
+        pub fn iter_by_heat(&self) -> impl Iterator<Item = (&Key, u64, &Value)> {
+            let mut entries: Vec<(&Key, u64, &Value)> = self.heats().collect();
+            entries.sort_by_key(|&(_, heat, _)| Reverse(heat));
+            entries.into_iter()
+        }
+
+        /// Returns the number of unkeyed objects that `take` can hand out,
+        /// not counting objects put under a tag.
+        pub fn pool_len(&self) -> usize {
+            self.freelist.len()
+        }
+
+        /// Returns an iterator over the unkeyed objects that `take` can hand
+        /// out, in the order it hands them out. Objects put under a tag
+        /// aren't included.
+        pub fn pool_iter(&self) -> impl Iterator<Item = &Value> {
+            self.freelist.iter()
+        }
+
+        /// Returns the unkeyed object the next `take` would hand out, if
+        /// any, without taking it.
+        pub fn peek_pool_front(&self) -> Option<&Value> {
+            self.freelist.front()
+        }
+
+        // The live keyed entries with their heat, in key order.
+        fn heats(&self) -> impl Iterator<Item = (&Key, u64, &Value)> {
+            self.cache.iter().filter(move |(key, entry)| !self.stale(key, entry))
+                .map(|(key, entry)| (&**key, entry.heat(), &entry.val))
+        }
+}
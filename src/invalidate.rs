@@ -0,0 +1,111 @@
+//! Invalidating many entries at once: all of them, those matching a
+//! predicate, or those given a tag.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::{CacheEntry,PoolCache};
+
+// Decides whether an entry is invalidated by `invalidate_entries_if`.
+pub type Predicate<Key, Value> = dyn Fn(&Key, &Value) -> bool + Send + Sync;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Invalidate every keyed entry in constant time, however many
+        /// there are, by starting a new epoch: entries inserted before it
+        /// are treated as expired from then on. Like expired entries, they
+        /// are cooled as they are looked up, and stay in the cache (and
+        /// count towards `weight`) until `take` evicts them or
+        /// `purge_invalidated` removes them, their values then being reused
+        /// like any others.
+        pub fn invalidate_all(&mut self) {
+            self.epoch += 1;
+            #[cfg(feature = "log")]
+            log::debug!("poolcache: invalidated every entry, epoch {}", self.epoch);
+        }
+
+        /// Invalidate every keyed entry for which `predicate` returns `true`,
+        /// without visiting them now. Entries inserted or given a new value
+        /// from then on aren't affected. Until `purge_invalidated` is
+        /// called, every lookup checks the entry it finds against each
+        /// pending predicate, and matching entries are treated as expired,
+        /// as with `invalidate_all`.
+        pub fn invalidate_entries_if<F>(&mut self, predicate: F)
+            where F: Fn(&Key, &Value) -> bool + Send + Sync + 'static {
+            self.predicates.push((self.generation, Box::new(predicate)));
+        }
+
+        /// Remove the entries invalidated by `invalidate_all` or
+        /// `invalidate_entries_if`, moving their values to the pool, and
+        /// drop the pending predicates. Returns the number of entries
+        /// removed. Takes time linear in the number of keyed entries.
+        pub fn purge_invalidated(&mut self) -> usize {
+            let keys: BTreeSet<Arc<Key>> = self.cache.iter()
+                .filter(|(key, entry)| self.invalidated(key, entry))
+                .map(|(key, _)| key.clone())
+                .collect();
+            let count = keys.len();
+            self.invalidate(keys);
+            self.predicates.clear();
+            count
+        }
+
+        /// Like `insert`, but tags the new entry with each of `tags`, so that
+        /// it can be invalidated together with the other entries under any
+        /// of them by `invalidate_tag`, e.g. along with the other values
+        /// derived from the same source. These tags are unrelated to those
+        /// of `put_tagged`.
+        pub fn insert_with_tags(&mut self, key: Key, val: Value, tags: &[&str]) {
+            let mut entry = CacheEntry::new(val);
+            entry.groups = tags.iter().map(|&tag| match self.groups.get_key_value(tag) {
+                // shared, so that entries don't each hold a copy.
+                Some((name, _)) => name.clone(),
+                None => Arc::from(tag),
+            }).collect();
+            self.insert_entry(key, entry)
+        }
+
+        /// Remove every entry tagged with `tag` by `insert_with_tags`,
+        /// moving their values to the pool, like `clear_namespace`. Returns
+        /// the number of entries removed. Takes time linear in the number
+        /// of keyed entries.
+        pub fn invalidate_tag(&mut self, tag: &str) -> usize {
+            match self.groups.remove(tag) {
+                Some(keys) => {
+                    let count = keys.len();
+                    self.invalidate(keys);
+                    count
+                }
+                None => 0,
+            }
+        }
+
+        pub(crate) fn join_groups(&mut self, key: &Arc<Key>, groups: Vec<Arc<str>>) {
+            for tag in groups {
+                self.groups.entry(tag).or_default().insert(key.clone());
+            }
+        }
+
+        pub(crate) fn leave_groups(&mut self, key: &Key, groups: &[Arc<str>]) {
+            for tag in groups {
+                if let Some(keys) = self.groups.get_mut(&**tag) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.groups.remove(&**tag);
+                    }
+                }
+            }
+        }
+
+        // Returns `true` if `entry` has expired, or was invalidated.
+        pub(crate) fn stale(&self, key: &Key, entry: &CacheEntry<Value, Meta>) -> bool {
+            entry.expired() || self.invalidated(key, entry)
+        }
+
+        pub(crate) fn invalidated(&self, key: &Key, entry: &CacheEntry<Value, Meta>) -> bool {
+            entry.epoch != self.epoch || self.predicates.iter().any(|(generation, predicate)| {
+                entry.generation <= *generation && predicate(key, &entry.val)
+            })
+        }
+}
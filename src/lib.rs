@@ -34,112 +34,907 @@
 //! ```
 //!
 
-use std::cell::Cell;
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "snapshot")]
+extern crate bincode;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "cached")]
+extern crate cached;
+#[cfg(feature = "snapshot")]
+extern crate crc32fast;
+#[cfg(feature = "crossbeam-epoch")]
+extern crate crossbeam_epoch;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+#[cfg(feature = "thread_local")]
+extern crate thread_local;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tower")]
+extern crate tower_layer;
+#[cfg(feature = "tower")]
+extern crate tower_service;
+#[cfg(feature = "zeroize")]
+extern crate zeroize;
+
 use std::cmp;
-use std::collections::{BTreeMap,VecDeque};
+use std::collections::{BTreeMap,BTreeSet,VecDeque};
+use std::iter;
+use std::mem;
+use std::ops::Index;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,AtomicU64,Ordering};
+use std::time::{Duration,Instant};
+
+#[cfg(feature = "tokio")]
+mod async_cache;
+#[cfg(feature = "bytes")]
+mod buffers;
+mod checkout;
+mod compact;
+mod compare;
+mod deps;
+mod dot;
+#[cfg(feature = "serde_json")]
+mod dump;
+#[cfg(feature = "crossbeam-epoch")]
+mod epoch;
+mod events;
+mod expiry;
+mod fixed;
+mod fork;
+mod frozen;
+mod handle;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+mod inspect;
+mod invalidate;
+mod load;
+mod lru;
+#[cfg(feature = "thread_local")]
+mod local;
+#[cfg(feature = "log")]
+mod logging;
+#[cfg(feature = "cached")]
+mod memo;
+mod meta;
+#[cfg(feature = "tower")]
+mod middleware;
+mod namespace;
+#[cfg(feature = "rayon")]
+mod par;
+mod policy;
+mod pooled;
+mod prefix;
+mod pressure;
+mod protect;
+mod scrub;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shadow;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+mod stacked;
+mod stats;
+mod storage;
+mod sync;
+mod tagged;
+#[cfg(feature = "timestamps")]
+mod timestamps;
+#[cfg(feature = "proptest")]
+pub mod testing;
+mod trace;
+mod tune;
+mod weak;
+#[cfg(feature = "tokio")]
+pub use async_cache::{AsyncPoolCache,Evictions};
+pub use compact::CompactPoolCache;
+#[cfg(feature = "crossbeam-epoch")]
+pub use epoch::EpochPoolCache;
+pub use events::{Eviction,RemovalCause};
+pub use fixed::StaticPoolCache;
+pub use fork::Fork;
+pub use frozen::Frozen;
+pub use handle::Handle;
+pub use load::Freshness;
+pub use lru::LruAdapter;
+#[cfg(feature = "cached")]
+pub use memo::MemoPoolCache;
+#[cfg(feature = "tower")]
+pub use middleware::{CacheLayer,CacheService};
+#[cfg(feature = "thread_local")]
+pub use local::LocalPoolCache;
+pub use namespace::Quota;
+pub use policy::Policy;
+pub use pooled::Pooled;
+#[cfg(feature = "debug-leaks")]
+pub use pooled::Leak;
+pub use pressure::{PressureHandle,Reclaim};
+#[cfg(feature = "serde")]
+pub use serialize::{Contents,State};
+#[cfg(feature = "snapshot")]
+pub use snapshot::{Compression,SnapshotError,SNAPSHOT_VERSION};
+pub use stats::Stats;
+pub use storage::{CacheStorage,DenseKey,DenseStorage};
+pub use sync::SyncPoolCache;
+#[cfg(feature = "timestamps")]
+pub use timestamps::{EntryInfo,SystemClock,TimeSource};
+pub use trace::{Op,Trace};
+use trace::Recorder;
+pub use weak::WeakPoolCache;
+use checkout::Checkout;
+use events::{EvictionLog,Listener};
+use invalidate::Predicate;
+use load::{BatchLoader,Loader};
+use policy::{Cost,Ghosts,Rng};
+use pooled::{Tracker,Warning};
+use pressure::{Adaptive,Pressure,Weigher};
+use shadow::Shadow;
+use stats::Counters;
+use storage::Storage;
+#[cfg(feature = "timestamps")]
+use timestamps::Stamps;
+use tune::Tuner;
+use protect::Filter;
 
-struct CacheEntry<Value> {
+/// A keyed entry of a PoolCache, as held by its `CacheStorage`. Its
+/// contents are private to the cache.
+pub struct CacheEntry<Value, Meta> {
     val: Value,
-    heat: Cell<u64>,
+    // further objects under the same key, for `take_for`.
+    spares: Vec<Value>,
+    meta: Option<Meta>,
+    ns: Option<Arc<str>>,
+    // the tags the entry can be invalidated by.
+    groups: Vec<Arc<str>>,
+    expires: Option<Instant>,
+    // the time to live the entry was inserted with, if its own.
+    ttl: Option<Duration>,
+    // changes whenever the value is replaced, to detect stale handles.
+    generation: u64,
+    // the cache's epoch when the entry was inserted.
+    epoch: u64,
+    // overrides the cache's `max_heat` for this entry.
+    max_heat: Option<u64>,
+    heat: AtomicU64,
+    // the cache's tick at the entry's last use, when tracking recency.
+    last_used: AtomicU64,
+    // the cache's age at the entry's last use, for `Policy::LazyClock` and
+    // `Policy::Gdsf`.
+    aged: AtomicU64,
+    #[cfg(feature = "timestamps")]
+    stamps: Stamps,
+    // set once the entry is queued for refresh-ahead.
+    refresh: AtomicBool,
+    // set for entries in the hot set of a CLOCK-Pro cache, or the main
+    // queue of a 2Q cache.
+    hot: bool,
+    // set for cold CLOCK-Pro entries that are in their test period.
+    test: bool,
 }
 
-impl<Value> CacheEntry<Value> {
-    fn new(val: Value) -> CacheEntry<Value> {
-        CacheEntry{val: val, heat: Cell::new(1)}
+impl<Value, Meta> CacheEntry<Value, Meta> {
+    fn new(val: Value) -> CacheEntry<Value, Meta> {
+        CacheEntry{
+            val,
+            spares: Vec::new(),
+            meta: None,
+            ns: None,
+            groups: Vec::new(),
+            expires: None,
+            ttl: None,
+            generation: 0,
+            epoch: 0,
+            max_heat: None,
+            heat: AtomicU64::new(1),
+            last_used: AtomicU64::new(0),
+            aged: AtomicU64::new(0),
+            #[cfg(feature = "timestamps")]
+            stamps: Stamps::new(Instant::now()),
+            refresh: AtomicBool::new(false),
+            hot: false,
+            test: false}
+    }
+
+    // Heat is atomic so that uses can be counted from several threads at
+    // once, but updates aren't read-modify-write: racing uses may be
+    // counted as one.
+    fn heat(&self) -> u64 {
+        self.heat.load(Ordering::Relaxed)
+    }
+
+    fn set_heat(&self, heat: u64) {
+        self.heat.store(heat, Ordering::Relaxed)
     }
 
     fn inc(&self, max_heat: u64) -> u64 {
-        self.heat.set(cmp::min(self.heat.get() + 1, max_heat));
-        self.heat.get()
+        self.set_heat(cmp::min(self.heat() + 1, max_heat));
+        self.heat()
+    }
+
+    // Raise the heat by `heat`, up to `max_heat`.
+    fn warm(&self, heat: u64, max_heat: u64) {
+        self.set_heat(cmp::min(self.heat().saturating_add(heat), max_heat));
     }
 
-    fn dec(&self) -> u64 {
-        self.heat.set(cmp::max(self.heat.get() - 1, 0));
-        self.heat.get()
+    // Lower the heat by one, but not below `floor`, and never raise it.
+    fn dec(&self, floor: u64) -> u64 {
+        let heat = self.heat();
+        if heat > floor {
+            self.set_heat(heat - 1);
+        }
+        self.heat()
+    }
+
+    fn last_used(&self) -> u64 {
+        self.last_used.load(Ordering::Relaxed)
+    }
+
+    fn aged(&self) -> u64 {
+        self.aged.load(Ordering::Relaxed)
+    }
+
+    fn expired(&self) -> bool {
+        self.expires.is_some_and(|expires| Instant::now() >= expires)
     }
 }
 
-pub struct PoolCache<Key, Value> {
-    cache: BTreeMap<Key, CacheEntry<Value>>,
+/// Where `take` gets objects from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TakeOrder {
+    /// Hand out unkeyed objects first, and evict keyed entries only once
+    /// there are none.
+    #[default]
+    FreelistFirst,
+    /// Evict keyed entries first, and hand out unkeyed objects only once
+    /// there are none to evict, keeping the freelist as a reserve.
+    EvictFirst,
+    /// Only hand out unkeyed objects, never evicting keyed entries.
+    FreelistOnly,
+}
+
+// The outcome of a search for an object to hand out, bounded by a budget.
+pub(crate) enum Take<Value> {
+    Taken(Value),
+    // no object can be handed out, however long the search.
+    Nothing,
+    // the search gave up after inspecting its budget of entries.
+    GaveUp,
+}
+
+pub struct PoolCache<Key, Value, Meta = ()> {
+    // keys are shared with the clock, so they are stored only once.
+    cache: Storage<Key, CacheEntry<Value, Meta>>,
     freelist: VecDeque<Value>,
-    clock: VecDeque<Key>,
+    // free objects reserved for `take_tagged`, by tag.
+    tagged: BTreeMap<String, VecDeque<Value>>,
+    clock: VecDeque<Arc<Key>>,
+    hot: VecDeque<Arc<Key>>,
+    buckets: Vec<VecDeque<Arc<Key>>>,
+    // the score of `buckets[0]`, for `Policy::LazyClock`.
+    bucket_base: u64,
+    // how many times entries have cooled, for `Policy::LazyClock`, or the
+    // inflation of `Policy::Gdsf`.
+    age: u64,
+    ghosts: Ghosts<Key>,
+    cold_target: usize,
     max_heat: u64,
+    // the lowest heat that entries decay to.
+    heat_floor: u64,
+    policy: Policy,
+    take_order: TakeOrder,
+    uses: AtomicU64,
+    // set to prefer the least recently used among equally cold entries.
+    recency: bool,
+    // counts uses and inserts, when tracking recency.
+    ticks: AtomicU64,
+    rng: Rng,
+    weigher: Option<Box<Weigher<Value>>>,
+    pressure: Pressure,
+    listener: Option<Box<Listener<Key, Value>>>,
+    tracker: Arc<Tracker>,
+    outstanding_warning: Option<(usize, Box<Warning>)>,
+    max_objects: usize,
+    recorder: Option<Recorder<Key>>,
+    // keys in each namespace, for clearing them together.
+    namespaces: BTreeMap<Arc<str>, BTreeSet<Arc<Key>>>,
+    // keys under each tag given to `insert_with_tags`.
+    groups: BTreeMap<Arc<str>, BTreeSet<Arc<Key>>>,
+    // the keys each entry depends on, and the reverse.
+    dependencies: BTreeMap<Arc<Key>, Vec<Arc<Key>>>,
+    dependents: BTreeMap<Arc<Key>, BTreeSet<Arc<Key>>>,
+    quotas: BTreeMap<String, Quota>,
+    // keys that are never evicted.
+    protected: BTreeSet<Key>,
+    // what is kept of checked out entries, until they are checked in.
+    checked_out: BTreeMap<Arc<Key>, Checkout<Key, Meta>>,
+    eviction_filter: Option<Box<Filter<Key, Value>>>,
+    cost: Option<Box<Cost<Key, Value>>>,
+    // how long entries live by default.
+    ttl: Option<Duration>,
+    // the generation last given to an entry.
+    generation: u64,
+    // bumped by `invalidate_all`, invalidating the entries of earlier ones.
+    epoch: u64,
+    // predicates given to `invalidate_entries_if`, with the generation
+    // they were given at: later entries are unaffected.
+    predicates: Vec<(u64, Box<Predicate<Key, Value>>)>,
+    counters: Counters,
+    // entries looked at while choosing victims.
+    swept: u64,
+    // set when the last search for an entry to evict gave up because no
+    // entry could be evicted, rather than for lack of budget.
+    stuck: bool,
+    // entries swept in a row without their heat dropping, and the length
+    // of the queue they were swept from.
+    unchanged: (usize, usize),
+    tuner: Option<Tuner>,
+    adaptive: Option<Adaptive<Key>>,
+    shadow: Option<Box<Shadow<Key>>>,
+    eviction_log: Option<EvictionLog<Key>>,
+    #[cfg(feature = "timestamps")]
+    time: Box<dyn TimeSource>,
+    // applied to values before they are dropped.
+    scrub: Option<fn(&mut Value)>,
+    loader: Option<Box<Loader<Key, Value>>>,
+    batch_loader: Option<Box<BatchLoader<Key, Value>>>,
+    // the share of its time to live after which a used entry is refreshed.
+    refresh_ahead: Option<f64>,
+    // how long after expiring entries may still be served as stale.
+    stale_grace: Duration,
 }
 
 impl<Key, Value> PoolCache<Key, Value>
-    where Key: PartialOrd + Ord + Clone {
+    where Key: PartialOrd + Ord {
 
         /// Create a new PoolCache where the maximum heat of a value
         /// is limited to `max_heat`.
         pub fn new(max_heat: u64) -> PoolCache<Key, Value> {
+            PoolCache::with_policy(max_heat, Policy::Clock)
+        }
+
+        /// Create a new PoolCache that evicts entries according to
+        /// `policy`.
+        pub fn with_policy(max_heat: u64, policy: Policy) -> PoolCache<Key, Value> {
+            PoolCache::with_metadata(max_heat, policy)
+        }
+
+        /// Create a new PoolCache like `new`, with `pool` as its initial
+        /// unkeyed objects, handed out by `take` in order.
+        pub fn from_pool<I>(max_heat: u64, pool: I) -> PoolCache<Key, Value>
+            where I: IntoIterator<Item = Value> {
+            let mut cache = PoolCache::new(max_heat);
+            cache.freelist.extend(pool);
+            cache
+        }
+}
+
+impl<Key, Value, Meta> Default for PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Create a PoolCache using `Policy::Clock`, with a maximum heat of
+        /// 4.
+        fn default() -> PoolCache<Key, Value, Meta> {
+            PoolCache::with_metadata(4, Policy::Clock)
+        }
+}
+
+impl<Key, Value, Meta> Index<&Key> for PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        type Output = Value;
+
+        /// Returns a reference to the value associated with `key`, like
+        /// `get`, counting as a use of the entry.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the key is not present in the cache.
+        fn index(&self, key: &Key) -> &Value {
+            self.get(key).expect("key not present in PoolCache")
+        }
+}
+
+impl<Key, Value> From<Vec<Value>> for PoolCache<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Create a `PoolCache::default()` with `pool` as its initial unkeyed
+        /// objects, like `from_pool`.
+        fn from(pool: Vec<Value>) -> PoolCache<Key, Value> {
+            PoolCache::from_pool(4, pool)
+        }
+}
+
+impl<Key, Value> From<BTreeMap<Key, Value>> for PoolCache<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Create a `PoolCache::default()` holding the entries of `map`, all
+        /// with the initial heat, and queued for eviction in key order. The
+        /// map is already sorted, so it is built in linear time.
+        fn from(map: BTreeMap<Key, Value>) -> PoolCache<Key, Value> {
+            let mut cache = PoolCache::default();
+            cache.cache = Storage::Ordered(map.into_iter()
+                .map(|(key, val)| (Arc::new(key), CacheEntry::new(val)))
+                .collect());
+            cache.clock = cache.cache.keys().cloned().collect();
+            cache
+        }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Create a new PoolCache like `with_policy`, where keyed entries
+        /// can also carry metadata of type `Meta`.
+        pub fn with_metadata(max_heat: u64, policy: Policy) -> PoolCache<Key, Value, Meta> {
             PoolCache{
-                cache: BTreeMap::new(),
+                cache: Storage::Ordered(BTreeMap::new()),
                 freelist: VecDeque::new(),
+                tagged: BTreeMap::new(),
                 clock: VecDeque::new(),
-                max_heat: max_heat}
+                hot: VecDeque::new(),
+                buckets: Vec::new(),
+                bucket_base: 0,
+                age: 0,
+                ghosts: Ghosts::new(),
+                cold_target: 1,
+                max_heat,
+                heat_floor: 0,
+                policy,
+                take_order: TakeOrder::FreelistFirst,
+                uses: AtomicU64::new(0),
+                recency: false,
+                ticks: AtomicU64::new(0),
+                rng: Rng::new(),
+                weigher: None,
+                pressure: Pressure::new(),
+                listener: None,
+                tracker: Arc::new(Tracker::new()),
+                outstanding_warning: None,
+                max_objects: usize::MAX,
+                recorder: None,
+                namespaces: BTreeMap::new(),
+                groups: BTreeMap::new(),
+                dependencies: BTreeMap::new(),
+                dependents: BTreeMap::new(),
+                quotas: BTreeMap::new(),
+                protected: BTreeSet::new(),
+                checked_out: BTreeMap::new(),
+                eviction_filter: None,
+                cost: None,
+                ttl: None,
+                generation: 0,
+                epoch: 0,
+                predicates: Vec::new(),
+                counters: Counters::new(),
+                swept: 0,
+                stuck: false,
+                unchanged: (0, 0),
+                tuner: None,
+                adaptive: None,
+                shadow: None,
+                eviction_log: None,
+                #[cfg(feature = "timestamps")]
+                time: Box::new(SystemClock),
+                scrub: None,
+                loader: None,
+                batch_loader: None,
+                refresh_ahead: None,
+                stale_grace: Duration::from_secs(0)}
+        }
+
+        /// Set where `take` gets objects from. The default is
+        /// `TakeOrder::FreelistFirst`.
+        pub fn with_take_order(mut self, order: TakeOrder) -> PoolCache<Key, Value, Meta> {
+            self.take_order = order;
+            self
+        }
+
+        /// Reserve room for `capacity` free objects and keyed entries up
+        /// front, so that filling the PoolCache doesn't reallocate its
+        /// queues as it grows.
+        pub fn with_pool_capacity(mut self, capacity: usize) -> PoolCache<Key, Value, Meta> {
+            self.freelist.reserve(capacity);
+            self.clock.reserve(capacity);
+            self
         }
 
         /// Returns `true` if the given key is present in the cache.
         pub fn contains_key(&self, key: &Key) -> bool {
-            self.cache.contains_key(key)
+            self.live(key).is_some()
         }
 
         /// Returns a reference to the value associated with `key`, or `None`
         /// if the key is not present in the cache.
         pub fn get(&self, key: &Key) -> Option<&Value> {
-            self.cache.get(key).and_then(|entry| {
-                entry.inc(self.max_heat);
-                Some(&entry.val)
-            })
+            self.get_key_value(key).map(|(_, val)| val)
+        }
+
+        /// Returns the stored key along with a reference to the value
+        /// associated with `key`, or `None` if the key is not present in the
+        /// cache. Like `get`, this counts as a use of the entry.
+        pub fn get_key_value(&self, key: &Key) -> Option<(&Key, &Value)> {
+            self.record(|copy| Op::Get(copy(key)));
+            let found = self.live(key).map(|(stored, entry)| {
+                self.hit(entry);
+                (&**stored, &entry.val)
+            });
+            self.looked_up(key, found.is_some());
+            found
+        }
+
+        /// Counts a use of the entry for `key` without reading its value,
+        /// making it less likely to be evicted. Returns `true` if the key
+        /// was present in the cache.
+        pub fn touch(&self, key: &Key) -> bool {
+            self.record(|copy| Op::Get(copy(key)));
+            let found = self.live(key).map(|(_, entry)| self.hit(entry)).is_some();
+            self.looked_up(key, found);
+            found
+        }
+
+        /// Returns a clone of the value associated with `key`, or `None`
+        /// if the key is not present in the cache. Like `get`, this counts
+        /// as a use of the entry.
+        pub fn get_cloned(&self, key: &Key) -> Option<Value>
+            where Value: Clone {
+            self.get(key).cloned()
         }
 
         /// Add a new object to the pool, not associated with any
         /// key. This will become available to any callers of `take`. 
         pub fn put(&mut self, val: Value) {
-            self.freelist.push_back(val)
+            self.record(|_| Op::Put);
+            self.relieve_pressure();
+            self.freelist.push_back(val);
+            self.enforce_budget();
+        }
+
+        /// Remove every unkeyed object from the pool, untagged ones first
+        /// in the order `take` would hand them out, then those put under
+        /// each tag. Keyed entries are left alone.
+        pub fn drain_pool(&mut self) -> impl Iterator<Item = Value> {
+            let tagged = mem::take(&mut self.tagged);
+            mem::take(&mut self.freelist).into_iter().chain(tagged.into_values().flatten())
         }
 
         /// Insert `val` into the map associated with `key`. Any previous
         /// entry for `key` will be replaced, and the old value will become
         /// available for new callers of `take`.
         pub fn insert(&mut self, key: Key, val: Value) {
-            let mut found_entry = false;
-            if let Some(old_entry) = self.cache.remove(&key) {
-                self.freelist.push_back(old_entry.val);
-                found_entry = true;
+            self.insert_entry(key, CacheEntry::new(val))
+        }
+
+        /// Like `insert`, but the entry's heat is capped at `max_heat` rather
+        /// than the cache's, until it is replaced. A low cap keeps large
+        /// values reusable; a high one lets small values stay cached longer.
+        /// Only the `Clock`, `TwoQ`, `Sampled`, `Bucketed`, `LazyClock` and
+        /// `WeightedRandom` policies cap heat.
+        pub fn insert_with_max_heat(&mut self, key: Key, val: Value, max_heat: u64) {
+            let mut entry = CacheEntry::new(val);
+            entry.max_heat = Some(max_heat);
+            self.insert_entry(key, entry)
+        }
+
+        fn insert_entry(&mut self, key: Key, entry: CacheEntry<Value, Meta>) {
+            self.insert_entry_with(key, entry, PoolCache::admit)
+        }
+
+        // Insert `entry`, placing its key on the clock with `place` if the
+        // key is new.
+        fn insert_entry_with(&mut self, key: Key, mut entry: CacheEntry<Value, Meta>,
+                             place: fn(&mut Self, Arc<Key>, &mut CacheEntry<Value, Meta>)) {
+            self.record(|copy| Op::Insert(copy(&key)));
+            self.relieve_pressure();
+            if entry.expires.is_none() {
+                entry.expires = self.ttl.map(|ttl| Instant::now() + ttl);
+            }
+            entry.generation = self.next_generation();
+            entry.epoch = self.epoch;
+            self.used(&entry);
+            *entry.aged.get_mut() = self.age;
+            #[cfg(feature = "timestamps")]
+            {
+                entry.stamps = Stamps::new(self.time.now());
             }
-            if !found_entry {
-                self.clock.push_back(key.clone());
+            self.counters.insert();
+            self.adapt(&key);
+            let ns = entry.ns.clone();
+            let groups = entry.groups.clone();
+            let stored = self.cache.get_key_value(&key).map(|(stored, _)| stored.clone());
+            match stored {
+                Some(key) => {
+                    // the entry keeps its key, and its place on the clock.
+                    let current = self.cache.get_mut(&key).unwrap();
+                    entry.hot = current.hot;
+                    entry.test = current.test;
+                    entry.spares = mem::take(&mut current.spares);
+                    let old_entry = mem::replace(current, entry);
+                    self.leave_namespace(&key, old_entry.ns.as_ref());
+                    self.leave_groups(&key, &old_entry.groups);
+                    self.forget_dependencies(&key);
+                    self.notify(&key, &old_entry.val, RemovalCause::Replaced);
+                    #[cfg(feature = "log")]
+                    log::trace!("poolcache: replaced an entry, heat was {}", old_entry.heat());
+                    self.freelist.push_back(old_entry.val);
+                    self.join_namespace(&key, ns);
+                    self.join_groups(&key, groups);
+                    self.invalidate_dependents(&key);
+                }
+                None => {
+                    self.shadow_insert(&key);
+                    let key = Arc::new(key);
+                    place(self, key.clone(), &mut entry);
+                    self.join_namespace(&key, ns);
+                    self.join_groups(&key, groups);
+                    self.cache.insert(key, entry);
+                    #[cfg(feature = "log")]
+                    log::trace!("poolcache: inserted an entry, {} entries", self.cache.len());
+                }
+            }
+            self.enforce_budget();
+        }
+
+        /// Replace the value associated with `key` with `val`, returning the
+        /// previous value. Unlike `insert`, the entry keeps its current
+        /// heat, metadata and position in the eviction clock. If `key` is not
+        /// present, `val` is inserted as a new entry and `None` is returned.
+        pub fn swap(&mut self, key: &Key, val: Value) -> Option<Value>
+            where Key: Clone {
+            if self.live(key).is_some() {
+                self.record(|copy| Op::Swap(copy(key)));
+                let generation = self.next_generation();
+                let entry = self.cache.get_mut(key).unwrap();
+                entry.generation = generation;
+                let old = mem::replace(&mut entry.val, val);
+                self.invalidate_dependents(key);
+                return Some(old);
             }
-            self.cache.insert(key, CacheEntry::new(val));
+            self.insert(key.clone(), val);
+            None
         }
 
         /// Take returns an object from the pool, evicting the least-used
-        /// cached key if necessary. Returns `None` only if the PoolCache
-        /// contains no items.
+        /// cached key if necessary. Returns `None` if there is nothing it
+        /// could hand out, such as when only protected keys, entries the
+        /// eviction filter rejects or entries held at the heat floor are
+        /// cached, or when `TakeOrder::FreelistOnly` is set and there are no
+        /// unkeyed objects. See `with_take_order` for preferring eviction
+        /// over the freelist, or never evicting.
         pub fn take(&mut self) -> Option<Value> {
-            if let Some(val) = self.freelist.pop_front() {
-                return Some(val);
+            self.take_bounded(usize::MAX)
+        }
+
+        /// Like `take`, but gives up and returns `None` after inspecting
+        /// `max_scan` keyed entries without finding one to evict, bounding
+        /// the work done by a single call. Heat drained by the search is
+        /// kept, so a later call picks up where this one left off. The `Lfu`
+        /// and `Sampled` policies always find a victim, and ignore
+        /// `max_scan`.
+        pub fn take_bounded(&mut self, max_scan: usize) -> Option<Value> {
+            match self.try_take(max_scan) {
+                Take::Taken(val) => Some(val),
+                Take::Nothing | Take::GaveUp => None,
+            }
+        }
+
+        // Like `take_bounded`, but tells a search that gave up apart from
+        // one that could never succeed.
+        pub(crate) fn try_take(&mut self, max_scan: usize) -> Take<Value> {
+            self.relieve_pressure();
+            self.stuck = false;
+            let val = match self.take_order {
+                TakeOrder::FreelistFirst => self.freelist.pop_front()
+                    .or_else(|| self.evict(max_scan, RemovalCause::Evicted).map(|(_, val)| val)),
+                TakeOrder::EvictFirst => self.evict(max_scan, RemovalCause::Evicted)
+                    .map(|(_, val)| val)
+                    .or_else(|| self.freelist.pop_front()),
+                TakeOrder::FreelistOnly => self.freelist.pop_front(),
+            };
+            match val {
+                Some(val) => {
+                    self.record(|_| Op::Take);
+                    Take::Taken(val)
+                }
+                None if self.stuck || self.cache.is_empty() => Take::Nothing,
+                None if self.take_order == TakeOrder::FreelistOnly => Take::Nothing,
+                None => Take::GaveUp,
+            }
+        }
+
+        /// Evict the least valuable keyed entry, even if there are unkeyed
+        /// objects to hand out instead, returning its key and value. Returns
+        /// `None` only if no keys are cached.
+        pub fn take_evicting(&mut self) -> Option<(Key, Value)>
+            where Key: Clone {
+            self.relieve_pressure();
+            let (key, val) = self.evict(usize::MAX, RemovalCause::Evicted)?;
+            // the key is still shared if the policy remembers it.
+            let key = Arc::try_unwrap(key).unwrap_or_else(|key| (*key).clone());
+            Some((key, val))
+        }
+
+        // Remove the least valuable keyed entry according to the current
+        // policy, inspecting at most `max_scan` entries.
+        fn evict(&mut self, max_scan: usize, cause: RemovalCause) -> Option<(Arc<Key>, Value)> {
+            #[cfg(feature = "log")]
+            let started = Instant::now();
+            let swept = self.swept;
+            let key = self.evict_allowed(max_scan);
+            #[cfg(feature = "log")]
+            logging::searched(started.elapsed(), key.is_some());
+            let key = key?;
+            let mut entry = self.cache.remove(&key).unwrap();
+            self.freelist.extend(entry.spares.drain(..));
+            self.log_eviction(&key, entry.heat(), self.swept - swept, cause);
+            #[cfg(feature = "log")]
+            log::debug!("poolcache: evicted an entry with heat {} ({:?})", entry.heat(), cause);
+            self.leave_namespace(&key, entry.ns.as_ref());
+            self.leave_groups(&key, &entry.groups);
+            self.forget_dependencies(&key);
+            self.notify(&key, &entry.val, cause);
+            self.tune();
+            Some((key, entry.val))
+        }
+
+        // Like `evict_key`, but passes over entries that may not be evicted,
+        // putting them back in their queues afterwards.
+        fn evict_allowed(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            if self.protected.is_empty() && self.eviction_filter.is_none() {
+                return self.evict_key(max_scan);
+            }
+            let mut skipped = Vec::new();
+            let mut found = None;
+            // each entry is passed over at most once.
+            while skipped.len() < self.cache.len() {
+                match self.evict_key(max_scan) {
+                    Some(key) if !self.evictable(&key) => skipped.push(key),
+                    key => {
+                        found = key;
+                        break;
+                    }
+                }
             }
-            // cache is empty.
-            if self.clock.is_empty() {
+            if found.is_none() && skipped.len() >= self.cache.len() {
+                self.stuck = true;
+            }
+            for key in skipped {
+                self.requeue(key);
+            }
+            found
+        }
+
+        // Returns the entry for `key`, unless it has expired. Expired entries
+        // are cooled when they are found, so that they are evicted soon.
+        fn live(&self, key: &Key) -> Option<(&Arc<Key>, &CacheEntry<Value, Meta>)> {
+            let (stored, entry) = self.cache.get_key_value(key)?;
+            if self.stale(key, entry) {
+                #[cfg(feature = "log")]
+                log::trace!("poolcache: found an expired entry");
+                self.cool(entry);
                 return None;
             }
-            // loop over the elements in `clock`, decrementing heat until
-            // we find an eligible value to evict.
-            loop {
-                let key = self.clock.pop_front().unwrap();
-                let heat = self.cache.get(&key).unwrap().dec();
-                if heat == 0 {
-                    // eligible element.
-                    return Some(self.cache.remove(&key).unwrap().val);
+            Some((stored, entry))
+        }
+
+        // Remove the keyed entry for `key`, wherever it is on the clock.
+        // Takes time linear in the number of keyed entries.
+        fn remove_entry(&mut self, key: &Key, cause: RemovalCause) -> Option<(Arc<Key>, Value)> {
+            let queues = iter::once(&mut self.clock).chain(iter::once(&mut self.hot))
+                .chain(self.buckets.iter_mut());
+            for queue in queues {
+                if let Some(idx) = queue.iter().position(|queued| **queued == *key) {
+                    queue.remove(idx);
+                    break;
+                }
+            }
+            let (key, mut entry) = self.cache.remove_entry(key)?;
+            self.freelist.extend(entry.spares.drain(..));
+            self.leave_namespace(&key, entry.ns.as_ref());
+            self.leave_groups(&key, &entry.groups);
+            self.forget_dependencies(&key);
+            self.notify(&key, &entry.val, cause);
+            Some((key, entry.val))
+        }
+
+        // Remove the keyed entries for `keys`, returning them. Entries that
+        // depend on invalidated ones are invalidated in turn. Takes time
+        // linear in the number of keyed entries, to take the keys off the
+        // clock.
+        fn remove_entries(&mut self, keys: BTreeSet<Arc<Key>>, cause: RemovalCause)
+                          -> Vec<(Arc<Key>, Value)> {
+            let queues = iter::once(&mut self.clock).chain(iter::once(&mut self.hot))
+                .chain(self.buckets.iter_mut());
+            for queue in queues {
+                queue.retain(|key| !keys.contains(key));
+            }
+            let removed: Vec<_> = keys.into_iter().filter_map(|key| {
+                let (key, mut entry) = self.cache.remove_entry(&*key)?;
+                self.freelist.extend(entry.spares.drain(..));
+                self.leave_namespace(&key, entry.ns.as_ref());
+                self.leave_groups(&key, &entry.groups);
+                self.forget_dependencies(&key);
+                self.notify(&key, &entry.val, cause);
+                Some((key, entry.val))
+            }).collect();
+            if cause == RemovalCause::Invalidated {
+                for (key, _) in &removed {
+                    self.invalidate_dependents(key);
                 }
-                // non-zero heat, keep looping.
-                self.clock.push_back(key);
+            }
+            removed
+        }
+
+        // Remove the keyed entries for `keys`, moving their values to the
+        // freelist.
+        fn invalidate(&mut self, keys: BTreeSet<Arc<Key>>) {
+            for (_, val) in self.remove_entries(keys, RemovalCause::Invalidated) {
+                self.freelist.push_back(val);
             }
         }
 }
 
+/// A PoolCache holding reference-counted values, so readers can keep a
+/// value alive after the borrow of the cache ends.
+pub type SharedPoolCache<Key, Value> = PoolCache<Key, Arc<Value>>;
+
+impl<Key, Value, Meta> PoolCache<Key, Arc<Value>, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns a new shared handle to the value associated with `key`,
+        /// or `None` if the key is not present in the cache.
+        pub fn get_shared(&self, key: &Key) -> Option<Arc<Value>> {
+            self.get(key).cloned()
+        }
+
+        /// Like `take`, but returns the unwrapped value. Objects that are
+        /// still referenced outside of the cache can't be reused; they are
+        /// released from the cache and skipped. Returns `None` once the
+        /// PoolCache contains no uniquely-owned items.
+        pub fn take_unique(&mut self) -> Option<Value> {
+            while let Some(val) = self.take() {
+                if let Ok(val) = Arc::try_unwrap(val) {
+                    return Some(val);
+                }
+            }
+            None
+        }
+
+        /// Returns the value interned under `key`, creating it with `create`
+        /// if it isn't cached, so that equal keys share one value for as long
+        /// as it stays cached. With a limit set by `with_max_objects`, an
+        /// entry is evicted to make room for a new one once the limit is
+        /// reached, so that (e.g. with `Policy::Lfu`) rarely used values are
+        /// eventually dropped from the cache.
+        pub fn get_or_intern<F>(&mut self, key: Key, create: F) -> Arc<Value>
+            where F: FnOnce(&Key) -> Value {
+            if let Some(val) = self.get_shared(&key) {
+                return val;
+            }
+            if self.cache.len() >= self.max_objects {
+                if let Some((_, val)) = self.evict(usize::MAX, RemovalCause::Evicted) {
+                    self.discard(val);
+                }
+            }
+            let val = Arc::new(create(&key));
+            self.insert(key, val.clone());
+            val
+        }
+}
 
 #[cfg(test)]
 mod test {
@@ -194,4 +989,2468 @@ mod test {
         // leaving the cache empty.
         assert_eq!(None, cache.take());
     }
+
+    #[test]
+    fn swap() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+
+        // swapping a missing key behaves like insert.
+        assert_eq!(None, cache.swap(&1, String::from("foo")));
+        cache.get(&1);
+        cache.get(&1);
+        cache.insert(2, String::from("bar"));
+
+        // swapping an existing key hands back the old value, and the
+        // entry keeps its heat.
+        assert_eq!(Some(String::from("foo")), cache.swap(&1, String::from("newfoo")));
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("newfoo")), cache.take());
+    }
+
+    #[test]
+    fn lfu() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(2, super::Policy::Lfu { period: 100 });
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.insert(3, String::from("baz"));
+
+        // heat is not capped at `max_heat`, so '1' stays ahead of '2'.
+        for _ in 0..5 {
+            cache.get(&1);
+        }
+        for _ in 0..3 {
+            cache.get(&2);
+        }
+        assert_eq!(Some(String::from("baz")), cache.take());
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn clock_pro() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(0, super::Policy::ClockPro);
+        for key in 0..4 {
+            cache.insert(key, key.to_string());
+        }
+
+        // '0' is re-used during its test period, so it is promoted to hot,
+        // and the other cold entries are evicted ahead of it.
+        cache.get(&0);
+        assert_eq!(Some(String::from("1")), cache.take());
+        assert_eq!(Some(String::from("2")), cache.take());
+        assert!(cache.contains_key(&0));
+
+        // '1' was evicted recently, so on its return it starts out hot.
+        cache.insert(1, String::from("1"));
+        assert_eq!(Some(String::from("3")), cache.take());
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn two_q() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::TwoQ);
+        for key in 1..6 {
+            cache.insert(key, key.to_string());
+        }
+
+        // uses of new entries are ignored: '1' is evicted first anyway.
+        cache.get(&1);
+        assert_eq!(Some(String::from("1")), cache.take());
+
+        // '1' comes back soon enough to enter the main queue, and outlives
+        // the unproven entries that are still in the FIFO queue.
+        cache.insert(1, String::from("1"));
+        assert_eq!(Some(String::from("2")), cache.take());
+        assert_eq!(Some(String::from("3")), cache.take());
+        assert_eq!(Some(String::from("4")), cache.take());
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn sampled() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::Sampled { samples: 3 });
+        for key in 0..8 {
+            cache.insert(key, key.to_string());
+        }
+        cache.get(&0);
+
+        // every entry is eventually taken, whichever are sampled.
+        let mut taken: Vec<String> = (0..8).map(|_| cache.take().unwrap()).collect();
+        taken.sort();
+        assert_eq!(taken, (0..8).map(|key| key.to_string()).collect::<Vec<_>>());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn weighted_random() {
+        let mut last = 0;
+        for _ in 0..100 {
+            let mut cache: super::PoolCache<u64, String> =
+                super::PoolCache::with_policy(8, super::Policy::WeightedRandom);
+            for key in 0..8 {
+                cache.insert(key, key.to_string());
+            }
+            for _ in 0..8 {
+                cache.get(&0);
+            }
+
+            // every entry is eventually taken, whichever are drawn.
+            let taken: Vec<String> = (0..8).map(|_| cache.take().unwrap()).collect();
+            assert_eq!(None, cache.take());
+            let mut sorted = taken.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..8).map(|key| key.to_string()).collect::<Vec<_>>());
+            if taken[7] == "0" {
+                last += 1;
+            }
+        }
+        // the hot entry is the last to go far more often than by chance.
+        assert!(last > 30);
+    }
+
+    #[test]
+    fn bucketed() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::Bucketed);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.insert(3, String::from("baz"));
+        for _ in 0..10 {
+            cache.get(&1);
+        }
+        cache.get(&3);
+
+        // '2' is the coldest, then '3'; '1' is capped at max_heat.
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("baz")), cache.take());
+
+        // heat doesn't decay, so a new entry goes before '1'.
+        cache.insert(4, String::from("qux"));
+        assert_eq!(Some(String::from("qux")), cache.take());
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn shed() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::new(5).with_weigher(|val: &String| val.len());
+        cache.put(String::from("free"));
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.get(&2);
+
+        // the unkeyed object goes first, then the coldest entry.
+        assert_eq!(7, cache.evict_bytes(5));
+        assert!(cache.contains_key(&2));
+        assert!(!cache.contains_key(&1));
+
+        // a signal from elsewhere is applied on the next modification.
+        cache.pressure_handle().signal(1.0);
+        cache.put(String::from("free"));
+        assert_eq!(Some(String::from("free")), cache.take());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn dot() {
+        let mut cache: super::PoolCache<String, u64> = super::PoolCache::new(4);
+        cache.insert(String::from("a\"b"), 1);
+        cache.insert(String::from("c"), 2);
+        cache.get(&String::from("c"));
+        cache.put(3);
+
+        let dot = cache.to_dot();
+        assert!(dot.starts_with("digraph poolcache {"));
+        assert!(dot.contains(r#"q0_0 [label="\"a\\\"b\"\nheat 1", style=""];"#));
+        assert!(dot.contains(r#"q0_1 [label="\"c\"\nheat 2", style=""];"#));
+        assert!(dot.contains("q0_1 -> q0_0;"));
+        assert!(dot.contains(r#"label="freelist: 1\ntagged: 0""#));
+    }
+
+    #[test]
+    fn eq() {
+        let mut left: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        let mut right: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        left.insert(1, String::from("foo"));
+        left.insert(2, String::from("bar"));
+        right.insert(2, String::from("bar"));
+        right.insert(1, String::from("foo"));
+        right.get(&1);
+        assert!(left == right);
+
+        left.put(String::from("a"));
+        left.put(String::from("b"));
+        right.put(String::from("b"));
+        assert!(left == right);
+        assert!(!left.eq_with_pool(&right));
+        right.put(String::from("a"));
+        assert!(left.eq_with_pool(&right));
+
+        right.insert(1, String::from("baz"));
+        assert!(left != right);
+    }
+
+    #[test]
+    fn default() {
+        #[derive(Default)]
+        struct Holder {
+            cache: super::PoolCache<u64, String>,
+        }
+
+        let mut holder = Holder::default();
+        assert_eq!(4, holder.cache.max_heat);
+        holder.cache.insert(1, String::from("foo"));
+        assert_eq!(Some(&String::from("foo")), holder.cache.get(&1));
+    }
+
+    #[test]
+    fn take_order() {
+        use super::TakeOrder;
+
+        let fill = |order| {
+            let mut cache: super::PoolCache<u64, String> =
+                super::PoolCache::new(4).with_take_order(order);
+            cache.insert(1, String::from("foo"));
+            cache.put(String::from("free"));
+            cache
+        };
+
+        let mut cache = fill(TakeOrder::EvictFirst);
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(Some(String::from("free")), cache.take());
+        assert_eq!(None, cache.take());
+
+        let mut cache = fill(TakeOrder::FreelistOnly);
+        assert_eq!(Some(String::from("free")), cache.take());
+        assert_eq!(None, cache.take());
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn take_evicting() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::TwoQ);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.put(String::from("free"));
+
+        assert_eq!(Some((1, String::from("foo"))), cache.take_evicting());
+        assert_eq!(Some((2, String::from("bar"))), cache.take_evicting());
+        assert_eq!(None, cache.take_evicting());
+        assert_eq!(Some(String::from("free")), cache.take());
+    }
+
+    #[test]
+    fn pool_iter() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        assert_eq!(None, cache.peek_pool_front());
+        cache.put(String::from("foo"));
+        cache.put(String::from("bar"));
+        cache.insert(1, String::from("baz"));
+        cache.put_tagged("tag", String::from("qux"));
+
+        assert_eq!(2, cache.pool_len());
+        assert_eq!(vec!["foo", "bar"], cache.pool_iter().collect::<Vec<_>>());
+        assert_eq!(Some(&String::from("foo")), cache.peek_pool_front());
+        assert_eq!(Some(String::from("foo")), cache.take());
+    }
+
+    #[test]
+    fn drain_pool() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.put(String::from("foo"));
+        cache.put_tagged("tag", String::from("bar"));
+        cache.insert(1, String::from("baz"));
+
+        assert_eq!(vec![String::from("foo"), String::from("bar")],
+                   cache.drain_pool().collect::<Vec<_>>());
+        assert_eq!(0, cache.tagged_len("tag"));
+        assert!(cache.contains_key(&1));
+        assert_eq!(Some(String::from("baz")), cache.take());
+    }
+
+    #[test]
+    fn handles() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        let foo = cache.get_handle(&1).unwrap();
+        let bar = cache.get_handle(&2).unwrap();
+        assert!(cache.get_handle(&3).is_none());
+        assert_eq!(Some(&String::from("foo")), cache.resolve(&foo));
+
+        // replacing or evicting an entry makes its handles stale.
+        cache.insert(1, String::from("baz"));
+        assert_eq!(None, cache.resolve(&foo));
+        assert_eq!(Some(&String::from("baz")), cache.resolve(&cache.get_handle(&1).unwrap()));
+        cache.take();
+        cache.insert(2, String::from("bar"));
+        assert_eq!(None, cache.resolve(&bar));
+    }
+
+    #[test]
+    fn index() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.insert(1, String::from("foo"));
+        assert_eq!("foo", cache[&1]);
+        assert_eq!(Some(2), cache.hottest().map(|(_, heat, _)| heat));
+        let missing = std::panic::AssertUnwindSafe(|| cache[&2].len());
+        assert!(std::panic::catch_unwind(missing).is_err());
+    }
+
+    #[test]
+    fn from_map() {
+        let map: super::BTreeMap<u64, String> = vec![
+            (2, String::from("bar")),
+            (1, String::from("foo")),
+        ].into_iter().collect();
+        let mut cache = super::PoolCache::from(map);
+        assert_eq!(Some(&String::from("bar")), cache.get(&2));
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn from_pool() {
+        let mut cache: super::PoolCache<u64, Vec<u8>> =
+            super::PoolCache::from_pool(4, (0..3).map(|_| Vec::with_capacity(64)));
+        assert_eq!(3, cache.pool_len());
+        assert_eq!(64, cache.take().unwrap().capacity());
+
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::from(vec![String::from("foo"), String::from("bar")]);
+        assert_eq!(Some(String::from("foo")), cache.take());
+    }
+
+    #[test]
+    fn heat_floor() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::new(4).with_heat_floor(1);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.get(&1);
+        cache.get(&1);
+
+        // entries decay to the floor, but no further, so they stay put.
+        assert_eq!(None, cache.take());
+        assert!(cache.contains_key(&1) && cache.contains_key(&2));
+        assert_eq!(Some((&1, 1, &String::from("foo"))), cache.hottest());
+
+        // expired entries go regardless.
+        cache.insert_expiring_at(3, String::from("baz"), std::time::Instant::now());
+        assert!(!cache.contains_key(&3));
+        assert_eq!(Some(String::from("baz")), cache.take());
+    }
+
+    #[test]
+    fn entry_max_heat() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(2);
+        cache.insert_with_max_heat(1, String::from("big"), 1);
+        cache.insert_with_max_heat(2, String::from("small"), 8);
+        cache.insert(3, String::from("foo"));
+        for _ in 0..8 {
+            cache.get(&1);
+            cache.get(&2);
+            cache.get(&3);
+        }
+        let heats: Vec<u64> = cache.iter_by_heat().map(|(_, heat, _)| heat).collect();
+        assert_eq!(vec![8, 2, 1], heats);
+        assert_eq!(Some(String::from("big")), cache.take());
+    }
+
+    #[test]
+    fn lazy_clock() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(3, super::Policy::LazyClock);
+        cache.insert(1, String::from("one"));
+        cache.insert(2, String::from("two"));
+        cache.insert(3, String::from("three"));
+        for _ in 0..5 {
+            cache.get(&1);
+            cache.get(&3);
+        }
+        cache.get(&2);
+
+        // heat 2 goes cold first, ageing the others to heat 1.
+        assert_eq!(Some(String::from("two")), cache.take());
+        cache.insert(4, String::from("four"));
+        cache.get(&3);
+        assert_eq!(Some(String::from("one")), cache.take());
+        assert_eq!(Some(String::from("four")), cache.take());
+        assert_eq!(Some(String::from("three")), cache.take());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn lazy_clock_scan() {
+        use super::Policy;
+
+        let scanned = |policy| {
+            let mut cache: super::PoolCache<u64, String> =
+                super::PoolCache::with_policy(16, policy).with_eviction_log(2);
+            for i in 0..100 {
+                cache.insert(i, i.to_string());
+                for _ in 0..20 {
+                    cache.get(&i);
+                }
+            }
+            cache.insert(100, String::from("cold"));
+            cache.take();
+            cache.insert(101, String::from("cold"));
+            cache.take();
+            cache.recent_evictions().last().map(|eviction| eviction.scanned)
+        };
+        // the hot entries are passed over once, then left in their queue.
+        assert_eq!(Some(1), scanned(Policy::LazyClock));
+        assert!(scanned(Policy::Clock).unwrap() > 100);
+    }
+
+    #[test]
+    fn gdsf() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::Gdsf)
+                .with_weigher(|val: &String| val.len())
+                .with_cost(|_, val: &String| if val.len() > 10 { 1 } else { 10 });
+        cache.insert(1, "thumbnail".repeat(10));
+        cache.insert(2, String::from("meta"));
+        for _ in 0..5 {
+            cache.get(&1);
+        }
+
+        // the large, cheap entry goes first, however often it was used.
+        assert_eq!(Some(90), cache.take().map(|val| val.len()));
+        cache.insert(3, String::from("meta"));
+        cache.insert(4, "thumbnail".repeat(10));
+        assert_eq!(Some(90), cache.take().map(|val| val.len()));
+        // of two equal entries, the one not used since inflation rose goes.
+        assert_eq!(Some(String::from("meta")), cache.take());
+        assert!(!cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+    }
+
+    #[test]
+    fn protected() {
+        use super::Policy;
+
+        let policies = [Policy::Clock, Policy::Lfu { period: 100 }, Policy::ClockPro, Policy::TwoQ,
+                        Policy::Sampled { samples: 2 }, Policy::Bucketed, Policy::LazyClock,
+                        Policy::Gdsf, Policy::WeightedRandom];
+        for &policy in &policies {
+            let mut cache: super::PoolCache<u64, String> =
+                super::PoolCache::with_policy(4, policy);
+            cache.protect(1);
+            cache.insert(1, String::from("root"));
+            cache.insert(2, String::from("foo"));
+            cache.insert(3, String::from("bar"));
+            cache.get(&2);
+
+            let mut taken = vec![cache.take().unwrap(), cache.take().unwrap()];
+            taken.sort();
+            assert_eq!(vec![String::from("bar"), String::from("foo")], taken);
+            assert_eq!(None, cache.take());
+            assert!(cache.contains_key(&1));
+
+            assert!(cache.unprotect(&1));
+            assert_eq!(Some(String::from("root")), cache.take());
+        }
+    }
+
+    #[test]
+    fn eviction_filter() {
+        use std::sync::atomic::{AtomicBool,Ordering};
+
+        // buffers that are busy can't be reused.
+        type Buffer = (String, super::Arc<AtomicBool>);
+        let mut cache: super::PoolCache<u64, Buffer> = super::PoolCache::new(4)
+            .with_eviction_filter(|_, val: &Buffer| !val.1.load(Ordering::Relaxed));
+        let busy = super::Arc::new(AtomicBool::new(true));
+        cache.insert(1, (String::from("foo"), busy.clone()));
+        cache.insert(2, (String::from("bar"), super::Arc::new(AtomicBool::new(false))));
+
+        assert_eq!(Some(String::from("bar")), cache.take().map(|val| val.0));
+        assert_eq!(None, cache.take().map(|val| val.0));
+        busy.store(false, Ordering::Relaxed);
+        assert_eq!(Some(String::from("foo")), cache.take().map(|val| val.0));
+    }
+
+    #[test]
+    fn cost() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::Lfu { period: 100 })
+                .with_cost(|_, val: &String| val.len() as u64);
+        cache.insert(1, String::from("expensive"));
+        cache.insert(2, String::from("cheap"));
+        cache.insert(3, String::from("hot"));
+        cache.get(&3);
+
+        assert_eq!(Some(String::from("cheap")), cache.take());
+        assert_eq!(Some(String::from("expensive")), cache.take());
+    }
+
+    #[test]
+    fn recency_tiebreak() {
+        // with a maximum heat of 1 the clock is first in, first out, unless
+        // recency breaks the tie.
+        let mut fifo: super::PoolCache<u64, String> = super::PoolCache::new(1);
+        let mut lru = super::PoolCache::new(1).with_recency_tiebreak();
+        for cache in [&mut fifo, &mut lru] {
+            cache.insert(1, String::from("one"));
+            cache.insert(2, String::from("two"));
+            cache.get(&1);
+        }
+        assert_eq!(Some(String::from("one")), fifo.take());
+        assert_eq!(Some(String::from("two")), lru.take());
+        assert_eq!(Some(String::from("one")), lru.take());
+
+        let mut lfu: super::PoolCache<u64, String> =
+            super::PoolCache::with_policy(4, super::Policy::Lfu { period: 100 })
+                .with_recency_tiebreak();
+        lfu.insert(1, String::from("one"));
+        lfu.insert(2, String::from("two"));
+        lfu.get(&1);
+        lfu.get(&2);
+        lfu.get(&1);
+        lfu.get(&2);
+        assert_eq!(Some(String::from("one")), lfu.take());
+    }
+
+    #[test]
+    fn stats() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, String::from("foo"));
+        cache.get(&1);
+        cache.get(&2);
+        cache.insert(1, String::from("bar"));
+        assert_eq!(super::Stats{hits: 1, misses: 1, inserts: 2, evictions: 0, removals: 1},
+                   cache.stats());
+        assert_eq!(0.5, cache.stats().hit_rate());
+
+        // windows count from the previous window, leaving the totals alone.
+        assert_eq!(cache.stats(), cache.window_stats().0);
+        cache.take();
+        cache.take();
+        let (window, _) = cache.window_stats();
+        assert_eq!(super::Stats{evictions: 1, ..Default::default()}, window);
+        assert_eq!(1, cache.stats().evictions);
+
+        cache.reset_stats();
+        assert_eq!(super::Stats::default(), cache.stats());
+        assert_eq!(super::Stats::default(), cache.window_stats().0);
+    }
+
+    #[test]
+    fn recent_hit_rate() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::new(5).with_hit_rate_span(10);
+        assert_eq!(0.0, cache.recent_hit_rate());
+        cache.insert(1, String::from("foo"));
+        for _ in 0..100 {
+            cache.get(&1);
+        }
+        assert_eq!(1.0, cache.recent_hit_rate());
+
+        // after the traffic shifts to misses, the recent rate follows while
+        // the lifetime rate barely moves.
+        for _ in 0..50 {
+            cache.get(&2);
+        }
+        assert!(cache.recent_hit_rate() < 0.01);
+        assert!(cache.stats().hit_rate() > 0.6);
+    }
+
+    #[test]
+    fn heat_tuning() {
+        // a scan never hits, so raising the heat doesn't help, and it is
+        // kept low.
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(4)
+            .with_heat_tuning(2, 16);
+        assert_eq!(4, cache.max_heat());
+        for key in 0..10 {
+            cache.insert(key, key);
+        }
+        for key in 10..20_000 {
+            cache.get(&key);
+            let val = cache.take().unwrap();
+            cache.insert(key, val);
+        }
+        assert!(cache.max_heat() <= 4);
+
+        // a sliding window of keys is kept hot, so the clock is swept past
+        // hot entries for each eviction, and the heat comes down.
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(16)
+            .with_heat_tuning(1, 16);
+        for key in 0..100 {
+            cache.insert(key, key);
+        }
+        for round in 0..5_000 {
+            for key in round + 1..round + 100 {
+                cache.get(&key);
+            }
+            let val = cache.take().unwrap();
+            cache.insert(round + 100, val);
+        }
+        assert!(cache.max_heat() < 16);
+
+        // policies that aren't tuned keep their `max_heat`.
+        let cache: super::PoolCache<u64, u64> =
+            super::PoolCache::with_policy(32, super::Policy::LazyClock).with_heat_tuning(2, 16);
+        assert_eq!(32, cache.max_heat());
+    }
+
+    #[test]
+    fn adaptive_budget() {
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(4)
+            .with_weigher(|_| 1)
+            .with_adaptive_budget(10, 100);
+        assert_eq!(Some(100), cache.byte_budget());
+
+        // keys never come back, so more memory wouldn't help.
+        for key in 0..20_000 {
+            cache.insert(key, key);
+        }
+        assert_eq!(Some(10), cache.byte_budget());
+
+        // cycling over a few more keys than fit, dropped keys come straight
+        // back, and the budget grows.
+        for round in 0..20_000 {
+            let key = round % 15;
+            if cache.get(&key).is_none() {
+                cache.insert(key, key);
+            }
+        }
+        assert!(cache.byte_budget().unwrap() > 10);
+    }
+
+    #[test]
+    fn shadow() {
+        // a few hot keys among a stream of one-off keys: without heat, the
+        // stream pushes the hot keys out, while a shadow with heat keeps
+        // them.
+        let shadow = super::PoolCache::new(4);
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(1).with_shadow(shadow);
+        for val in 0..10 {
+            cache.put(val);
+        }
+        for round in 0..1000 {
+            for key in [round % 5, 100 + round] {
+                if cache.get(&key).is_none() {
+                    let val = cache.take().unwrap();
+                    cache.insert(key, val);
+                }
+            }
+        }
+        let shadow = cache.shadow_stats().unwrap();
+        assert_eq!(2000, shadow.hits + shadow.misses);
+        assert_eq!(500, cache.stats().hits);
+        assert!(shadow.hits > 900);
+        assert_eq!(None, super::PoolCache::<u64, u64>::new(1).shadow_stats());
+    }
+
+    #[test]
+    fn recent_evictions() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::new(5).with_eviction_log(2);
+        cache.insert(1, String::from("one"));
+        cache.insert(2, String::from("two"));
+        cache.insert(3, String::from("three"));
+        cache.get(&1);
+        cache.take();
+        cache.take();
+        cache.shed(1.0);
+        let evictions: Vec<_> = cache.recent_evictions().cloned().collect();
+        assert_eq!(vec![
+            // the first eviction, of '2' after passing over '1', has been
+            // pushed out of the log.
+            super::Eviction{key: 3, heat: 0, scanned: 1, cause: super::RemovalCause::Evicted},
+            super::Eviction{key: 1, heat: 0, scanned: 1, cause: super::RemovalCause::Shed},
+        ], evictions);
+        assert_eq!(0, super::PoolCache::<u64, String>::new(5).recent_evictions().count());
+    }
+
+    #[test]
+    fn freeze() {
+        use std::thread;
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        let frozen = cache.freeze();
+
+        // the snapshot stays as it was while the cache changes.
+        cache.insert(1, String::from("baz"));
+        cache.take();
+        cache.take();
+        let reader = frozen.clone();
+        let entries = thread::spawn(move || {
+            reader.iter().map(|(&key, val)| (key, val.clone())).collect::<Vec<_>>()
+        }).join().unwrap();
+        assert_eq!(vec![(1, String::from("foo")), (2, String::from("bar"))], entries);
+        assert_eq!(Some(&String::from("bar")), frozen.get(&2));
+        assert!(frozen.contains_key(&1));
+        assert_eq!(2, frozen.len());
+        assert_eq!(1, cache.freeze().len());
+    }
+
+    #[test]
+    fn fork() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, String::from("one"));
+
+        let mut fork = cache.fork();
+        fork.insert(2, String::from("two"));
+        fork.insert(1, String::from("uno"));
+        assert_eq!(Some(&String::from("uno")), fork.get(&1));
+        assert!(fork.contains_key(&2));
+        fork.discard();
+        // the cache is unchanged, and the fork's values are back in the pool.
+        assert_eq!(Some(&String::from("one")), cache.get(&1));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(2, cache.pool_len());
+
+        let mut fork = cache.fork();
+        fork.insert(2, String::from("two"));
+        assert_eq!(Some(&String::from("one")), fork.get(&1));
+        fork.commit();
+        assert_eq!(Some(&String::from("two")), cache.get(&2));
+        assert_eq!(2, cache.pool_len());
+
+        // removals are hidden by the fork until it is committed.
+        let mut fork = cache.fork();
+        assert!(fork.remove(&1));
+        assert!(!fork.remove(&3));
+        assert!(!fork.contains_key(&1));
+        fork.discard();
+        assert!(cache.contains_key(&1));
+        let mut fork = cache.fork();
+        fork.remove(&1);
+        fork.commit();
+        assert!(!cache.contains_key(&1));
+        assert_eq!(3, cache.pool_len());
+
+        // objects put into the fork come out first, then copies of the
+        // cache's, and keyed entries are never evicted.
+        let mut fork = cache.fork();
+        fork.put(String::from("mine"));
+        assert_eq!(Some(String::from("mine")), fork.take());
+        for _ in 0..3 {
+            assert!(fork.take().is_some());
+        }
+        assert_eq!(None, fork.take());
+        fork.put(String::from("kept"));
+        fork.discard();
+        assert!(cache.contains_key(&2));
+        // discarding returns the cache's objects as they were.
+        assert_eq!(4, cache.pool_len());
+        assert_eq!(Some(String::from("uno")), cache.take());
+
+        // committing uses them up.
+        let mut fork = cache.fork();
+        while fork.take().is_some() {}
+        fork.commit();
+        assert_eq!(0, cache.pool_len());
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn clear() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, String::from("one"));
+        cache.put(String::from("free"));
+        cache.put_tagged("tag", String::from("tagged"));
+        cache.clear();
+        assert!(!cache.contains_key(&1));
+        assert_eq!(None, cache.take());
+        assert_eq!(None, cache.take_tagged("tag"));
+    }
+
+    #[test]
+    fn refresh_ahead() {
+        use std::time::{Duration,Instant};
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_ttl(Duration::from_secs(3600))
+            .with_loader(|key| format!("fresh {}", key))
+            .with_refresh_ahead(0.5);
+        assert_eq!(Some(&String::from("fresh 3")), cache.get_or_load(&3));
+
+        // '2' is used with less than half of its time to live left.
+        cache.insert(1, String::from("one"));
+        cache.insert_expiring_at(2, String::from("two"), Instant::now() + Duration::from_secs(600));
+        cache.get(&1);
+        cache.get(&2);
+        assert_eq!(vec![&2], cache.pending_refresh().collect::<Vec<_>>());
+
+        assert_eq!(1, cache.refresh_pending());
+        assert_eq!(Some(&String::from("fresh 2")), cache.get(&2));
+        assert_eq!(0, cache.pending_refresh().count());
+        assert_eq!(Some(String::from("two")), cache.take());
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        assert_eq!(None, cache.get_or_load(&1));
+    }
+
+    #[test]
+    fn refresh_pending_replaces() {
+        use std::sync::{Arc,Mutex};
+        use std::time::{Duration,Instant};
+        use super::RemovalCause;
+
+        let replaced = Arc::new(Mutex::new(Vec::new()));
+        let listened = replaced.clone();
+        let mut cache: super::PoolCache<&str, String> = super::PoolCache::new(5)
+            .with_ttl(Duration::from_secs(3600))
+            .with_loader(|key: &&str| key.repeat(200))
+            .with_refresh_ahead(0.5)
+            .with_listener(move |key, val: &String, cause| {
+                listened.lock().unwrap().push((*key, val.clone(), cause));
+            })
+            .with_weigher(|val: &String| val.len())
+            .with_byte_budget(250);
+        let soon = Instant::now() + Duration::from_secs(600);
+        cache.insert_expiring_at("a", String::from("old"), soon);
+        cache.insert_with_deps("page", String::from("<a>"), &["a"]);
+        cache.insert("b", "b".repeat(100));
+        cache.get(&"a");
+
+        // the old value is reported, the dependent entry goes, and objects
+        // are dropped to fit the budget again.
+        assert_eq!(1, cache.refresh_pending());
+        assert_eq!(Some(&"a".repeat(200)), cache.get(&"a"));
+        assert!(!cache.contains_key(&"page"));
+        assert!(cache.weight() <= 250);
+        let replaced = replaced.lock().unwrap();
+        assert_eq!(("a", String::from("old"), RemovalCause::Replaced), replaced[0]);
+    }
+
+    #[test]
+    fn stale_while_revalidate() {
+        use std::time::Duration;
+        use super::Freshness;
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_loader(|key| format!("fresh {}", key))
+            .with_stale_grace(Duration::from_secs(3600));
+        cache.insert(1, String::from("one"));
+        cache.insert_with_ttl(2, String::from("two"), Duration::from_secs(0));
+        assert_eq!(Some((&String::from("one"), Freshness::Fresh)), cache.get_or_stale(&1));
+        assert_eq!(Some((&String::from("two"), Freshness::Stale)), cache.get_or_stale(&2));
+        assert_eq!(None, cache.get(&2));
+
+        // the stale entry was queued for refresh (which restarts its zero
+        // time to live, so it is stale again straight away).
+        assert_eq!(1, cache.refresh_pending());
+        assert_eq!(Some((&String::from("fresh 2"), Freshness::Stale)), cache.get_or_stale(&2));
+
+        // past the grace period, an expired entry is a miss.
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert_with_ttl(1, String::from("one"), Duration::from_secs(0));
+        assert_eq!(None, cache.get_or_stale(&1));
+    }
+
+    #[test]
+    fn single_flight() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        let cache = Arc::new(super::SyncPoolCache::new(super::PoolCache::<u64, String>::new(5)));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..8).map(|_| {
+            let (cache, calls) = (cache.clone(), calls.clone());
+            thread::spawn(move || cache.get_or_insert_with(&1, |key| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(50));
+                format!("value {}", key)
+            }))
+        }).collect();
+        for thread in threads {
+            assert_eq!("value 1", thread.join().unwrap());
+        }
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        // a panicking computation hands over to the next caller.
+        let panicked = thread::spawn({
+            let cache = cache.clone();
+            move || cache.get_or_insert_with(&2, |_| -> String { panic!("failed") })
+        }).join();
+        assert!(panicked.is_err());
+        assert_eq!("two", cache.get_or_insert_with(&2, |_| String::from("two")));
+    }
+
+    #[test]
+    fn load_many() {
+        use std::sync::{Arc,Mutex};
+
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let seen = batches.clone();
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_batch_loader(move |keys: &[&u64]| {
+                seen.lock().unwrap().push(keys.iter().map(|&&key| key).collect::<Vec<_>>());
+                keys.iter().map(|key| format!("loaded {}", key)).collect()
+            });
+        cache.insert(2, String::from("two"));
+        let vals: Vec<Option<String>> = cache.load_many(&[3, 2, 1, 3]).into_iter()
+            .map(|val| val.cloned())
+            .collect();
+        assert_eq!(vec![Some(String::from("loaded 3")), Some(String::from("two")),
+                        Some(String::from("loaded 1")), Some(String::from("loaded 3"))],
+                   vals);
+        // the misses were loaded in one call, each once.
+        assert_eq!(vec![vec![1, 3]], *batches.lock().unwrap());
+        assert_eq!(vec![None, Some(&String::from("two"))], cache.get_many(&[4, 2]));
+    }
+
+    #[test]
+    fn hash_storage() {
+        use std::collections::HashMap;
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.insert(1, String::from("one"));
+        let mut cache = cache.with_storage(HashMap::new());
+        assert_eq!(Some(&String::from("one")), cache.get(&1));
+        cache.insert(2, String::from("two"));
+        cache.get(&1);
+        assert_eq!(Some(String::from("two")), cache.take());
+        assert!(!cache.contains_key(&2));
+        assert_eq!(Some((&1, 2, &String::from("one"))), cache.hottest());
+    }
+
+    #[test]
+    fn dense_storage() {
+        let mut cache: super::PoolCache<u32, String> = super::PoolCache::new(4)
+            .with_storage(super::DenseStorage::with_capacity(4));
+        cache.insert(7, String::from("seven"));
+        cache.insert(2, String::from("two"));
+        cache.insert(7, String::from("SEVEN"));
+        assert_eq!(Some(&String::from("SEVEN")), cache.get(&7));
+        assert_eq!(None, cache.get(&3));
+        assert_eq!(None, cache.get(&1000));
+        assert_eq!(vec![7, 2], cache.iter_by_heat().map(|(&key, _, _)| key).collect::<Vec<_>>());
+        assert_eq!(Some(String::from("two")), cache.take_evicting().map(|(_, val)| val));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn lru_adapter() {
+        use std::num::NonZeroUsize;
+
+        let cache = super::PoolCache::<u64, String>::with_policy(4, super::Policy::Bucketed);
+        let mut lru = super::LruAdapter::new(cache, NonZeroUsize::new(2).unwrap());
+        assert_eq!(None, lru.put(1, String::from("one")));
+        assert_eq!(None, lru.put(2, String::from("two")));
+        assert_eq!(Some(String::from("one")), lru.put(1, String::from("uno")));
+        lru.get(&1);
+        assert_eq!(Some(&String::from("two")), lru.peek(&2));
+
+        // past capacity, the least used entry makes room.
+        assert_eq!(Some((2, String::from("two"))), lru.push(3, String::from("three")));
+        assert_eq!((2, false), (lru.len(), lru.contains(&2)));
+        lru.get_mut(&3).unwrap().push('!');
+        assert_eq!(Some(String::from("three!")), lru.pop(&3));
+
+        lru.put(4, String::from("four"));
+        lru.put(5, String::from("five"));
+        lru.resize(NonZeroUsize::new(1).unwrap());
+        assert_eq!((1, 1), (lru.len(), lru.cap().get()));
+        assert!(lru.pop_lru().is_some());
+        assert!(lru.is_empty());
+    }
+
+    #[test]
+    fn prefix() {
+        let mut cache: super::PoolCache<String, u64> = super::PoolCache::new(4);
+        for (idx, key) in ["a/b", "a/b/c", "a/b/d", "a/bc", "b"].iter().enumerate() {
+            cache.insert(key.to_string(), idx as u64);
+        }
+        let prefix = String::from("a/b/");
+        assert_eq!(vec![(&String::from("a/b/c"), &1), (&String::from("a/b/d"), &2)],
+                   cache.iter_prefix(&prefix).collect::<Vec<_>>());
+        assert_eq!(4, cache.iter_prefix(&String::from("a/")).count());
+
+        assert_eq!(vec![(String::from("a/b/c"), 1), (String::from("a/b/d"), 2)],
+                   cache.remove_prefix(&prefix));
+        assert_eq!(2, cache.invalidate_prefix(&String::from("a/b")));
+        assert_eq!(0, cache.invalidate_prefix(&String::from("a/b")));
+        let all = String::new();
+        let keys: Vec<&String> = cache.iter_prefix(&all).map(|(key, _)| key).collect();
+        assert_eq!(vec!["b"], keys);
+        assert_eq!(2, cache.pool_len());
+    }
+
+    #[test]
+    fn compact_pool_cache() {
+        let mut cache: super::CompactPoolCache<u64, String, 2> = super::CompactPoolCache::new();
+        cache.insert(1, String::from("one"));
+        cache.insert(2, String::from("two"));
+        cache.insert(3, String::from("three"));
+        for _ in 0..5 {
+            cache.get(&1);
+        }
+        cache.get(&3);
+
+        // heat saturates at 2, so one sweep cools the first key enough.
+        assert_eq!(Some(String::from("two")), cache.take());
+        assert_eq!(Some(String::from("one")), cache.take());
+        cache.insert(4, String::from("four"));
+        cache.insert(3, String::from("THREE"));
+        assert_eq!((2, 1), (cache.len(), cache.pool_len()));
+        assert_eq!(Some(String::from("three")), cache.take());
+        assert_eq!(Some(&String::from("THREE")), cache.get(&3));
+        assert!(!cache.contains_key(&1));
+    }
+
+    #[test]
+    fn static_pool_cache() {
+        let mut cache: super::StaticPoolCache<u64, String, 3> = super::StaticPoolCache::new(4);
+        assert_eq!(Ok(None), cache.insert(1, String::from("one")));
+        assert_eq!(Ok(None), cache.insert(2, String::from("two")));
+        assert_eq!(Ok(()), cache.put(String::from("free")));
+        assert_eq!(Err(String::from("more")), cache.put(String::from("more")));
+        assert_eq!(Err((3, String::from("three"))), cache.insert(3, String::from("three")));
+        assert_eq!(Ok(Some(String::from("one"))), cache.insert(1, String::from("uno")));
+        cache.get(&1);
+
+        // unkeyed objects go first, then the coldest entry.
+        assert_eq!(Some(String::from("free")), cache.take());
+        assert_eq!(Some(String::from("two")), cache.take());
+        assert_eq!((1, 0, 3), (cache.len(), cache.pool_len(), cache.capacity()));
+        assert_eq!(Some(&String::from("uno")), cache.get(&1));
+        assert_eq!(Some(String::from("uno")), cache.take());
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn pool_capacity() {
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::new(5).with_pool_capacity(100);
+        assert!(cache.freelist.capacity() >= 100);
+        assert!(cache.clock.capacity() >= 100);
+        let freelist = cache.freelist.as_slices().0.as_ptr();
+        for key in 0..100 {
+            cache.put(key.to_string());
+        }
+        assert_eq!(freelist, cache.freelist.as_slices().0.as_ptr());
+    }
+
+    #[test]
+    fn byte_budget() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_weigher(|val: &String| val.len())
+            .with_byte_budget(10);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.put(String::from("free"));
+        assert_eq!(10, cache.weight());
+
+        // over budget: the free object goes before any keyed entry.
+        cache.insert(3, String::from("baz"));
+        assert_eq!(9, cache.weight());
+        assert!(cache.contains_key(&1));
+        assert_eq!(Some(String::from("foo")), cache.take());
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_weigher(|val: &String| val.len())
+            .with_byte_budget(10)
+            .with_reclaim_order(super::Reclaim::KeyedFirst);
+        cache.insert(1, String::from("foo"));
+        cache.put(String::from("free"));
+        cache.insert(2, String::from("barn"));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(Some(String::from("free")), cache.take());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_cache() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let cache = super::AsyncPoolCache::new(super::PoolCache::new(1000));
+            for key in 0..100 {
+                cache.insert(key, key.to_string()).await;
+            }
+            for key in 0..99 {
+                for _ in 0..100 {
+                    cache.touch(&key).await;
+                }
+            }
+
+            // finding the cold entry takes several scans, yielding between
+            // them.
+            assert_eq!(Some(String::from("99")), cache.take().await);
+            assert_eq!(Some(3), cache.with(&1, |val| val.len() + 2).await);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_take_nothing() {
+        use std::time::Duration;
+        use super::{PoolCache,TakeOrder};
+
+        // more entries than one bounded search inspects, none of which can
+        // be evicted: async `take` returns `None` rather than retrying
+        // forever.
+        let setups: Vec<PoolCache<u64, String>> = vec![
+            PoolCache::new(4).with_heat_floor(1),
+            PoolCache::new(4).with_eviction_filter(|_, _| false),
+            PoolCache::new(4),
+            PoolCache::new(4).with_take_order(TakeOrder::FreelistOnly)];
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        for (idx, mut cache) in setups.into_iter().enumerate() {
+            for key in 0..100 {
+                cache.insert(key, key.to_string());
+                cache.get(&key);
+                if idx == 2 {
+                    cache.protect(key);
+                }
+            }
+            let cache = super::AsyncPoolCache::new(cache);
+            runtime.block_on(async {
+                let taken = tokio::time::timeout(Duration::from_secs(2), cache.take()).await;
+                assert_eq!(Ok(None), taken, "setup {}", idx);
+            });
+        }
+    }
+
+    #[cfg(feature = "cached")]
+    #[test]
+    fn memo() {
+        use cached::Cached;
+
+        let cache = super::PoolCache::<String, u64>::new(4).with_max_objects(2);
+        let mut memo = super::MemoPoolCache::new(cache);
+        assert_eq!(None, memo.cache_set(String::from("a"), 1));
+        assert_eq!(Some(1), memo.cache_set(String::from("a"), 2));
+        // keys are looked up by their borrowed form.
+        assert_eq!(Some(&2), memo.cache_get("a"));
+        assert_eq!(None, memo.cache_get("b"));
+        *memo.cache_get_or_set_with(String::from("b"), || 3) += 1;
+        assert_eq!(Some(&4), memo.cache_get("b"));
+        assert_eq!(Some(2), memo.cache_hits());
+        assert_eq!(Some(2), memo.cache_misses());
+        assert_eq!(Some(2), memo.cache_capacity());
+
+        // a third key evicts one of the first two.
+        memo.cache_set(String::from("c"), 5);
+        assert_eq!(2, memo.cache_size());
+        assert_eq!(Some(5), memo.cache_remove("c"));
+        assert_eq!(None, memo.cache_remove("c"));
+
+        // clearing keeps the values for reuse.
+        memo.cache_clear();
+        assert_eq!(0, memo.cache_size());
+        assert_eq!(None, memo.cache_get("a"));
+        assert!(memo.take().is_some());
+        memo.cache_set(String::from("d"), 6);
+        memo.cache_reset();
+        assert_eq!((0, None), (memo.cache_size(), memo.take()));
+        assert_eq!(None, memo.cache_set_lifespan(60));
+        assert_eq!(Some(60), memo.cache_unset_lifespan());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn buffers() {
+        use bytes::{BufMut,BytesMut};
+
+        let mut cache: super::PoolCache<u64, BytesMut> = super::PoolCache::new(4);
+        let mut buf = cache.take_with_capacity(100);
+        assert!(buf.capacity() >= 100);
+        buf.put_slice(b"hello");
+        let ptr = buf.as_ptr();
+        cache.put_buffer(buf);
+        cache.put_buffer(BytesMut::with_capacity(1000));
+
+        // the smallest buffer that fits is reused, cleared.
+        let buf = cache.take_with_capacity(50);
+        assert_eq!(ptr, buf.as_ptr());
+        assert!(buf.is_empty());
+        // buffers much larger than asked for are left for larger requests.
+        assert_eq!(16, cache.take_with_capacity(16).capacity());
+        assert!(cache.take_with_capacity(500).capacity() >= 1000);
+
+        // evicted values are cleared and grown.
+        cache.insert(1, BytesMut::from(&b"cached"[..]));
+        let buf = cache.take_with_capacity(64);
+        assert!(buf.is_empty() && buf.capacity() >= 64);
+        assert!(!cache.contains_key(&1));
+    }
+
+    #[cfg(all(feature = "tower", feature = "tokio"))]
+    #[test]
+    fn cache_layer() {
+        use std::future;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use tower_layer::Layer;
+        use tower_service::Service;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let layer = super::CacheLayer::new(super::PoolCache::<u64, Vec<u8>>::new(4), 2,
+                                           |req: &u64| if *req == 0 { None } else { Some(*req) });
+        let mut service = layer.layer(tower_service_fn(move |req: u64| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            future::ready(Ok::<_, ()>(vec![req as u8]))
+        }));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            assert_eq!(Ok(vec![1]), service.call(1).await);
+            assert_eq!(Ok(vec![1]), service.call(1).await);
+            assert_eq!(1, calls.load(Ordering::SeqCst));
+            // uncached requests always reach the inner service.
+            service.call(0).await.unwrap();
+            service.call(0).await.unwrap();
+            assert_eq!(3, calls.load(Ordering::SeqCst));
+
+            // a third response evicts one of the first two, for reuse.
+            service.call(2).await.unwrap();
+            service.call(3).await.unwrap();
+            assert_eq!(2, layer.with_cache(|cache| cache.live_entries().count()));
+            let spare = layer.take_spare().unwrap();
+            assert!(spare == vec![1] || spare == vec![2]);
+        });
+    }
+
+    // A service answering requests with `f`.
+    #[cfg(all(feature = "tower", feature = "tokio"))]
+    fn tower_service_fn<F, Fut>(f: F) -> impl tower_service::Service<u64, Response = Vec<u8>,
+                                                                      Error = (), Future = Fut>
+        where F: FnMut(u64) -> Fut,
+              Fut: std::future::Future<Output = Result<Vec<u8>, ()>> {
+        struct ServiceFn<F>(F);
+        impl<F, Fut> tower_service::Service<u64> for ServiceFn<F>
+            where F: FnMut(u64) -> Fut,
+                  Fut: std::future::Future<Output = Result<Vec<u8>, ()>> {
+                type Response = Vec<u8>;
+                type Error = ();
+                type Future = Fut;
+
+                fn poll_ready(&mut self, _: &mut std::task::Context<'_>)
+                              -> std::task::Poll<Result<(), ()>> {
+                    std::task::Poll::Ready(Ok(()))
+                }
+
+                fn call(&mut self, req: u64) -> Fut {
+                    (self.0)(req)
+                }
+        }
+        ServiceFn(f)
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn batch_loader() {
+        use std::sync::{Arc,Mutex};
+        use std::time::Duration;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let batches = Arc::new(Mutex::new(Vec::new()));
+            let seen = batches.clone();
+            let cache = Arc::new(super::AsyncPoolCache::new(super::PoolCache::<u64, String>::new(5))
+                .with_batch_loader(Duration::from_millis(10), 100, move |keys: Vec<u64>| {
+                    seen.lock().unwrap().push(keys.clone());
+                    async move { keys.iter().map(|key| key.to_string()).collect() }
+                }));
+            cache.insert(9, String::from("nine")).await;
+
+            // misses within the window are loaded together.
+            let loads: Vec<_> = [3, 1, 3, 9].iter().map(|&key| {
+                let cache = cache.clone();
+                tokio::spawn(async move { cache.load(key).await })
+            }).collect();
+            let mut vals = Vec::new();
+            for load in loads {
+                vals.push(load.await.unwrap());
+            }
+            assert_eq!(vec![Some(String::from("3")), Some(String::from("1")),
+                            Some(String::from("3")), Some(String::from("nine"))], vals);
+            assert_eq!(vec![vec![1, 3]], *batches.lock().unwrap());
+            assert_eq!(Some(String::from("1")), cache.get_cloned(&1).await);
+
+            let plain = super::AsyncPoolCache::new(super::PoolCache::<u64, String>::new(5));
+            assert_eq!(None, plain.load(1).await);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn evictions() {
+        use std::future;
+        use std::pin::Pin;
+
+        use futures_core::Stream;
+        use super::RemovalCause;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let cache = super::AsyncPoolCache::new(super::PoolCache::new(5));
+            let mut evictions = cache.evictions().await;
+            cache.insert(1, String::from("foo")).await;
+            cache.insert(1, String::from("bar")).await;
+            cache.insert(2, String::from("baz")).await;
+            cache.take().await;
+            cache.take().await;
+            for cause in [RemovalCause::Replaced, RemovalCause::Evicted].iter() {
+                let next = future::poll_fn(|cx| Pin::new(&mut evictions).poll_next(cx));
+                assert_eq!(Some((1, *cause)), next.await);
+            }
+        });
+    }
+
+    #[cfg(feature = "crossbeam-epoch")]
+    #[test]
+    fn epoch() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let cache = Arc::new(super::EpochPoolCache::new(super::PoolCache::new(5)));
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+
+        // readers on other threads see the published entries, and their
+        // uses are counted.
+        let readers: Vec<_> = (0..4).map(|_| {
+            let cache = cache.clone();
+            thread::spawn(move || cache.get(&1).unwrap())
+        }).collect();
+        for reader in readers {
+            assert_eq!("foo", *reader.join().unwrap());
+        }
+        assert_eq!(Some(String::from("bar")), cache.take());
+
+        // a value still held by a reader isn't reused.
+        let held = cache.peek(&1).unwrap();
+        assert_eq!(None, cache.take());
+        assert!(!cache.contains_key(&1));
+        assert_eq!("foo", *held);
+    }
+
+    #[cfg(feature = "crossbeam-epoch")]
+    #[test]
+    fn epoch_live() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let mut inner: super::SharedPoolCache<u64, u64> = super::PoolCache::new(5);
+        inner.insert_with_ttl(1, Arc::new(10), Duration::from_millis(20));
+        inner.insert(2, Arc::new(20));
+        inner.insert(3, Arc::new(30));
+        inner.invalidate_entries_if(|key, _| *key == 3);
+        let cache = super::EpochPoolCache::new(inner);
+
+        // readers don't see invalidated entries, nor entries that expire
+        // after the view was published.
+        assert!(cache.contains_key(&1) && cache.contains_key(&2));
+        assert_eq!(None, cache.get(&3));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(None, cache.get(&1));
+        assert_eq!(Some(Arc::new(20)), cache.get(&2));
+    }
+
+    #[test]
+    fn hit_n() {
+        use super::{PoolCache,Policy};
+
+        // many uses at once are counted up to the cap, like one at a time.
+        let mut cache: PoolCache<u64, u64> = PoolCache::new(4);
+        cache.insert(1, 10);
+        cache.hit_n(cache.cache.get(&1).unwrap(), 1_000_000_000);
+        assert_eq!(Some((&1, 4, &10)), cache.hottest());
+
+        // but however many periods they span, counts are halved once.
+        let mut cache: PoolCache<u64, u64> = PoolCache::with_policy(0, Policy::Lfu{period: 2});
+        cache.insert(1, 10);
+        let heat = cache.hottest().unwrap().1;
+        cache.hit_n(cache.cache.get(&1).unwrap(), 7);
+        assert_eq!(Some((&1, (heat + 7) / 2, &10)), cache.hottest());
+        assert_eq!(7, cache.stats().hits);
+    }
+
+    #[cfg(feature = "thread_local")]
+    #[test]
+    fn local() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let cache = Arc::new(super::LocalPoolCache::new(super::PoolCache::<u64, Vec<u8>>::new(5), 4));
+
+        // a thread's own objects come back to it, without touching the
+        // shared cache.
+        cache.put(vec![1]);
+        assert_eq!(Some(vec![1]), cache.take());
+
+        // objects beyond the local capacity spill over, and other threads
+        // can pick them up from the shared cache.
+        for val in 0..6 {
+            cache.put(vec![val]);
+        }
+        let other = cache.clone();
+        let taken = thread::spawn(move || {
+            (0..3).map(|_| other.take()).filter(Option::is_some).count()
+        }).join().unwrap();
+        assert_eq!(3, taken);
+        cache.shared().insert(1, vec![]);
+        assert!(cache.shared().contains_key(&1));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_iter() {
+        use rayon::prelude::*;
+
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(5);
+        for key in 0..100 {
+            cache.insert(key, key * 2);
+        }
+        assert_eq!(9900, cache.par_iter().map(|(_, val)| *val).sum::<u64>());
+
+        // iteration is heat-neutral: entries are still evicted in order.
+        assert_eq!(Some(0), cache.take());
+
+        // invalidated entries aren't visited.
+        cache.invalidate_all();
+        cache.insert(100, 1);
+        assert_eq!(vec![(&100, &1)], cache.par_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sync() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let cache = Arc::new(super::SyncPoolCache::new(super::PoolCache::new(5)));
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+
+        // readers on other threads share the read lock.
+        let readers: Vec<_> = (0..4).map(|_| {
+            let cache = cache.clone();
+            thread::spawn(move || cache.with(&2, |val| val.len()))
+        }).collect();
+        for reader in readers {
+            assert_eq!(Some(3), reader.join().unwrap());
+        }
+
+        // their uses were counted, so '1' is evicted first.
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(Some(String::from("bar")), cache.get_cloned(&2));
+    }
+
+    #[test]
+    fn take_timeout() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let cache = Arc::new(super::SyncPoolCache::new(super::PoolCache::<u64, String>::new(5)));
+
+        // nothing arrives in time.
+        assert_eq!(None, cache.take_timeout(Duration::from_millis(10)));
+
+        // an object put by another thread wakes the waiter.
+        let other = cache.clone();
+        let putter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            other.put(String::from("foo"));
+        });
+        assert_eq!(Some(String::from("foo")), cache.take_timeout(Duration::from_secs(10)));
+        putter.join().unwrap();
+    }
+
+    #[test]
+    fn outstanding() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let warned = Arc::new(AtomicUsize::new(0));
+        let counter = warned.clone();
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_outstanding_warning(1, move |count| counter.store(count, Ordering::Relaxed));
+        cache.put(String::from("foo"));
+        cache.put(String::from("bar"));
+
+        let foo = cache.take_pooled().unwrap();
+        assert_eq!("foo", *foo);
+        assert_eq!(0, warned.load(Ordering::Relaxed));
+        let bar = cache.take_pooled().unwrap();
+        assert_eq!(2, cache.outstanding());
+        assert_eq!(2, warned.load(Ordering::Relaxed));
+
+        // handing objects back settles the count.
+        cache.insert_pooled(1, foo);
+        cache.put_pooled(bar);
+        assert_eq!(0, cache.outstanding());
+    }
+
+    #[test]
+    fn max_objects() {
+        let mut cache: super::PoolCache<u64, Vec<u8>> = super::PoolCache::new(5).with_max_objects(2);
+        cache.insert(1, vec![1]);
+
+        // existing objects are used first, then new ones are created up
+        // to the limit.
+        let cached = cache.take_or_create(Vec::new).unwrap();
+        assert_eq!(vec![1], *cached);
+        let new = cache.take_or_create(Vec::new).unwrap();
+        assert!(new.is_empty());
+        assert!(cache.take_or_create(Vec::new).is_none());
+
+        // objects handed back can be taken again.
+        cache.put_pooled(new);
+        assert!(cache.take_or_create(Vec::new).is_some());
+    }
+
+    #[cfg(feature = "debug-leaks")]
+    #[test]
+    fn leaks() {
+        use std::sync::{Arc,Mutex};
+        use std::time::Duration;
+
+        let leaks = Arc::new(Mutex::new(Vec::new()));
+        let reported = leaks.clone();
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_leak_reporter(move |leak| reported.lock().unwrap().push(leak.origin.line()));
+        cache.put(String::from("foo"));
+        cache.put(String::from("bar"));
+
+        let line = line!() + 1;
+        let foo = cache.take_pooled().unwrap();
+        let bar = cache.take_pooled().unwrap();
+        assert_eq!(vec![line, line + 1],
+                   cache.overdue(Duration::from_secs(0)).iter().map(|leak| leak.origin.line())
+                       .collect::<Vec<_>>());
+
+        // handing back isn't a leak, dropping is.
+        cache.put_pooled(bar);
+        drop(foo);
+        assert_eq!(vec![line], *leaks.lock().unwrap());
+        assert!(cache.overdue(Duration::from_secs(0)).is_empty());
+    }
+
+    #[test]
+    fn touch() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        assert!(!cache.touch(&1));
+
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        assert!(cache.touch(&1));
+
+        // '1' was touched, so '2' is evicted first.
+        assert_eq!(Some(String::from("bar")), cache.take());
+    }
+
+    #[test]
+    fn get_key_value() {
+        let mut cache: super::PoolCache<String, u64> = super::PoolCache::new(5);
+        let key = String::from("foo");
+        let stored = key.as_ptr();
+        cache.insert(key, 1);
+
+        // the key returned is the one the cache holds, not the one looked up.
+        let (key, val) = cache.get_key_value(&String::from("foo")).unwrap();
+        assert_eq!(("foo", 1), (key.as_str(), *val));
+        assert_eq!(stored, key.as_ptr());
+        assert_eq!(None, cache.get_key_value(&String::from("bar")));
+    }
+
+    #[test]
+    fn hottest() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        assert_eq!(None, cache.hottest());
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.insert(3, String::from("baz"));
+        cache.get(&2);
+        cache.get(&2);
+        cache.get(&3);
+
+        assert_eq!(Some((&2, 3, &String::from("bar"))), cache.hottest());
+        assert_eq!(Some((&1, 1, &String::from("foo"))), cache.coldest());
+
+        // ties go to the smallest key.
+        cache.get(&1);
+        assert_eq!(Some((&1, 2, &String::from("foo"))), cache.coldest());
+
+        let keys: Vec<&u64> = cache.iter_by_heat().map(|(key, _, _)| key).collect();
+        assert_eq!(vec![&2, &1, &3], keys);
+
+        // invalidated entries aren't considered.
+        cache.invalidate_all();
+        assert_eq!(None, cache.hottest());
+        cache.insert(4, String::from("qux"));
+        assert_eq!(Some((&4, 1, &String::from("qux"))), cache.coldest());
+        assert_eq!(1, cache.iter_by_heat().count());
+    }
+
+    #[test]
+    fn take_bounded() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        for _ in 0..3 {
+            cache.get(&1);
+            cache.get(&2);
+        }
+
+        // both entries need several passes of the clock to cool down.
+        assert_eq!(None, cache.take_bounded(4));
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+
+        // the heat drained so far isn't restored.
+        assert_eq!(Some(String::from("foo")), cache.take_bounded(3));
+    }
+
+    #[test]
+    fn meta() {
+        let mut cache: super::PoolCache<u64, String, &str> =
+            super::PoolCache::with_metadata(5, super::Policy::Clock);
+        cache.insert_with_meta(1, String::from("foo"), "disk");
+        cache.insert(2, String::from("bar"));
+        assert_eq!(Some((&String::from("foo"), Some(&"disk"))), cache.get_with_meta(&1));
+        assert_eq!(None, cache.meta(&2));
+
+        *cache.meta_mut(&2).unwrap() = Some("network");
+        assert_eq!(Some(&"network"), cache.meta(&2));
+
+        // metadata stays with the entry, not the value.
+        cache.insert(1, String::from("baz"));
+        assert_eq!(None, cache.meta(&1));
+        assert_eq!(None, cache.meta_mut(&3));
+    }
+
+    #[test]
+    fn take_or_create_held() {
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(4).with_max_objects(1);
+        cache.insert(1, 1);
+        cache.protect(1);
+
+        // the protected entry can't be taken, but counts towards the limit.
+        assert!(cache.take_or_create(|| 99).is_none());
+        cache.unprotect(&1);
+        assert_eq!(Some(1), cache.take_or_create(|| 99).map(|val| val.detach()));
+    }
+
+    #[test]
+    fn checkout() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.insert(1, String::from("conn"));
+        cache.insert(2, String::from("other"));
+        for _ in 0..3 {
+            cache.get(&1);
+        }
+
+        let conn = cache.checkout(&1).unwrap();
+        assert!(!cache.contains_key(&1));
+        assert!(cache.is_checked_out(&1));
+        cache.checkin(&1, conn);
+        assert!(!cache.is_checked_out(&1));
+        cache.insert(3, String::from("three"));
+
+        // the returned entry is as hot as before, so the others go first.
+        assert_eq!(Some(String::from("other")), cache.take());
+        assert_eq!(Some(String::from("three")), cache.take());
+        assert_eq!(None, cache.checkout(&2));
+        cache.checkin(&2, String::from("new"));
+        assert!(cache.contains_key(&2));
+        assert_eq!(Some(String::from("new")), cache.take());
+    }
+
+    #[test]
+    fn checkin_policies() {
+        use super::{PoolCache,Policy};
+
+        // checking an entry out and in is like any other use of it, even
+        // where the policy treats new entries differently.
+        let policies = vec![Policy::Clock, Policy::Lfu{period: 100}, Policy::ClockPro,
+                            Policy::TwoQ, Policy::Bucketed, Policy::LazyClock, Policy::Gdsf];
+        for policy in policies {
+            let mut caches: Vec<PoolCache<u64, u64>> =
+                (0..2).map(|_| PoolCache::with_policy(4, policy)).collect();
+            for cache in caches.iter_mut() {
+                // once evicted, a re-inserted key goes to the hot queue.
+                cache.insert(1, 10);
+                cache.take();
+                cache.insert(1, 10);
+                for _ in 0..3 {
+                    cache.get(&1);
+                }
+                cache.insert(2, 20);
+                cache.insert(3, 30);
+            }
+            let val = caches[0].checkout(&1).unwrap();
+            caches[0].checkin(&1, val);
+            caches[1].get(&1);
+            for cache in caches.iter_mut() {
+                cache.insert(4, 40);
+            }
+            let taken: Vec<_> = (0..4).map(|_| caches[0].take()).collect();
+            let expected: Vec<_> = (0..4).map(|_| caches[1].take()).collect();
+            assert_eq!(expected, taken, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn stacked() {
+        let mut cache: super::PoolCache<&str, String> = super::PoolCache::new(5);
+        cache.put_for(&"db", String::from("conn1"));
+        cache.put_for(&"db", String::from("conn2"));
+        cache.put(String::from("spare"));
+        assert_eq!(2, cache.stack_len(&"db"));
+
+        assert_eq!(Some(String::from("conn2")), cache.take_for(&"db"));
+        assert_eq!(Some(String::from("conn1")), cache.take_for(&"db"));
+        assert!(!cache.contains_key(&"db"));
+        // with nothing under the key, fall back to `take`.
+        assert_eq!(Some(String::from("spare")), cache.take_for(&"db"));
+
+        // evicting the entry leaves the rest of its stack to the pool.
+        cache.put_for(&"db", String::from("conn1"));
+        cache.put_for(&"db", String::from("conn2"));
+        let mut taken = vec![cache.take().unwrap(), cache.take().unwrap()];
+        taken.sort();
+        assert_eq!(vec![String::from("conn1"), String::from("conn2")], taken);
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn tagged() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.put_tagged("tls", String::from("foo"));
+        cache.put(String::from("bar"));
+        cache.insert(1, String::from("baz"));
+        assert_eq!(1, cache.tagged_len("tls"));
+
+        // tagged objects are only handed out for their own tag.
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("foo")), cache.take_tagged("tls"));
+
+        // with none left under the tag, fall back to `take`.
+        assert_eq!(Some(String::from("baz")), cache.take_tagged("tls"));
+        cache.put_tagged("plain", String::from("qux"));
+        assert_eq!(None, cache.take());
+        assert_eq!(0, cache.tagged_len("tls"));
+    }
+
+    #[test]
+    fn namespaces() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert_in("a", 1, String::from("foo"));
+        cache.insert_in("a", 2, String::from("bar"));
+        cache.insert_in("b", 3, String::from("baz"));
+        cache.insert(4, String::from("qux"));
+        cache.get(&4);
+        assert_eq!(2, cache.namespace_len("a"));
+
+        // re-inserting a key moves it out of its namespace.
+        cache.insert(2, String::from("bar"));
+        assert_eq!(1, cache.clear_namespace("a"));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(0, cache.namespace_len("a"));
+
+        // the replaced and cleared values are free, and the other entries
+        // keep their heat.
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("baz")), cache.take());
+        assert!(cache.contains_key(&4));
+        assert_eq!(0, cache.namespace_len("b"));
+    }
+
+    #[test]
+    fn quotas() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.set_quota("a", super::Quota{max_entries: 2, ..Default::default()});
+        cache.insert(1, String::from("foo"));
+        cache.insert_in("a", 2, String::from("bar"));
+        cache.insert_in("a", 3, String::from("baz"));
+        cache.get(&2);
+        cache.get(&3);
+        cache.get(&3);
+
+        // "a" is at its quota, so it gives up its own coldest entry, even
+        // though '1' is colder.
+        assert_eq!(Some(String::from("bar")), cache.take_in("a"));
+        assert!(cache.contains_key(&1));
+
+        // below its quota, it takes like anyone else.
+        assert_eq!(Some(String::from("foo")), cache.take_in("a"));
+
+        // quotas can also limit the weight of a namespace.
+        cache.set_quota("a", super::Quota{max_weight: 1, ..Default::default()});
+        assert_eq!(Some(String::from("baz")), cache.take_in("a"));
+        assert_eq!(None, cache.take_in("a"));
+    }
+
+    #[test]
+    fn ttl() {
+        use std::time::{Duration,Instant};
+
+        let mut cache: super::PoolCache<u64, String> =
+            super::PoolCache::new(5).with_ttl(Duration::from_secs(3600));
+        cache.insert(1, String::from("foo"));
+        cache.insert_with_ttl(2, String::from("bar"), Duration::from_secs(0));
+        cache.get(&1);
+
+        // an expired entry can't be read, and is cooled down when that's
+        // attempted.
+        assert_eq!(None, cache.get(&2));
+        assert!(!cache.contains_key(&2));
+        assert_eq!("foo", cache.get(&1).unwrap());
+        assert_eq!(Some(String::from("bar")), cache.take());
+
+        // re-inserting a key replaces its expiry.
+        cache.insert_with_ttl(1, String::from("baz"), Duration::from_secs(0));
+        assert_eq!(None, cache.get(&1));
+
+        let now = Instant::now();
+        cache.insert_expiring_at(3, String::from("qux"), now);
+        cache.insert_expiring_at(4, String::from("quux"), now + Duration::from_secs(3600));
+        assert!(!cache.contains_key(&3));
+        assert!(cache.contains_key(&4));
+
+        // expiries can be pushed back, but expired entries can't be revived.
+        assert!(cache.extend_ttl(&4, Duration::from_secs(3600)));
+        assert!(cache.refresh_expiry(&4));
+        assert!(cache.contains_key(&4));
+        assert!(!cache.extend_ttl(&3, Duration::from_secs(3600)));
+        assert!(!cache.refresh_expiry(&3));
+    }
+
+    #[test]
+    fn invalidate_all() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, String::from("one"));
+        cache.insert(2, String::from("two"));
+        cache.insert(3, String::from("three"));
+        cache.invalidate_all();
+        assert_eq!(None, cache.get(&1));
+        assert!(!cache.contains_key(&2));
+
+        // looking an invalidated entry up cools it, so it goes first.
+        assert_eq!(Some(String::from("one")), cache.take());
+        // re-inserted entries are valid again.
+        cache.insert(2, String::from("new"));
+        assert_eq!(Some(&String::from("new")), cache.get(&2));
+        assert_eq!(1, cache.purge_invalidated());
+        assert_eq!(1, cache.cache.len());
+        let mut taken: Vec<String> = (0..3).map(|_| cache.take().unwrap()).collect();
+        taken.sort();
+        assert_eq!(vec![String::from("new"), String::from("three"), String::from("two")], taken);
+        assert_eq!(None, cache.take());
+    }
+
+    #[test]
+    fn get_or_stale_invalidated() {
+        let mut cache: super::PoolCache<u64, u64> = super::PoolCache::new(4);
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+        cache.insert(3, 30);
+        cache.invalidate_all();
+        cache.insert(2, 21);
+        cache.insert(3, 31);
+        cache.invalidate_entries_if(|key, _| *key == 3);
+
+        assert_eq!(None, cache.get_or_stale(&1));
+        assert_eq!(Some((&21, super::Freshness::Fresh)), cache.get_or_stale(&2));
+        assert_eq!(None, cache.get_or_stale(&3));
+    }
+
+    #[test]
+    fn invalidate_entries_if() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        for key in 0..4 {
+            cache.insert(key, key.to_string());
+        }
+        cache.invalidate_entries_if(|key, _| key % 2 == 0);
+        assert_eq!(None, cache.get(&0));
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+
+        // entries inserted since aren't affected.
+        cache.insert(2, String::from("new"));
+        assert!(cache.contains_key(&2));
+        assert_eq!(1, cache.purge_invalidated());
+        assert_eq!(3, cache.cache.len());
+        cache.insert(4, String::from("four"));
+        assert!(cache.contains_key(&4));
+    }
+
+    #[test]
+    fn invalidate_tag() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert_with_tags(1, String::from("profile"), &["user:42"]);
+        cache.insert_with_tags(2, String::from("feed"), &["user:42", "user:7"]);
+        cache.insert_with_tags(3, String::from("other"), &["user:7"]);
+
+        assert_eq!(2, cache.invalidate_tag("user:42"));
+        assert!(!cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        // entries leave every tag they were under.
+        assert_eq!(1, cache.invalidate_tag("user:7"));
+        assert_eq!(0, cache.invalidate_tag("user:42"));
+        assert_eq!(3, cache.pool_len());
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut cache: super::PoolCache<&str, String> = super::PoolCache::new(5);
+        cache.insert("header", String::from("<h1>"));
+        cache.insert_with_deps("page", String::from("<h1>page"), &["header"]);
+        cache.insert_with_deps("site", String::from("<h1>page..."), &["page", "footer"]);
+        cache.insert("other", String::from("other"));
+
+        // replacing a dependency invalidates its dependents, transitively.
+        cache.insert("header", String::from("<h2>"));
+        assert!(!cache.contains_key(&"page"));
+        assert!(!cache.contains_key(&"site"));
+        assert!(cache.contains_key(&"other"));
+
+        // dependencies need not be cached.
+        cache.insert_with_deps("site", String::from("site"), &["footer"]);
+        assert!(!cache.invalidate_key(&"footer"));
+        assert!(!cache.contains_key(&"site"));
+        // an entry inserted again without them no longer depends on them.
+        cache.insert("site", String::from("site"));
+        cache.swap(&"footer", String::from("footer"));
+        cache.swap(&"footer", String::from("new footer"));
+        assert!(cache.contains_key(&"site"));
+        assert!(cache.invalidate_key(&"footer"));
+        assert!(cache.contains_key(&"site"));
+    }
+
+    #[test]
+    fn drain_expired() {
+        use std::time::Duration;
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert_with_ttl(1, String::from("foo"), Duration::from_secs(0));
+        cache.insert_with_ttl(2, String::from("bar"), Duration::from_secs(3600));
+        cache.insert_with_ttl(3, String::from("baz"), Duration::from_secs(0));
+        cache.insert(4, String::from("qux"));
+
+        let expired: Vec<(u64, String)> = cache.drain_expired().collect();
+        assert_eq!(vec![(1, String::from("foo")), (3, String::from("baz"))], expired);
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert_eq!(Some(String::from("qux")), cache.take());
+        assert_eq!(None, cache.take());
+
+        // keys still held by a snapshot are copied.
+        cache.insert_with_ttl(5, String::from("frozen"), Duration::from_millis(20));
+        let frozen = cache.freeze();
+        std::thread::sleep(Duration::from_millis(50));
+        let expired: Vec<(u64, String)> = cache.drain_expired().collect();
+        assert_eq!(vec![(5, String::from("frozen"))], expired);
+        drop(frozen);
+    }
+
+    #[test]
+    fn intern() {
+        let mut cache: super::SharedPoolCache<String, String> =
+            super::PoolCache::with_policy(0, super::Policy::Lfu { period: 100 }).with_max_objects(2);
+        let intern = |cache: &mut super::SharedPoolCache<String, String>, name: &str| {
+            cache.get_or_intern(String::from(name), |name| name.to_uppercase())
+        };
+        let foo = intern(&mut cache, "foo");
+        assert!(super::Arc::ptr_eq(&foo, &intern(&mut cache, "foo")));
+        intern(&mut cache, "bar");
+
+        // the limit is reached, so the least used value makes room.
+        intern(&mut cache, "baz");
+        assert!(cache.contains_key(&String::from("foo")));
+        assert!(!cache.contains_key(&String::from("bar")));
+        assert_eq!("FOO", *foo);
+    }
+
+    #[test]
+    fn weak() {
+        let mut cache: super::WeakPoolCache<u64, String> =
+            super::WeakPoolCache::new(super::PoolCache::new(5));
+        let foo = cache.insert(1, String::from("foo"));
+        let bar = cache.insert(2, String::from("bar"));
+        assert_eq!("foo", *cache.get(&1).unwrap());
+
+        // entries still referenced by the application can't be taken.
+        assert_eq!(None, cache.take());
+
+        // once the last handle is dropped, the entry is gone, and its value
+        // can be reused.
+        drop(foo);
+        assert_eq!(None, cache.get(&1));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert!(cache.contains_key(&2));
+        assert_eq!("bar", *bar);
+    }
+
+    #[test]
+    fn shared() {
+        let mut cache: super::SharedPoolCache<u64, String> = super::PoolCache::new(5);
+        cache.insert(1, super::Arc::new(String::from("foo")));
+        cache.insert(2, super::Arc::new(String::from("bar")));
+
+        // hold on to '1' beyond the borrow of the cache, and make '2'
+        // the hotter entry.
+        let foo = cache.get_shared(&1).unwrap();
+        cache.get(&2);
+        cache.get(&2);
+
+        // '1' is colder, but still shared, so it is released and skipped.
+        assert_eq!(Some(String::from("bar")), cache.take_unique());
+        assert!(!cache.contains_key(&1));
+        assert_eq!("foo", *foo);
+        assert_eq!(None, cache.take_unique());
+    }
+
+    #[test]
+    fn unclonable_keys() {
+        #[derive(PartialEq, Eq, PartialOrd, Ord)]
+        struct Key(u64);
+
+        let mut cache: super::PoolCache<Key, String> = super::PoolCache::new(5);
+        cache.insert(Key(1), String::from("foo"));
+        cache.insert(Key(1), String::from("bar"));
+        assert_eq!("bar", cache.get(&Key(1)).unwrap());
+
+        // the replaced value is free, and taken first.
+        assert_eq!(Some(String::from("foo")), cache.take());
+        assert_eq!(Some(String::from("bar")), cache.take());
+        assert!(!cache.contains_key(&Key(1)));
+    }
+
+    #[test]
+    fn replay() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        cache.start_recording();
+        cache.put(String::from("foo"));
+        cache.insert(1, String::from("bar"));
+        cache.insert(2, String::from("baz"));
+        cache.get(&1);
+        cache.touch(&3);
+        assert!(cache.take().is_some());
+        assert!(cache.take().is_some());
+        let trace = cache.finish_recording().unwrap();
+        assert_eq!(&[super::Op::Put, super::Op::Insert(1), super::Op::Insert(2),
+                     super::Op::Get(1), super::Op::Get(3), super::Op::Take, super::Op::Take],
+                   trace.ops());
+
+        // replaying onto an empty cache ends in the same state: '2' was
+        // evicted and '1' remains.
+        let mut replayed: super::PoolCache<u64, String> = super::PoolCache::new(5);
+        replayed.replay(&trace, String::new);
+        assert!(!replayed.contains_key(&2));
+        assert!(replayed.contains_key(&1));
+        assert!(cache.contains_key(&1));
+        assert_eq!(None, cache.finish_recording());
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log() {
+        use std::sync::Mutex;
+        use std::thread::{self,ThreadId};
+
+        // collects the messages logged by each thread.
+        struct Capture(Mutex<Vec<(ThreadId, String)>>);
+
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                let message = format!("{} {}", record.level(), record.args());
+                self.0.lock().unwrap().push((thread::current().id(), message));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.insert(1, String::from("foo"));
+        cache.get(&1);
+        cache.take();
+
+        let me = thread::current().id();
+        let logged: Vec<String> = CAPTURE.0.lock().unwrap().iter()
+            .filter(|(thread, _)| *thread == me)
+            .map(|(_, message)| message.clone())
+            .collect();
+        assert_eq!(vec![
+            String::from("TRACE poolcache: inserted an entry, 1 entries"),
+            String::from("DEBUG poolcache: evicted an entry with heat 0 (Evicted)"),
+        ], logged);
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn entry_info() {
+        use std::sync::{Arc,Mutex};
+        use std::time::{Duration,Instant};
+
+        let start = Instant::now();
+        let now = Arc::new(Mutex::new(start));
+        let time = now.clone();
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_time_source(move || *time.lock().unwrap());
+        assert_eq!(None, cache.entry_info(&1));
+
+        cache.insert(1, String::from("foo"));
+        *now.lock().unwrap() += Duration::from_secs(5);
+        assert_eq!(Some(super::EntryInfo{created: start, last_accessed: start, heat: 1}),
+                   cache.entry_info(&1));
+        cache.get(&1);
+        let info = cache.entry_info(&1).unwrap();
+        assert_eq!(start, info.created);
+        assert_eq!(start + Duration::from_secs(5), info.last_accessed);
+        assert_eq!(2, info.heat);
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn iter_by_recency() {
+        use std::sync::{Arc,Mutex};
+        use std::time::{Duration,Instant};
+
+        let start = Instant::now();
+        let now = Arc::new(Mutex::new(start));
+        let time = now.clone();
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(5)
+            .with_time_source(move || *time.lock().unwrap());
+        cache.insert(1, String::from("one"));
+        cache.insert(2, String::from("two"));
+        cache.insert(3, String::from("three"));
+        *now.lock().unwrap() += Duration::from_secs(2);
+        cache.get(&2);
+        *now.lock().unwrap() += Duration::from_secs(2);
+        cache.get(&3);
+
+        let keys: Vec<u64> = cache.iter_by_recency().map(|(&key, _, _)| key).collect();
+        assert_eq!(vec![3, 2, 1], keys);
+        let recent: Vec<u64> = cache.iter_by_recency()
+            .take_while(|&(_, accessed, _)| accessed >= start + Duration::from_secs(1))
+            .map(|(&key, _, _)| key)
+            .collect();
+        assert_eq!(vec![3, 2], recent);
+
+        // invalidated entries aren't listed.
+        cache.invalidate_all();
+        cache.insert(4, String::from("four"));
+        let keys: Vec<u64> = cache.iter_by_recency().map(|(&key, _, _)| key).collect();
+        assert_eq!(vec![4], keys);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        struct Secret(Arc<AtomicUsize>);
+        impl zeroize::Zeroize for Secret {
+            fn zeroize(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let scrubbed = Arc::new(AtomicUsize::new(0));
+        let mut cache: super::PoolCache<u64, Secret> = super::PoolCache::new(5).with_zeroize();
+        for key in 0..4 {
+            cache.insert(key, Secret(scrubbed.clone()));
+        }
+        cache.put(Secret(scrubbed.clone()));
+
+        // values handed back aren't scrubbed, but those dropped are.
+        assert!(cache.take().is_some());
+        assert_eq!(0, scrubbed.load(Ordering::Relaxed));
+        cache.shed(0.25);
+        assert_eq!(1, scrubbed.load(Ordering::Relaxed));
+        cache.clear();
+        assert_eq!(4, scrubbed.load(Ordering::Relaxed));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use super::{Contents,PoolCache,Policy,State};
+
+        let mut cache: PoolCache<u64, String> = PoolCache::with_policy(4, Policy::TwoQ);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.insert(3, String::from("baz"));
+        cache.get(&2);
+        cache.put(String::from("free"));
+
+        let json = serde_json::to_string(&Contents(&cache)).unwrap();
+        assert_eq!(r#"{"1":"foo","2":"bar","3":"baz"}"#, json);
+        let Contents(contents): Contents<PoolCache<u64, String>> = serde_json::from_str(&json).unwrap();
+        assert!(contents == cache);
+        assert_eq!(None, contents.freelist.front());
+
+        let json = serde_json::to_string(&State(&cache)).unwrap();
+        let State(mut restored): State<PoolCache<u64, String>> = serde_json::from_str(&json).unwrap();
+        assert!(restored.eq_with_pool(&cache));
+        assert_eq!(Policy::TwoQ, restored.policy);
+        for _ in 0..4 {
+            assert_eq!(cache.take(), restored.take());
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn dump_json() {
+        let mut cache: super::PoolCache<u64, String> = super::PoolCache::new(4);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.get(&2);
+        cache.put(String::from("free"));
+
+        let dumped: serde_json::Value = serde_json::from_str(&cache.dump_json().unwrap()).unwrap();
+        assert_eq!(2, dumped["entries"]);
+        assert_eq!(1, dumped["freelist"]);
+        assert_eq!(1, dumped["clock"][0]["key"]);
+        assert_eq!(2, dumped["clock"][1]["heat"]);
+        assert!(dumped["clock"][0].get("val").is_none());
+
+        let dumped: serde_json::Value =
+            serde_json::from_str(&cache.dump_json_with_values().unwrap()).unwrap();
+        assert_eq!("bar", dumped["clock"][1]["val"]);
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn snapshot() {
+        use super::{PoolCache,Policy,SnapshotError};
+
+        let mut cache: PoolCache<u64, String> = PoolCache::with_policy(4, Policy::Bucketed);
+        cache.insert(1, String::from("foo"));
+        cache.insert(2, String::from("bar"));
+        cache.get(&1);
+        cache.put(String::from("free"));
+        cache.put_for(&2, String::from("spare"));
+
+        let mut snapshot = Vec::new();
+        cache.snapshot_to_writer(&mut snapshot).unwrap();
+        let mut restored: PoolCache<u64, String> =
+            PoolCache::restore_from_reader(&snapshot[..]).unwrap();
+        assert!(restored.eq_with_pool(&cache));
+        assert_eq!(2, restored.stack_len(&2));
+
+        // version 1 had no compression byte or checksum, and didn't save
+        // the age of the cache or its entries.
+        let entries = vec![(1u64, String::from("foo"), None::<()>, 2u64, false, false),
+                           (2, String::from("bar"), None, 1, false, false)];
+        let tagged = std::collections::BTreeMap::<String, Vec<String>>::new();
+        let state = (4u64, Policy::Clock, 0usize, vec![entries, vec![]],
+                     vec![String::from("free")], tagged);
+        let mut version1 = b"POOLSNAP".to_vec();
+        version1.extend_from_slice(&1u32.to_le_bytes());
+        bincode::serialize_into(&mut version1, &state).unwrap();
+        let migrated: PoolCache<u64, String> =
+            PoolCache::restore_from_reader(&version1[..]).unwrap();
+        assert!(migrated.eq_with_pool(&cache));
+
+        // version 4 didn't save stacked objects.
+        let entries = vec![(1u64, String::from("foo"), None::<()>, 2u64, 0u64, false, false),
+                           (2, String::from("bar"), None, 1, 0, false, false)];
+        let tagged = std::collections::BTreeMap::<String, Vec<String>>::new();
+        let state = (4u64, Policy::Clock, 0usize, 0u64, 0u64, 0u64, vec![entries, vec![]],
+                     vec![String::from("free")], tagged);
+        let body = bincode::serialize(&state).unwrap();
+        let mut version4 = b"POOLSNAP".to_vec();
+        version4.extend_from_slice(&4u32.to_le_bytes());
+        version4.push(0);
+        version4.extend_from_slice(&body);
+        version4.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        let migrated: PoolCache<u64, String> =
+            PoolCache::restore_from_reader(&version4[..]).unwrap();
+        assert!(migrated.eq_with_pool(&cache));
+        assert_eq!(1, migrated.stack_len(&2));
+
+        for _ in 0..3 {
+            assert_eq!(cache.take(), restored.take());
+        }
+
+        // corrupt and truncated snapshots.
+        let foo = snapshot.windows(3).position(|bytes| bytes == b"foo").unwrap();
+        snapshot[foo] = b'g';
+        match PoolCache::<u64, String>::restore_from_reader(&snapshot[..]) {
+            Err(SnapshotError::ChecksumMismatch) => {},
+            _ => panic!("restored a corrupt snapshot"),
+        }
+        match PoolCache::<u64, String>::restore_from_reader(&snapshot[..snapshot.len() - 5]) {
+            Err(SnapshotError::Truncated) => {},
+            _ => panic!("restored a truncated snapshot"),
+        }
+
+        // a snapshot from the future, and garbage.
+        snapshot[8] = 99;
+        match PoolCache::<u64, String>::restore_from_reader(&snapshot[..]) {
+            Err(SnapshotError::UnsupportedVersion(99)) => {},
+            _ => panic!("restored from an unknown version"),
+        }
+        match PoolCache::<u64, String>::restore_from_reader(&b"POOL"[..]) {
+            Err(SnapshotError::NotASnapshot) => {},
+            _ => panic!("restored from garbage"),
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn snapshot_policies() {
+        use super::{PoolCache,Policy};
+
+        let policies = vec![Policy::Clock, Policy::Lfu{period: 4}, Policy::ClockPro, Policy::TwoQ,
+                            Policy::Sampled{samples: 2}, Policy::Bucketed, Policy::LazyClock,
+                            Policy::Gdsf, Policy::WeightedRandom];
+        for policy in policies {
+            let mut cache: PoolCache<u64, u64> = PoolCache::with_policy(4, policy);
+            cache.insert(1, 10);
+            for _ in 0..3 {
+                cache.get(&1);
+            }
+            cache.insert(9, 90);
+            for _ in 0..2 {
+                cache.get(&9);
+            }
+            cache.take();
+
+            let mut snapshot = Vec::new();
+            cache.snapshot_to_writer(&mut snapshot).unwrap();
+            let mut restored: PoolCache<u64, u64> =
+                PoolCache::restore_from_reader(&snapshot[..]).unwrap();
+            assert!(restored.eq_with_pool(&cache));
+            for cache in [&mut cache, &mut restored].iter_mut() {
+                cache.insert(4, 40);
+                for _ in 0..2 {
+                    cache.get(&4);
+                }
+            }
+            // random policies needn't evict in the same order.
+            let mut taken: Vec<_> = (0..3).map(|_| cache.take()).collect();
+            let mut restored: Vec<_> = (0..3).map(|_| restored.take()).collect();
+            if let Policy::Sampled{..} | Policy::WeightedRandom = policy {
+                taken.sort();
+                restored.sort();
+            }
+            assert_eq!(taken, restored, "{:?}", policy);
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn snapshot_file() {
+        use super::PoolCache;
+
+        let dir = std::env::temp_dir().join(format!("poolcache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.snap");
+
+        let mut cache: PoolCache<u64, String> = PoolCache::new(4);
+        cache.insert(1, String::from("foo"));
+        cache.save_to_path(&path).unwrap();
+        cache.insert(2, String::from("bar"));
+        cache.save_to_path(&path).unwrap();
+
+        let restored: PoolCache<u64, String> = PoolCache::load_from_path(&path).unwrap();
+        assert!(restored.eq_with_pool(&cache));
+        // only the snapshot itself is left behind.
+        assert_eq!(1, std::fs::read_dir(&dir).unwrap().count());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn compressed_snapshot() {
+        use super::{Compression,PoolCache};
+
+        let mut cache: PoolCache<u64, String> = PoolCache::new(4);
+        for key in 0..100 {
+            cache.insert(key, String::from("foo").repeat(100));
+        }
+        let mut plain = Vec::new();
+        cache.snapshot_to_writer(&mut plain).unwrap();
+        let mut compressed = Vec::new();
+        cache.snapshot_to_writer_compressed(&mut compressed, Compression::Lz4).unwrap();
+        assert!(compressed.len() < plain.len() / 10);
+
+        let restored: PoolCache<u64, String> =
+            PoolCache::restore_from_reader(&compressed[..]).unwrap();
+        assert!(restored.eq_with_pool(&cache));
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn fuzz() {
+        use arbitrary::{Arbitrary,Unstructured};
+        use super::{Op,Policy,PoolCache,Trace};
+
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let bytes: Vec<u8> = (0..4096).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect();
+        let policies = [Policy::Clock, Policy::Lfu { period: 16 }, Policy::ClockPro, Policy::TwoQ,
+                        Policy::Sampled { samples: 3 }, Policy::Bucketed, Policy::LazyClock,
+                        Policy::Gdsf, Policy::WeightedRandom];
+        for &policy in &policies {
+            let mut data = Unstructured::new(&bytes);
+            let mut trace = Trace::new();
+            while !data.is_empty() {
+                trace.push(Op::<u8>::arbitrary(&mut data).unwrap());
+            }
+            super::fuzz::check(PoolCache::with_policy(4, policy), &trace);
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn model(trace in super::testing::trace(0..16u8, 0..200), max_heat in 1..8u64) {
+            use super::Op;
+
+            let mut cache: super::PoolCache<u8, u64> = super::PoolCache::new(max_heat);
+            let mut model = super::testing::Model::new(max_heat);
+            for (val, op) in trace.ops().iter().enumerate() {
+                let val = val as u64;
+                match *op {
+                    Op::Get(ref key) => proptest::prop_assert_eq!(cache.get(key), model.get(key)),
+                    Op::Insert(key) => {
+                        cache.insert(key, val);
+                        model.insert(key, val);
+                    }
+                    Op::Swap(ref key) => proptest::prop_assert_eq!(cache.swap(key, val), model.swap(key, val)),
+                    Op::Put => {
+                        cache.put(val);
+                        model.put(val);
+                    }
+                    Op::Take => proptest::prop_assert_eq!(cache.take(), model.take()),
+                }
+                for key in 0..16 {
+                    proptest::prop_assert_eq!(cache.contains_key(&key), model.contains_key(&key));
+                }
+            }
+        }
+    }
 }
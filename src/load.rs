@@ -0,0 +1,209 @@
+//! Loading values into the cache on a miss, and reloading them before they
+//! expire.
+
+use std::collections::BTreeSet;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration,Instant};
+
+use super::{CacheEntry,Op,PoolCache,RemovalCause};
+
+/// Whether a value returned by `get_or_stale` is still within its time to
+/// live.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freshness {
+    /// The entry hasn't expired.
+    Fresh,
+    /// The entry has expired, but is within its grace period, and has been
+    /// queued for refresh.
+    Stale,
+}
+
+// Produces the value for a key that isn't cached.
+pub type Loader<Key, Value> = dyn Fn(&Key) -> Value + Send + Sync;
+
+// Produces the values for several keys that aren't cached, in order.
+pub type BatchLoader<Key, Value> = dyn Fn(&[&Key]) -> Vec<Value> + Send + Sync;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Set the function `get_or_load`, `refresh_pending` and (without a
+        /// batch loader) `load_many` call to produce the value for a key.
+        pub fn with_loader<F>(mut self, loader: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&Key) -> Value + Send + Sync + 'static {
+            self.loader = Some(Box::new(loader));
+            self
+        }
+
+        /// Like `get`, but if the key isn't present (or has expired), its
+        /// value is produced by the loader and inserted first. Returns
+        /// `None` only if no loader was set with `with_loader`.
+        pub fn get_or_load(&mut self, key: &Key) -> Option<&Value>
+            where Key: Clone {
+            if self.live(key).is_none() {
+                let val = (self.loader.as_ref()?)(key);
+                self.insert(key.clone(), val);
+                return self.cache.get(key).map(|entry| &entry.val);
+            }
+            self.get(key)
+        }
+
+        /// Set the function `load_many` calls to produce the values for all
+        /// the keys it missed at once, e.g. with a single query upstream. It
+        /// is given the missing keys, each once, and returns their values in
+        /// the same order; keys past the end of the values it returns are
+        /// left unloaded.
+        pub fn with_batch_loader<F>(mut self, loader: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&[&Key]) -> Vec<Value> + Send + Sync + 'static {
+            self.batch_loader = Some(Box::new(loader));
+            self
+        }
+
+        /// Returns a reference to the value associated with each of `keys`,
+        /// in order, or `None` for those not present in the cache. Like
+        /// `get`, this counts as a use of each entry found.
+        pub fn get_many(&self, keys: &[Key]) -> Vec<Option<&Value>> {
+            keys.iter().map(|key| self.get(key)).collect()
+        }
+
+        /// Like `get_many`, but the keys that aren't present (or have
+        /// expired) are loaded and inserted first: all together with the
+        /// batch loader if one was set, or else one at a time with the
+        /// loader. Keys are left `None` if neither was set.
+        pub fn load_many(&mut self, keys: &[Key]) -> Vec<Option<&Value>>
+            where Key: Clone {
+            let missing: BTreeSet<&Key> = keys.iter().filter(|key| self.live(key).is_none())
+                .collect();
+            let missing: Vec<&Key> = missing.into_iter().collect();
+            let loaded: Vec<(Key, Value)> = match (&self.batch_loader, &self.loader) {
+                (Some(batch), _) if !missing.is_empty() => {
+                    missing.iter().map(|&key| key.clone()).zip(batch(&missing)).collect()
+                }
+                (None, Some(loader)) => {
+                    missing.iter().map(|&key| (key.clone(), loader(key))).collect()
+                }
+                _ => Vec::new(),
+            };
+            for (key, val) in loaded {
+                self.insert(key, val);
+            }
+            self.get_many(keys)
+        }
+
+        /// Queue entries for reloading when they are used after `fraction`
+        /// (between 0 and 1) of their time to live has passed, so that hot
+        /// entries are reloaded before they expire rather than missed
+        /// afterwards. Queued entries are listed by `pending_refresh`, and
+        /// reloaded by `refresh_pending`, e.g. from a background thread.
+        /// Entries without a time to live (their own, or the cache's) are
+        /// never queued.
+        pub fn with_refresh_ahead(mut self, fraction: f64) -> PoolCache<Key, Value, Meta> {
+            self.refresh_ahead = Some(fraction.clamp(0.0, 1.0));
+            self
+        }
+
+        /// Let `get_or_stale` return entries for up to `grace` after they
+        /// expire, queueing them for refresh, so that callers can be served
+        /// a stale value while it is reloaded rather than miss. Other
+        /// lookups still treat expired entries as absent, and
+        /// `drain_expired` removes them, grace period or not.
+        pub fn with_stale_grace(mut self, grace: Duration) -> PoolCache<Key, Value, Meta> {
+            self.stale_grace = grace;
+            self
+        }
+
+        /// Like `get`, but an entry that has expired within the grace period
+        /// set by `with_stale_grace` is returned too, flagged as stale, and
+        /// queued for `refresh_pending` to reload.
+        pub fn get_or_stale(&self, key: &Key) -> Option<(&Value, Freshness)> {
+            self.record(|copy| Op::Get(copy(key)));
+            // invalidated entries aren't served, even within the grace period.
+            let entry = self.cache.get(key).filter(|entry| !self.invalidated(key, entry));
+            let found = entry.and_then(|entry| match entry.expires {
+                Some(expires) if Instant::now() >= expires => {
+                    if Instant::now() >= expires + self.stale_grace {
+                        return None;
+                    }
+                    entry.refresh.store(true, Ordering::Relaxed);
+                    Some((entry, Freshness::Stale))
+                }
+                _ => Some((entry, Freshness::Fresh)),
+            });
+            self.looked_up(key, found.is_some());
+            found.map(|(entry, freshness)| {
+                self.hit(entry);
+                (&entry.val, freshness)
+            })
+        }
+
+        /// Returns the keys of the entries queued for refresh, in key order.
+        /// Takes time linear in the number of keyed entries.
+        pub fn pending_refresh(&self) -> impl Iterator<Item = &Key> {
+            self.cache.iter().filter(|(_, entry)| entry.refresh.load(Ordering::Relaxed))
+                .map(|(key, _)| &**key)
+        }
+
+        /// Reload every entry queued for refresh with the loader, replacing
+        /// its value and restarting its time to live. Like `swap`, the entry
+        /// keeps its heat and place in the eviction order, and the entries
+        /// depending on it are invalidated; as with `insert`, the old value
+        /// goes back to the pool (notifying the listener with
+        /// `RemovalCause::Replaced`). Returns the number of entries
+        /// reloaded, which is 0 if no loader was set. Takes time linear in
+        /// the number of keyed entries.
+        pub fn refresh_pending(&mut self) -> usize {
+            let pending: BTreeSet<Arc<Key>> = self.pending_refresh()
+                .filter_map(|key| self.cache.get_key_value(key).map(|(key, _)| key.clone()))
+                .collect();
+            let loader = match self.loader {
+                Some(ref loader) => loader,
+                None => return 0,
+            };
+            let loaded: Vec<(Arc<Key>, Value)> = pending.into_iter()
+                .map(|key| {
+                    let val = loader(&key);
+                    (key, val)
+                })
+                .collect();
+            let mut count = 0;
+            let default = self.ttl;
+            for (key, val) in loaded {
+                // refreshing an earlier entry may have invalidated this one.
+                if !self.cache.contains_key(&key) {
+                    self.freelist.push_back(val);
+                    continue;
+                }
+                self.record(|copy| Op::Swap(copy(&key)));
+                let generation = self.next_generation();
+                let entry = self.cache.get_mut(&key).unwrap();
+                entry.generation = generation;
+                entry.refresh.store(false, Ordering::Relaxed);
+                if let Some(ttl) = entry.ttl.or(default) {
+                    entry.expires = Some(Instant::now() + ttl);
+                }
+                let old = mem::replace(&mut entry.val, val);
+                self.notify(&key, &old, RemovalCause::Replaced);
+                self.freelist.push_back(old);
+                self.invalidate_dependents(&key);
+                count += 1;
+            }
+            self.enforce_budget();
+            count
+        }
+
+        // Queue `entry` for refresh if it is far enough into its time to
+        // live.
+        pub(crate) fn refresh_if_due(&self, entry: &CacheEntry<Value, Meta>) {
+            let fraction = match self.refresh_ahead {
+                Some(fraction) => fraction,
+                None => return,
+            };
+            if let (Some(expires), Some(ttl)) = (entry.expires, entry.ttl.or(self.ttl)) {
+                if Instant::now() + ttl.mul_f64(1.0 - fraction) >= expires {
+                    entry.refresh.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+}
@@ -0,0 +1,82 @@
+//! A pool with a small freelist per thread, in front of a shared PoolCache.
+
+use std::cell::RefCell;
+
+use thread_local::ThreadLocal;
+
+use super::{PoolCache,SyncPoolCache};
+
+/// A PoolCache shared between threads, where each thread also keeps a small
+/// freelist of its own.
+///
+/// `put` and `take` are served from the calling thread's freelist when
+/// possible, without touching any shared state. When a thread's freelist is
+/// empty, `take` falls back to the shared cache, moving extra free objects
+/// over while it holds the lock; when it is full, `put` moves half of it to
+/// the shared cache. Keyed entries always live in the shared cache, which is
+/// available through `shared`.
+pub struct LocalPoolCache<Key, Value>
+    where Value: Send {
+    shared: SyncPoolCache<Key, Value>,
+    local: ThreadLocal<RefCell<Vec<Value>>>,
+    local_capacity: usize,
+}
+
+impl<Key, Value> LocalPoolCache<Key, Value>
+    where Key: PartialOrd + Ord,
+          Value: Send {
+
+        /// Wrap `cache` for sharing between threads, keeping up to
+        /// `local_capacity` free objects per thread.
+        pub fn new(cache: PoolCache<Key, Value>, local_capacity: usize) -> LocalPoolCache<Key, Value> {
+            LocalPoolCache{
+                shared: SyncPoolCache::new(cache),
+                local: ThreadLocal::new(),
+                local_capacity}
+        }
+
+        /// Add a new object to the calling thread's freelist, spilling to
+        /// the shared cache if it is full.
+        pub fn put(&self, val: Value) {
+            let mut local = self.freelist().borrow_mut();
+            if local.len() < self.local_capacity {
+                local.push(val);
+                return;
+            }
+            let keep = self.local_capacity / 2;
+            let mut shared = self.shared.write();
+            shared.put(val);
+            for val in local.drain(keep..) {
+                shared.put(val);
+            }
+        }
+
+        /// Returns an object from the calling thread's freelist, or from the
+        /// shared cache (evicting its least-used key if necessary) if the
+        /// thread has none.
+        pub fn take(&self) -> Option<Value> {
+            let mut local = self.freelist().borrow_mut();
+            if let Some(val) = local.pop() {
+                return Some(val);
+            }
+            let mut shared = self.shared.write();
+            let val = shared.take();
+            // restock with objects that are free anyway, without evicting.
+            while local.len() < self.local_capacity / 2 {
+                match shared.freelist.pop_front() {
+                    Some(val) => local.push(val),
+                    None => break,
+                }
+            }
+            val
+        }
+
+        /// Returns the shared cache, which holds all keyed entries.
+        pub fn shared(&self) -> &SyncPoolCache<Key, Value> {
+            &self.shared
+        }
+
+        fn freelist(&self) -> &RefCell<Vec<Value>> {
+            self.local.get_or(|| RefCell::new(Vec::with_capacity(self.local_capacity)))
+        }
+}
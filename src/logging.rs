@@ -0,0 +1,16 @@
+//! Logging of cache activity through the `log` crate. Insertions and
+//! expired entries are logged at trace level, and evictions at debug level.
+//! Keys and values aren't logged, since they may not be printable.
+
+use std::time::Duration;
+
+// Searches for an entry to evict that take longer than this are logged.
+const SLOW_SEARCH: Duration = Duration::from_millis(1);
+
+// Log a search for an entry to evict, if it took unusually long.
+pub(crate) fn searched(elapsed: Duration, found: bool) {
+    if elapsed >= SLOW_SEARCH {
+        log::debug!("poolcache: searching for an entry to evict took {:?}{}",
+                    elapsed, if found { "" } else { ", and gave up" });
+    }
+}
@@ -0,0 +1,138 @@
+//! An adapter with the method names and signatures of `lru::LruCache`, for
+//! moving code written against that crate onto a PoolCache.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use super::{PoolCache,RemovalCause};
+
+/// A PoolCache bounded to a number of entries, with the interface of
+/// `lru::LruCache`.
+///
+/// Call sites of an `LruCache` carry over unchanged, but entries are
+/// evicted by the policy of the wrapped cache: `pop_lru` and the eviction
+/// made by `put` past capacity remove its least valuable entry, which is
+/// the least recently used one only if the policy makes it so.
+/// Lookups take the key itself, rather than any borrowed form of it.
+/// Evicted values are dropped, as with `LruCache`.
+pub struct LruAdapter<Key, Value> {
+    cache: PoolCache<Key, Value>,
+    cap: NonZeroUsize,
+}
+
+impl<Key, Value> LruAdapter<Key, Value>
+    where Key: PartialOrd + Ord + Clone {
+
+        /// Wrap `cache`, holding at most `cap` entries. Entries beyond `cap`
+        /// already in `cache` are evicted.
+        pub fn new(cache: PoolCache<Key, Value>, cap: NonZeroUsize) -> LruAdapter<Key, Value> {
+            let mut lru = LruAdapter{cache, cap};
+            lru.shrink();
+            lru
+        }
+
+        /// Insert `val` under `key`, returning the value it replaced, if
+        /// any. If the cache is full, an entry is evicted first.
+        pub fn put(&mut self, key: Key, val: Value) -> Option<Value> {
+            if self.cache.live(&key).is_some() {
+                return self.cache.swap(&key, val);
+            }
+            self.push(key, val);
+            None
+        }
+
+        /// Insert `val` under `key`, returning the key and value it replaced,
+        /// or else those of the entry evicted to make room, if any.
+        pub fn push(&mut self, key: Key, val: Value) -> Option<(Key, Value)> {
+            if self.cache.live(&key).is_some() {
+                return self.cache.swap(&key, val).map(|old| (key, old));
+            }
+            let evicted = if self.len() >= self.cap.get() { self.pop_lru() } else { None };
+            self.cache.insert(key, val);
+            evicted
+        }
+
+        /// Returns the value associated with `key`, counting a use of it.
+        pub fn get(&mut self, key: &Key) -> Option<&Value> {
+            self.cache.get(key)
+        }
+
+        /// Returns the value associated with `key`, counting a use of it.
+        pub fn get_mut(&mut self, key: &Key) -> Option<&mut Value> {
+            self.cache.get(key)?;
+            self.cache.cache.get_mut(key).map(|entry| &mut entry.val)
+        }
+
+        /// Returns the value associated with `key`, without counting a use.
+        pub fn peek(&self, key: &Key) -> Option<&Value> {
+            self.cache.live(key).map(|(_, entry)| &entry.val)
+        }
+
+        /// Returns `true` if `key` is cached, without counting a use.
+        pub fn contains(&self, key: &Key) -> bool {
+            self.cache.contains_key(key)
+        }
+
+        /// Remove the entry for `key`, returning its value.
+        pub fn pop(&mut self, key: &Key) -> Option<Value> {
+            self.pop_entry(key).map(|(_, val)| val)
+        }
+
+        /// Remove the entry for `key`, returning its key and value.
+        pub fn pop_entry(&mut self, key: &Key) -> Option<(Key, Value)> {
+            self.cache.live(key)?;
+            let (key, val) = self.cache.remove_entry(key, RemovalCause::Invalidated)?;
+            Some((unshare(key), val))
+        }
+
+        /// Evict the least valuable entry, returning its key and value.
+        pub fn pop_lru(&mut self) -> Option<(Key, Value)> {
+            let (key, val) = self.cache.evict(usize::MAX, RemovalCause::Evicted)?;
+            Some((unshare(key), val))
+        }
+
+        /// Returns the number of entries.
+        pub fn len(&self) -> usize {
+            self.cache.cache.len()
+        }
+
+        /// Returns `true` if there are no entries.
+        pub fn is_empty(&self) -> bool {
+            self.cache.cache.is_empty()
+        }
+
+        /// Returns the most entries the cache holds.
+        pub fn cap(&self) -> NonZeroUsize {
+            self.cap
+        }
+
+        /// Change the most entries the cache holds, evicting entries until
+        /// it holds no more than `cap`.
+        pub fn resize(&mut self, cap: NonZeroUsize) {
+            self.cap = cap;
+            self.shrink();
+        }
+
+        /// Drop every entry.
+        pub fn clear(&mut self) {
+            self.cache.clear();
+        }
+
+        /// Unwrap the cache.
+        pub fn into_inner(self) -> PoolCache<Key, Value> {
+            self.cache
+        }
+
+        fn shrink(&mut self) {
+            while self.len() > self.cap.get() {
+                if self.pop_lru().is_none() {
+                    break;
+                }
+            }
+        }
+}
+
+// The key is still shared if the policy remembers it.
+fn unshare<Key: Clone>(key: Arc<Key>) -> Key {
+    Arc::try_unwrap(key).unwrap_or_else(|key| (*key).clone())
+}
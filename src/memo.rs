@@ -0,0 +1,235 @@
+//! The `cached` crate's `Cached` trait, for using a PoolCache as the store
+//! of memoized functions.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cached::Cached;
+
+use super::{PoolCache,RemovalCause};
+
+/// A PoolCache implementing `cached::Cached`, so that it can back
+/// `#[cached]` functions.
+///
+/// `Cached` looks up keys by any borrowed form that is `Hash + Eq`, which
+/// a PoolCache can't search its ordered map by, so the wrapper also keeps
+/// the stored keys by hash. The number of entries is limited by
+/// `with_max_objects`, evicting the least valuable entry to make room.
+/// `cache_clear` moves every cached value to the pool for reuse by `take`,
+/// while `cache_reset` drops them.
+pub struct MemoPoolCache<Key, Value> {
+    cache: PoolCache<Key, Value>,
+    // stored keys by hash. Keys that have since left the cache are dropped
+    // when found, or when the index outgrows the cache.
+    index: HashMap<u64, Vec<Arc<Key>>>,
+    indexed: usize,
+    hasher: RandomState,
+}
+
+impl<Key, Value> MemoPoolCache<Key, Value>
+    where Key: PartialOrd + Ord + Hash {
+
+        /// Wrap `cache` for use as a `cached::Cached` store. Entries already
+        /// in `cache` are indexed up front.
+        pub fn new(cache: PoolCache<Key, Value>) -> MemoPoolCache<Key, Value> {
+            let mut memo = MemoPoolCache{
+                cache,
+                index: HashMap::new(),
+                indexed: 0,
+                hasher: RandomState::new()};
+            memo.reindex();
+            memo
+        }
+
+        /// Returns the wrapped cache.
+        pub fn cache(&self) -> &PoolCache<Key, Value> {
+            &self.cache
+        }
+
+        /// Returns an object from the pool, evicting the least valuable entry
+        /// if necessary, like `PoolCache::take`.
+        pub fn take(&mut self) -> Option<Value> {
+            self.cache.take()
+        }
+
+        /// Unwrap the cache.
+        pub fn into_inner(self) -> PoolCache<Key, Value> {
+            self.cache
+        }
+
+        // Returns the stored key equal to `key`, if it is cached.
+        fn find<Q>(&mut self, key: &Q) -> Option<Arc<Key>>
+            where Key: Borrow<Q>,
+                  Q: Hash + Eq + ?Sized {
+            let hash = self.hasher.hash_one(key);
+            let keys = self.index.get_mut(&hash)?;
+            let stored = &self.cache.cache;
+            let before = keys.len();
+            keys.retain(|indexed| stored.contains_key(indexed));
+            self.indexed -= before - keys.len();
+            let found = keys.iter().find(|indexed| (***indexed).borrow() == key).cloned();
+            if keys.is_empty() {
+                self.index.remove(&hash);
+            }
+            found
+        }
+
+        // Index the stored key equal to `key`, which was just inserted.
+        fn add(&mut self, key: &Key) {
+            let stored = match self.cache.cache.get_key_value(key) {
+                Some((stored, _)) => stored.clone(),
+                None => return,
+            };
+            let keys = self.index.entry(self.hasher.hash_one(key)).or_default();
+            if !keys.iter().any(|indexed| Arc::ptr_eq(indexed, &stored)) {
+                keys.push(stored);
+                self.indexed += 1;
+            }
+            if self.indexed > 2 * self.cache.cache.len() + 16 {
+                self.reindex();
+            }
+        }
+
+        fn reindex(&mut self) {
+            self.index.clear();
+            for key in self.cache.cache.keys() {
+                self.index.entry(self.hasher.hash_one(&**key)).or_default().push(key.clone());
+            }
+            self.indexed = self.cache.cache.len();
+        }
+
+        // Insert `val` under `key`, first evicting an entry if the cache is
+        // full, and returning the live value it replaces, if any.
+        fn set(&mut self, key: Key, val: Value) -> Option<Value>
+            where Key: Clone {
+            let full = self.cache.cache.len() >= self.cache.max_objects;
+            if full && !self.cache.cache.contains_key(&key) {
+                if let Some((_, val)) = self.cache.evict(usize::MAX, RemovalCause::Evicted) {
+                    self.cache.discard(val);
+                }
+            }
+            let old = self.cache.swap(&key, val);
+            self.add(&key);
+            old
+        }
+
+        // Returns the value of the live entry for `key`.
+        fn value_mut(&mut self, key: &Key) -> Option<&mut Value> {
+            self.cache.get(key)?;
+            self.cache.cache.get_mut(key).map(|entry| &mut entry.val)
+        }
+}
+
+impl<Key, Value> Cached<Key, Value> for MemoPoolCache<Key, Value>
+    where Key: PartialOrd + Ord + Hash + Clone {
+
+        fn cache_get<Q>(&mut self, key: &Q) -> Option<&Value>
+            where Key: Borrow<Q>,
+                  Q: Hash + Eq + ?Sized {
+            match self.find(key) {
+                Some(stored) => self.cache.get(&stored),
+                None => {
+                    self.cache.counters.miss();
+                    None
+                }
+            }
+        }
+
+        fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut Value>
+            where Key: Borrow<Q>,
+                  Q: Hash + Eq + ?Sized {
+            match self.find(key) {
+                Some(stored) => self.value_mut(&stored),
+                None => {
+                    self.cache.counters.miss();
+                    None
+                }
+            }
+        }
+
+        fn cache_set(&mut self, key: Key, val: Value) -> Option<Value> {
+            self.set(key, val)
+        }
+
+        fn cache_get_or_set_with<F: FnOnce() -> Value>(&mut self, key: Key, f: F) -> &mut Value {
+            if self.cache.get(&key).is_none() {
+                self.set(key.clone(), f());
+            }
+            &mut self.cache.cache.get_mut(&key).unwrap().val
+        }
+
+        fn cache_try_get_or_set_with<F: FnOnce() -> Result<Value, E>, E>(
+            &mut self, key: Key, f: F) -> Result<&mut Value, E> {
+            if self.cache.get(&key).is_none() {
+                self.set(key.clone(), f()?);
+            }
+            Ok(&mut self.cache.cache.get_mut(&key).unwrap().val)
+        }
+
+        fn cache_remove<Q>(&mut self, key: &Q) -> Option<Value>
+            where Key: Borrow<Q>,
+                  Q: Hash + Eq + ?Sized {
+            let stored = self.find(key)?;
+            let live = self.cache.live(&stored).is_some();
+            let (_, val) = self.cache.remove_entry(&stored, RemovalCause::Invalidated)?;
+            if live {
+                return Some(val);
+            }
+            self.cache.discard(val);
+            None
+        }
+
+        fn cache_clear(&mut self) {
+            let keys = self.cache.cache.keys().cloned().collect();
+            for (_, val) in self.cache.remove_entries(keys, RemovalCause::Invalidated) {
+                self.cache.put(val);
+            }
+            self.index.clear();
+            self.indexed = 0;
+        }
+
+        fn cache_reset(&mut self) {
+            self.cache.clear();
+            self.cache.reset_stats();
+            self.index = HashMap::new();
+            self.indexed = 0;
+        }
+
+        fn cache_reset_metrics(&mut self) {
+            self.cache.reset_stats();
+        }
+
+        fn cache_size(&self) -> usize {
+            self.cache.cache.len()
+        }
+
+        fn cache_hits(&self) -> Option<u64> {
+            Some(self.cache.stats().hits)
+        }
+
+        fn cache_misses(&self) -> Option<u64> {
+            Some(self.cache.stats().misses)
+        }
+
+        fn cache_capacity(&self) -> Option<usize> {
+            Some(self.cache.max_objects).filter(|&max| max != usize::MAX)
+        }
+
+        fn cache_lifespan(&self) -> Option<u64> {
+            self.cache.ttl.map(|ttl| ttl.as_secs())
+        }
+
+        fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+            let old = self.cache_lifespan();
+            self.cache.ttl = Some(Duration::from_secs(seconds));
+            old
+        }
+
+        fn cache_unset_lifespan(&mut self) -> Option<u64> {
+            self.cache.ttl.take().map(|ttl| ttl.as_secs())
+        }
+}
@@ -0,0 +1,43 @@
+//! Caller-defined metadata attached to keyed entries.
+//!
+//! Metadata belongs to an entry rather than to its value: it is dropped
+//! when the entry is replaced or leaves the cache, and never passes through
+//! the pool with the value.
+
+use super::{CacheEntry,Op,PoolCache};
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Like `insert`, but also attaches `meta` to the new entry.
+        pub fn insert_with_meta(&mut self, key: Key, val: Value, meta: Meta) {
+            let mut entry = CacheEntry::new(val);
+            entry.meta = Some(meta);
+            self.insert_entry(key, entry)
+        }
+
+        /// Like `get`, but also returns the metadata attached to the entry,
+        /// if any.
+        pub fn get_with_meta(&self, key: &Key) -> Option<(&Value, Option<&Meta>)> {
+            self.record(|copy| Op::Get(copy(key)));
+            let found = self.live(key).map(|(_, entry)| {
+                self.hit(entry);
+                (&entry.val, entry.meta.as_ref())
+            });
+            self.looked_up(key, found.is_some());
+            found
+        }
+
+        /// Returns the metadata attached to the entry for `key`, if any.
+        /// This doesn't count as a use of the entry.
+        pub fn meta(&self, key: &Key) -> Option<&Meta> {
+            self.live(key).and_then(|(_, entry)| entry.meta.as_ref())
+        }
+
+        /// Returns the metadata slot of the entry for `key`, for attaching,
+        /// changing or removing metadata, or `None` if the key is not
+        /// present in the cache. This doesn't count as a use of the entry.
+        pub fn meta_mut(&mut self, key: &Key) -> Option<&mut Option<Meta>> {
+            self.cache.get_mut(key).map(|entry| &mut entry.meta)
+        }
+}
@@ -0,0 +1,151 @@
+//! A tower middleware that caches the responses of a service in a PoolCache.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc,Mutex};
+use std::task::{Context,Poll};
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::{PoolCache,RemovalCause};
+
+// Picks the key a request's response is cached under, or `None` to pass
+// the request through uncached.
+type Extractor<Request, Key> = dyn Fn(&Request) -> Option<Key> + Send + Sync;
+
+/// A `tower::Layer` that wraps services in a `CacheService`, answering
+/// requests from a PoolCache of earlier responses.
+///
+/// Responses are cached under the key that the extractor picks for each
+/// request, and at most `capacity` are kept: once full, caching a new
+/// response evicts the coldest, which goes to the freelist rather than
+/// being dropped. The wrapped service can build its responses from these
+/// with `take_spare`, so a cache of, say, byte buffers stops allocating
+/// once it has warmed up. All services made by a layer (and its clones)
+/// share its cache.
+pub struct CacheLayer<Request, Key, Response> {
+    shared: Arc<Shared<Request, Key, Response>>,
+}
+
+/// The service made by `CacheLayer`. Requests whose response is cached are
+/// answered without calling the inner service, although its `poll_ready`
+/// is still called first.
+pub struct CacheService<S, Request, Key, Response> {
+    inner: S,
+    shared: Arc<Shared<Request, Key, Response>>,
+}
+
+struct Shared<Request, Key, Response> {
+    cache: Mutex<PoolCache<Key, Response>>,
+    capacity: usize,
+    key: Box<Extractor<Request, Key>>,
+}
+
+impl<Request, Key, Response> CacheLayer<Request, Key, Response>
+    where Key: PartialOrd + Ord {
+
+        /// Cache up to `capacity` responses in `cache`, under the keys that
+        /// `key` extracts from requests. Requests for which `key` returns
+        /// `None` always go to the inner service, and their responses
+        /// aren't cached.
+        pub fn new<F>(cache: PoolCache<Key, Response>, capacity: usize, key: F)
+                      -> CacheLayer<Request, Key, Response>
+            where F: Fn(&Request) -> Option<Key> + Send + Sync + 'static {
+            CacheLayer{shared: Arc::new(Shared{
+                cache: Mutex::new(cache),
+                capacity,
+                key: Box::new(key)})}
+        }
+
+        /// Returns an unused response from the pool, evicting the coldest
+        /// cached one if there is none, for the inner service to overwrite
+        /// rather than allocating a new one. Returns `None` if nothing is
+        /// cached or pooled.
+        pub fn take_spare(&self) -> Option<Response> {
+            self.shared.cache.lock().unwrap().take()
+        }
+
+        /// Give back a response that won't be cached, for reuse by
+        /// `take_spare`.
+        pub fn put_spare(&self, response: Response) {
+            self.shared.cache.lock().unwrap().put(response);
+        }
+
+        /// Run `f` on the cache, to inspect or invalidate responses.
+        pub fn with_cache<F, R>(&self, f: F) -> R
+            where F: FnOnce(&mut PoolCache<Key, Response>) -> R {
+            f(&mut self.shared.cache.lock().unwrap())
+        }
+}
+
+impl<Request, Key, Response> Clone for CacheLayer<Request, Key, Response> {
+    fn clone(&self) -> Self {
+        CacheLayer{shared: self.shared.clone()}
+    }
+}
+
+impl<S, Request, Key, Response> Layer<S> for CacheLayer<Request, Key, Response> {
+    type Service = CacheService<S, Request, Key, Response>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService{inner, shared: self.shared.clone()}
+    }
+}
+
+impl<S, Request, Key, Response> Clone for CacheService<S, Request, Key, Response>
+    where S: Clone {
+
+        fn clone(&self) -> Self {
+            CacheService{inner: self.inner.clone(), shared: self.shared.clone()}
+        }
+}
+
+impl<Request, Key, Response> Shared<Request, Key, Response>
+    where Key: PartialOrd + Ord {
+
+        fn store(&self, key: Key, response: Response) {
+            let mut cache = self.cache.lock().unwrap();
+            if !cache.contains_key(&key) && cache.cache.len() >= self.capacity {
+                // the coldest response is kept for `take_spare`.
+                if let Some((_, spare)) = cache.evict(usize::MAX, RemovalCause::Evicted) {
+                    cache.put(spare);
+                }
+            }
+            cache.insert(key, response);
+        }
+}
+
+impl<S, Request, Key, Response> Service<Request> for CacheService<S, Request, Key, Response>
+    where S: Service<Request, Response = Response>,
+          S::Future: Send + 'static,
+          Request: 'static,
+          Key: PartialOrd + Ord + Send + Sync + 'static,
+          Response: Clone + Send + 'static {
+
+        type Response = Response;
+        type Error = S::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: Request) -> Self::Future {
+            let key = (self.shared.key)(&request);
+            if let Some(ref key) = key {
+                if let Some(response) = self.shared.cache.lock().unwrap().get_cloned(key) {
+                    return Box::pin(async move { Ok(response) });
+                }
+            }
+            let response = self.inner.call(request);
+            let shared = self.shared.clone();
+            Box::pin(async move {
+                let response = response.await?;
+                if let Some(key) = key {
+                    shared.store(key, response.clone());
+                }
+                Ok(response)
+            })
+        }
+}
@@ -0,0 +1,130 @@
+//! Namespaces, for managing groups of keyed entries within one cache.
+//!
+//! Entries in every namespace share the cache's freelist and eviction
+//! clock; a namespace only records which entries belong together, so that
+//! they can be cleared at once, or held to a quota.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::{CacheEntry,PoolCache,RemovalCause};
+
+/// Limits on the entries of one namespace, set with `set_quota`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    /// The number of entries the namespace may hold.
+    pub max_entries: usize,
+    /// The total weight of the values of the namespace's entries, as
+    /// measured by the weigher.
+    pub max_weight: usize,
+}
+
+impl Default for Quota {
+    // no limits.
+    fn default() -> Quota {
+        Quota{max_entries: usize::MAX, max_weight: usize::MAX}
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Like `insert`, but places the new entry in namespace `ns`. Keys
+        /// are shared between namespaces, so inserting a key that is
+        /// already cached (in any namespace, or none) replaces its entry.
+        pub fn insert_in(&mut self, ns: &str, key: Key, val: Value) {
+            let mut entry = CacheEntry::new(val);
+            entry.ns = Some(self.namespace_name(ns));
+            self.insert_entry(key, entry)
+        }
+
+        /// Remove every entry in namespace `ns`, moving their values to the
+        /// pool, without affecting the heat of other entries. Returns the
+        /// number of entries removed. Takes time linear in the number of
+        /// keyed entries.
+        pub fn clear_namespace(&mut self, ns: &str) -> usize {
+            match self.namespaces.remove(ns) {
+                Some(keys) => {
+                    let count = keys.len();
+                    self.invalidate(keys);
+                    count
+                }
+                None => 0,
+            }
+        }
+
+        /// Limit the entries of namespace `ns` to `quota`, which `take_in`
+        /// enforces.
+        pub fn set_quota(&mut self, ns: &str, quota: Quota) {
+            self.quotas.insert(ns.to_owned(), quota);
+        }
+
+        /// Like `take`, but if namespace `ns` has reached its quota, evicts
+        /// the coldest of its own entries instead, so that a namespace at
+        /// its quota only displaces its own working set. Use this to get
+        /// the object for an `insert_in` to `ns`. Evicting from a namespace
+        /// takes time linear in the number of keyed entries.
+        pub fn take_in(&mut self, ns: &str) -> Option<Value> {
+            if self.at_quota(ns) {
+                let coldest = self.namespaces.get(ns).and_then(|keys| {
+                    keys.iter().filter(|&key| self.evictable(key))
+                        .reduce(|coldest, key| if self.colder(key, coldest) { key } else { coldest })
+                        .cloned()
+                });
+                if let Some(key) = coldest {
+                    return self.remove_entry(&key, RemovalCause::Evicted).map(|(_, val)| val);
+                }
+            }
+            self.take()
+        }
+
+        /// Returns the number of entries in namespace `ns`.
+        pub fn namespace_len(&self, ns: &str) -> usize {
+            self.namespaces.get(ns).map_or(0, BTreeSet::len)
+        }
+
+        fn at_quota(&self, ns: &str) -> bool {
+            let quota = match self.quotas.get(ns) {
+                Some(quota) => quota,
+                None => return false,
+            };
+            let keys = match self.namespaces.get(ns) {
+                Some(keys) => keys,
+                None => return false,
+            };
+            if keys.len() >= quota.max_entries {
+                return true;
+            }
+            if quota.max_weight == usize::MAX {
+                return false;
+            }
+            let weight: usize = keys.iter().map(|key| self.weigh(&self.cache[key].val)).sum();
+            weight >= quota.max_weight
+        }
+
+        // Returns the shared name of `ns`, so that entries don't each hold
+        // a copy.
+        fn namespace_name(&self, ns: &str) -> Arc<str> {
+            match self.namespaces.get_key_value(ns) {
+                Some((name, _)) => name.clone(),
+                None => Arc::from(ns),
+            }
+        }
+
+        pub(crate) fn join_namespace(&mut self, key: &Arc<Key>, ns: Option<Arc<str>>) {
+            if let Some(ns) = ns {
+                self.namespaces.entry(ns).or_default().insert(key.clone());
+            }
+        }
+
+        pub(crate) fn leave_namespace(&mut self, key: &Key, ns: Option<&Arc<str>>) {
+            if let Some(ns) = ns {
+                if let Some(keys) = self.namespaces.get_mut(&**ns) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.namespaces.remove(&**ns);
+                    }
+                }
+            }
+        }
+}
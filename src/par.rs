@@ -0,0 +1,20 @@
+//! Parallel iteration over keyed entries, using rayon.
+
+use rayon::prelude::*;
+
+use super::PoolCache;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord + Sync,
+          Value: Sync {
+
+        /// Returns a parallel iterator over the keyed entries of the cache,
+        /// in no particular order. This doesn't count as a use of any entry.
+        pub fn par_iter(&self) -> impl ParallelIterator<Item = (&Key, &Value)> {
+            let entries: Vec<(&Key, &Value)> = self.cache.iter()
+                .filter(|(key, entry)| !self.stale(key, entry))
+                .map(|(key, entry)| (&**key, &entry.val))
+                .collect();
+            entries.into_par_iter()
+        }
+}
@@ -0,0 +1,681 @@
+//! Eviction policies, and the bookkeeping each of them needs.
+
+use std::cmp;
+use std::mem;
+use std::collections::{BTreeMap,VecDeque};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hasher};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use super::{CacheEntry, PoolCache};
+use super::storage::Storage;
+
+type Map<Key, Value, Meta> = Storage<Key, CacheEntry<Value, Meta>>;
+
+// Estimates the cost of rebuilding a value.
+pub type Cost<Key, Value> = dyn Fn(&Key, &Value) -> u64 + Send + Sync;
+
+/// Selects how a PoolCache tracks popularity, and how `take` chooses
+/// which keyed entry to evict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Policy {
+    /// Clock-LFU. Heat saturates at `max_heat`, and `take` sweeps the
+    /// clock, decrementing heat until it finds an entry that has gone
+    /// cold. When most entries are hot, that sweep is long; `LazyClock`
+    /// keeps the same clock as one queue per heat level instead.
+    Clock,
+    /// Uncapped LFU. Heat is a full access counter (`max_heat` is ignored),
+    /// and `take` evicts the entry with the lowest count, preferring the
+    /// oldest entry among ties. Every `period` uses, all counts are halved
+    /// so that past popularity fades over time. Eviction cost is linear in
+    /// the number of keyed entries.
+    Lfu { period: u64 },
+    /// CLOCK-Pro. Entries are split into a hot and a cold set, with heat
+    /// acting as a reference bit (`max_heat` is ignored). New entries start
+    /// cold, and only cold entries are evicted; a cold entry that is used
+    /// again during its test period is promoted to hot. The keys of
+    /// evicted entries are remembered for a while, and re-inserting one of
+    /// them grows the share of the cache given to cold entries.
+    ClockPro,
+    /// 2Q. New entries enter a FIFO queue, where further uses are ignored
+    /// as correlated references. Entries evicted from that queue have their
+    /// keys remembered, and re-inserting one of them places it in a main
+    /// queue managed like `Clock`. `take` evicts from the FIFO queue while
+    /// it holds more than a quarter of the keyed entries, and from the main
+    /// queue otherwise; up to half as many keys as there are entries are
+    /// remembered.
+    TwoQ,
+    /// Sampled eviction. Heat is tracked as for `Clock`, but `take` looks
+    /// at only `samples` randomly chosen entries, evicting the coldest of
+    /// them and cooling the rest. This bounds the work done by `take` on
+    /// very large caches, at the cost of sometimes evicting an entry that
+    /// isn't the coldest overall.
+    Sampled { samples: usize },
+    /// Capped LFU, with a FIFO queue of entries for each heat level. Heat
+    /// saturates at `max_heat` and doesn't decay, and `take` evicts the
+    /// oldest entry at the lowest heat. Uses are applied to the queues
+    /// lazily, when `take` finds an entry sitting below its heat, so the
+    /// work done by `take` is bounded by the number of uses since the last
+    /// one, however large `max_heat` is (each heat level costs a queue).
+    Bucketed,
+    /// Clock with lazy cooling. Heat saturates at `max_heat` as for
+    /// `Clock`, but rather than `take` decrementing the heat of each entry
+    /// it passes, the whole cache ages at once: an entry's heat is its heat
+    /// at its last use, less how much the cache has aged since. Entries are
+    /// queued by the age at which they go cold, and `take` evicts the first
+    /// to go, ageing the cache to it, so its work is bounded by the uses
+    /// since the last one, however hot the cache is. The heat reported for
+    /// an entry is its heat at its last use, and the heat floor is ignored.
+    /// Like `Bucketed`, each heat level costs a queue. This makes it a
+    /// clock split into one queue per heat level: `take` never looks at
+    /// entries hotter than the coldest, so a cache whose entries are mostly
+    /// at `max_heat` is as quick to evict from as any other.
+    LazyClock,
+    /// GreedyDual-Size-Frequency. Heat is a full access counter, as for
+    /// `Lfu` (`max_heat` is ignored), and each entry is valued at its heat
+    /// times its rebuild cost (see `with_cost`), divided by its weight (see
+    /// `with_weigher`), plus the cache's inflation at its last use. `take`
+    /// evicts the entry of least value, preferring the oldest among ties,
+    /// and raises the inflation to that value, so that entries not used
+    /// since fall behind. Large entries that are cheap to rebuild thus go
+    /// before small ones that are expensive to. Without a cost function
+    /// every entry costs 1, and without a weigher every entry weighs 1.
+    /// Eviction cost is linear in the number of keyed entries.
+    Gdsf,
+    /// Weighted random eviction. Heat is tracked as for `Clock`, but `take`
+    /// draws entries at random, evicting each with probability
+    /// `1 / (heat + 1)` and cooling those it spares. Having no order to
+    /// exploit, no pattern of access makes `take` sweep the cache, as a
+    /// scan does with `Clock`: each draw costs the same, and the number of
+    /// draws depends only on how hot the cache is.
+    WeightedRandom,
+}
+
+// Fixed-point scale of `Policy::Gdsf` values, so that entries costing less
+// than they weigh still differ by heat.
+const GDSF_SCALE: u64 = 1 << 10;
+
+// Keys of recently evicted entries, remembered without their values so
+// that a quick re-insert can be recognized.
+pub struct Ghosts<Key> {
+    keys: BTreeMap<Arc<Key>, u64>,
+    order: VecDeque<(u64, Arc<Key>)>,
+    seq: u64,
+}
+
+impl<Key> Ghosts<Key>
+    where Key: PartialOrd + Ord {
+
+        pub fn new() -> Ghosts<Key> {
+            Ghosts{keys: BTreeMap::new(), order: VecDeque::new(), seq: 0}
+        }
+
+        pub fn len(&self) -> usize {
+            self.keys.len()
+        }
+
+        // Remember `key` as the most recently evicted.
+        pub fn push(&mut self, key: Arc<Key>) {
+            self.seq += 1;
+            self.keys.insert(key.clone(), self.seq);
+            self.order.push_back((self.seq, key));
+        }
+
+        // Forget `key`, returning `true` if it was remembered.
+        pub fn remove(&mut self, key: &Key) -> bool {
+            if self.keys.remove(key).is_none() {
+                return false;
+            }
+            // drop stale `order` slots before they pile up.
+            if self.order.len() > 2 * self.keys.len() + 16 {
+                let keys = &self.keys;
+                self.order.retain(|&(seq, ref key)| keys.get(key) == Some(&seq));
+            }
+            true
+        }
+
+        // Forget the least recently evicted key.
+        pub fn pop(&mut self) -> Option<Arc<Key>> {
+            while let Some((seq, key)) = self.order.pop_front() {
+                if self.keys.get(&key) == Some(&seq) {
+                    self.keys.remove(&key);
+                    return Some(key);
+                }
+            }
+            None
+        }
+}
+
+// A small xorshift generator, good enough to pick eviction candidates.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new() -> Rng {
+        // seed from the hasher keys std already randomizes per process.
+        let seed = RandomState::new().build_hasher().finish();
+        Rng(seed | 1)
+    }
+
+    // Returns a value in `0..bound`; `bound` must be non-zero.
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Set the lowest heat that entries decay to as `take` passes them
+        /// over (or, with `Policy::Lfu`, as counts are halved). Entries are
+        /// evicted by `Clock` and `TwoQ` once their heat reaches 0, so with a
+        /// floor above 0 they stay until they expire or are replaced or
+        /// removed, and `take` only evicts entries that started out colder.
+        /// The default floor is 0. `ClockPro` and `Bucketed` ignore it, and
+        /// `Lfu` and `Sampled` still evict the coldest entry they find.
+        pub fn with_heat_floor(mut self, floor: u64) -> PoolCache<Key, Value, Meta> {
+            self.heat_floor = floor;
+            self
+        }
+
+        /// Set a function estimating the cost of rebuilding a value, used to
+        /// choose between equally cold entries: the cheapest is evicted
+        /// first. `Lfu` and `Sampled` consult it when picking the coldest
+        /// entry, as does `take_in`; the clock-based policies evict the
+        /// first cold entry they reach, and don't. `Gdsf` weighs it against
+        /// heat and weight instead.
+        pub fn with_cost<F>(mut self, cost: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&Key, &Value) -> u64 + Send + Sync + 'static {
+            self.cost = Some(Box::new(cost));
+            self
+        }
+
+        /// Prefer the least recently used among equally cold entries when
+        /// choosing which to evict, rather than the first one the policy
+        /// reaches. Uses and inserts are stamped with a cache-wide counter;
+        /// `Lfu` and `Sampled` compare stamps after heat and cost, as does
+        /// `take_in`, and `Clock` and the main queue of `TwoQ` look a few
+        /// entries past each cold entry they reach for an older one that
+        /// would also be evicted. Off by default, as stamping adds an atomic
+        /// increment to every use.
+        pub fn with_recency_tiebreak(mut self) -> PoolCache<Key, Value, Meta> {
+            self.recency = true;
+            self
+        }
+
+        // Stamp `entry` as just used, if tracking recency.
+        pub(crate) fn used(&self, entry: &CacheEntry<Value, Meta>) {
+            if self.recency {
+                let tick = self.ticks.fetch_add(1, Ordering::Relaxed) + 1;
+                entry.last_used.store(tick, Ordering::Relaxed);
+            }
+        }
+
+        // Record a use of `entry`.
+        pub(crate) fn hit(&self, entry: &CacheEntry<Value, Meta>) {
+            self.hit_n(entry, 1)
+        }
+
+        // Record `hits` uses of `entry` at once, in time independent of
+        // `hits`. However many periods of `Policy::Lfu` they span, counts
+        // are halved at most once.
+        pub(crate) fn hit_n(&self, entry: &CacheEntry<Value, Meta>, hits: u64) {
+            if hits == 0 {
+                return;
+            }
+            self.counters.hits(hits);
+            self.used(entry);
+            self.refresh_if_due(entry);
+            #[cfg(feature = "timestamps")]
+            entry.stamps.accessed_at(self.time.now());
+            match self.policy {
+                Policy::Clock | Policy::Sampled { .. } | Policy::Bucketed |
+                Policy::WeightedRandom => {
+                    entry.warm(hits, entry.max_heat.unwrap_or(self.max_heat));
+                }
+                Policy::Lfu { period } => {
+                    entry.warm(hits, u64::MAX);
+                    if self.uses.fetch_add(hits, Ordering::Relaxed) + hits >= period {
+                        self.uses.store(0, Ordering::Relaxed);
+                        for entry in self.cache.values() {
+                            let heat = entry.heat();
+                            entry.set_heat(cmp::min(cmp::max(heat / 2, self.heat_floor), heat));
+                        }
+                    }
+                }
+                Policy::ClockPro => {
+                    entry.inc(1);
+                }
+                Policy::TwoQ => {
+                    if entry.hot {
+                        entry.warm(hits, entry.max_heat.unwrap_or(self.max_heat));
+                    }
+                }
+                Policy::LazyClock => {
+                    let heat = entry.heat().saturating_sub(self.age - entry.aged());
+                    entry.aged.store(self.age, Ordering::Relaxed);
+                    entry.set_heat(cmp::min(heat.saturating_add(hits),
+                                            entry.max_heat.unwrap_or(self.max_heat)));
+                }
+                Policy::Gdsf => {
+                    entry.warm(hits, u64::MAX);
+                    entry.aged.store(self.age, Ordering::Relaxed);
+                }
+            }
+        }
+
+        // Make `entry` the next to go, as far as the policy allows without
+        // moving it on the clock. Cooled entries are evicted whatever the
+        // heat floor.
+        pub(crate) fn cool(&self, entry: &CacheEntry<Value, Meta>) {
+            entry.set_heat(0);
+        }
+
+        // Place the key of a newly inserted `entry` on the clock.
+        pub(crate) fn admit(&mut self, key: Arc<Key>, entry: &mut CacheEntry<Value, Meta>) {
+            match self.policy {
+                Policy::Clock | Policy::Lfu { .. } | Policy::Sampled { .. } | Policy::Gdsf |
+                Policy::WeightedRandom => {
+                    self.clock.push_back(key);
+                }
+                Policy::ClockPro => {
+                    entry.set_heat(0);
+                    if self.ghosts.remove(&key) {
+                        // evicted too soon: give cold entries more room.
+                        self.cold_target = cmp::min(self.cold_target + 1, self.cache.len() + 1);
+                        entry.hot = true;
+                        self.hot.push_back(key);
+                    } else {
+                        entry.test = true;
+                        self.clock.push_back(key);
+                    }
+                }
+                Policy::TwoQ => {
+                    if self.ghosts.remove(&key) {
+                        entry.hot = true;
+                        self.hot.push_back(key);
+                    } else {
+                        self.clock.push_back(key);
+                    }
+                }
+                Policy::Bucketed => {
+                    if self.buckets.is_empty() {
+                        self.buckets.resize_with(self.max_heat as usize + 1, VecDeque::new);
+                    }
+                    let level = self.level(entry.heat());
+                    self.buckets[level].push_back(key);
+                }
+                Policy::LazyClock => {
+                    let slot = self.lazy_slot(entry);
+                    self.queue_lazy(key, slot);
+                }
+            }
+        }
+
+        // Place the key of an `entry` returned by `checkin` back on the
+        // clock, keeping the heat and the hot or test status it left with.
+        pub(crate) fn readmit(&mut self, key: Arc<Key>, entry: &mut CacheEntry<Value, Meta>) {
+            match self.policy {
+                Policy::ClockPro | Policy::TwoQ if entry.hot => self.hot.push_back(key),
+                Policy::ClockPro | Policy::TwoQ => self.clock.push_back(key),
+                _ => self.admit(key, entry),
+            }
+        }
+
+        // Remove the least valuable key from the clock, giving up after
+        // inspecting `max_scan` entries where the policy has to search for
+        // a victim. The entry itself is left in the map for the caller to
+        // remove. Returns `None` if the cache is empty or the search gave up.
+        pub(crate) fn evict_key(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            // cache is empty.
+            if self.cache.is_empty() {
+                return None;
+            }
+            match self.policy {
+                Policy::Clock => {
+                    let key = sweep(&mut self.clock, &self.cache, self.heat_floor, self.recency,
+                                    max_scan, &mut self.swept, &mut self.unchanged);
+                    self.stuck |= key.is_none() && self.unchanged.0 >= self.clock.len();
+                    key
+                }
+                Policy::Lfu { .. } => Some(self.evict_lfu()),
+                Policy::ClockPro => self.evict_clock_pro(max_scan),
+                Policy::TwoQ => self.evict_two_q(max_scan),
+                Policy::Sampled { samples } => Some(self.evict_sampled(samples)),
+                Policy::Bucketed => self.evict_bucketed(max_scan),
+                Policy::LazyClock => self.evict_lazy(max_scan),
+                Policy::Gdsf => Some(self.evict_gdsf()),
+                Policy::WeightedRandom => self.evict_weighted(max_scan),
+            }
+        }
+
+        // Put back the key of an entry that `evict_key` removed from the
+        // clock, but that wasn't evicted after all.
+        pub(crate) fn requeue(&mut self, key: Arc<Key>) {
+            let entry = &self.cache[&key];
+            match self.policy {
+                Policy::Clock | Policy::Lfu { .. } | Policy::Sampled { .. } | Policy::Gdsf |
+                Policy::WeightedRandom => {
+                    self.clock.push_back(key);
+                }
+                Policy::ClockPro | Policy::TwoQ => {
+                    if entry.hot {
+                        self.hot.push_back(key);
+                    } else {
+                        self.clock.push_back(key);
+                    }
+                }
+                Policy::Bucketed => {
+                    let level = self.level(entry.heat());
+                    self.buckets[level].push_back(key);
+                }
+                Policy::LazyClock => {
+                    let slot = self.lazy_slot(entry);
+                    self.queue_lazy(key, slot);
+                }
+            }
+        }
+
+        fn evict_lfu(&mut self) -> Arc<Key> {
+            // `clock` is in insertion order, so the first minimum is also
+            // the oldest.
+            let mut coldest = 0;
+            self.swept += self.clock.len() as u64;
+            for idx in 1..self.clock.len() {
+                if self.colder(&self.clock[idx], &self.clock[coldest]) {
+                    coldest = idx;
+                }
+            }
+            self.clock.remove(coldest).unwrap()
+        }
+
+        fn evict_gdsf(&mut self) -> Arc<Key> {
+            let mut least = (0, self.value(&self.clock[0]));
+            self.swept += self.clock.len() as u64;
+            for idx in 1..self.clock.len() {
+                let value = self.value(&self.clock[idx]);
+                if value < least.1 {
+                    least = (idx, value);
+                }
+            }
+            self.age = cmp::max(self.age, least.1);
+            self.clock.remove(least.0).unwrap()
+        }
+
+        // The `Policy::Gdsf` value of the entry for `key`.
+        fn value(&self, key: &Key) -> u64 {
+            let entry = &self.cache[key];
+            let cost = self.cost.as_ref().map_or(1, |cost| cost(key, &entry.val));
+            let weight = self.weigher.as_ref().map_or(1, |weigher| weigher(&entry.val));
+            let worth = entry.heat().saturating_mul(cost).saturating_mul(GDSF_SCALE);
+            entry.aged().saturating_add(worth / cmp::max(weight, 1) as u64)
+        }
+
+        fn evict_sampled(&mut self, samples: usize) -> Arc<Key> {
+            let mut coldest = self.rng.below(self.clock.len());
+            self.swept += cmp::max(samples, 1) as u64;
+            for _ in 1..samples {
+                let mut idx = self.rng.below(self.clock.len());
+                if self.colder(&self.clock[idx], &self.clock[coldest]) {
+                    mem::swap(&mut idx, &mut coldest);
+                }
+                // cool the candidate that was passed over.
+                if idx != coldest {
+                    self.cache[&self.clock[idx]].dec(self.heat_floor);
+                }
+            }
+            // `clock` order doesn't matter when sampling.
+            self.clock.swap_remove_back(coldest).unwrap()
+        }
+
+        fn evict_weighted(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            for _ in 0..max_scan {
+                let idx = self.rng.below(self.clock.len());
+                self.swept += 1;
+                let heat = self.cache[&self.clock[idx]].heat();
+                let odds = cmp::min(heat.saturating_add(1), usize::MAX as u64) as usize;
+                if self.rng.below(odds) == 0 {
+                    // `clock` order doesn't matter when drawing at random.
+                    return self.clock.swap_remove_back(idx);
+                }
+                self.cache[&self.clock[idx]].dec(self.heat_floor);
+            }
+            None
+        }
+
+        // `clock` holds the cold entries and `hot` the hot ones; an entry's
+        // heat is its reference bit.
+        fn evict_clock_pro(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            for _ in 0..max_scan {
+                let hot_target = self.cache.len().saturating_sub(self.cold_target);
+                if self.clock.is_empty() || self.hot.len() > hot_target {
+                    self.run_hot_hand();
+                    if self.clock.is_empty() {
+                        continue;
+                    }
+                }
+                let key = self.clock.pop_front().unwrap();
+                self.swept += 1;
+                let entry = self.cache.get_mut(&key).unwrap();
+                if entry.heat() > 0 {
+                    entry.set_heat(0);
+                    if entry.test {
+                        // re-used during its test period: promote.
+                        entry.hot = true;
+                        entry.test = false;
+                        self.hot.push_back(key);
+                    } else {
+                        entry.test = true;
+                        self.clock.push_back(key);
+                    }
+                    continue;
+                }
+                if entry.test {
+                    self.ghosts.push(key.clone());
+                    // the oldest test period ends without a re-use.
+                    while self.ghosts.len() > self.cache.len() {
+                        self.ghosts.pop();
+                        self.cold_target = cmp::max(self.cold_target.saturating_sub(1), 1);
+                    }
+                }
+                return Some(key);
+            }
+            None
+        }
+
+        // `clock` is the FIFO queue and `hot` is the main queue.
+        fn evict_two_q(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            let fifo_target = cmp::max(self.cache.len() / 4, 1);
+            if self.hot.is_empty() || self.clock.len() > fifo_target {
+                let key = self.clock.pop_front().unwrap();
+                self.swept += 1;
+                self.ghosts.push(key.clone());
+                while self.ghosts.len() > cmp::max(self.cache.len() / 2, 1) {
+                    self.ghosts.pop();
+                }
+                return Some(key);
+            }
+            let key = sweep(&mut self.hot, &self.cache, self.heat_floor, self.recency, max_scan,
+                            &mut self.swept, &mut self.unchanged);
+            self.stuck |= key.is_none() && self.unchanged.0 >= self.hot.len();
+            key
+        }
+
+        // `buckets[level]` holds the entries that had heat `level` when they
+        // were last placed; uses since then only raise their heat.
+        fn evict_bucketed(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            let mut scanned = 0;
+            for level in 0..self.buckets.len() {
+                while let Some(key) = self.buckets[level].pop_front() {
+                    self.swept += 1;
+                    let actual = self.level(self.cache[&key].heat());
+                    if actual <= level {
+                        return Some(key);
+                    }
+                    // used since it was placed: move it up.
+                    self.buckets[actual].push_back(key);
+                    scanned += 1;
+                    if scanned >= max_scan {
+                        return None;
+                    }
+                }
+            }
+            None
+        }
+
+        // `buckets[slot]` holds the entries that were to go cold at age
+        // `bucket_base + slot` when they were last placed; uses since then
+        // only put that off.
+        fn evict_lazy(&mut self, max_scan: usize) -> Option<Arc<Key>> {
+            let (mut scanned, mut slot) = (0, 0);
+            // entries moved up may add buckets.
+            while slot < self.buckets.len() {
+                while let Some(key) = self.buckets[slot].pop_front() {
+                    self.swept += 1;
+                    let actual = self.lazy_slot(&self.cache[&key]);
+                    if actual <= slot {
+                        // the cache ages until this entry is cold.
+                        self.age = cmp::max(self.age, self.bucket_base + slot as u64);
+                        let expired = cmp::min((self.age - self.bucket_base) as usize, slot);
+                        self.buckets.drain(..expired);
+                        self.bucket_base += expired as u64;
+                        return Some(key);
+                    }
+                    self.queue_lazy(key, actual);
+                    scanned += 1;
+                    if scanned >= max_scan {
+                        return None;
+                    }
+                }
+                slot += 1;
+            }
+            None
+        }
+
+        // The bucket for the age at which `entry` goes cold. Entries that
+        // saturate above `max_heat` share the last bucket.
+        fn lazy_slot(&self, entry: &CacheEntry<Value, Meta>) -> usize {
+            let cold = (entry.aged() + entry.heat()).saturating_sub(self.bucket_base);
+            let last = (self.age - self.bucket_base).saturating_add(self.max_heat);
+            cmp::min(cold, last) as usize
+        }
+
+        fn queue_lazy(&mut self, key: Arc<Key>, slot: usize) {
+            if slot >= self.buckets.len() {
+                self.buckets.resize_with(slot + 1, VecDeque::new);
+            }
+            self.buckets[slot].push_back(key);
+        }
+
+        // Returns `true` if the entry for `key` should be evicted before the
+        // one for `other`: it is colder, or as cold and cheaper to rebuild,
+        // or as cheap and less recently used.
+        pub(crate) fn colder(&self, key: &Key, other: &Key) -> bool {
+            let (entry, other_entry) = (&self.cache[key], &self.cache[other]);
+            let order = entry.heat().cmp(&other_entry.heat()).then_with(|| match self.cost {
+                Some(ref cost) => cost(key, &entry.val).cmp(&cost(other, &other_entry.val)),
+                None => cmp::Ordering::Equal,
+            });
+            match order {
+                cmp::Ordering::Equal => {
+                    self.recency && entry.last_used() < other_entry.last_used()
+                }
+                order => order == cmp::Ordering::Less,
+            }
+        }
+
+        fn level(&self, heat: u64) -> usize {
+            cmp::min(heat, self.max_heat) as usize
+        }
+
+        // Advance the hot hand until one hot entry has been demoted.
+        fn run_hot_hand(&mut self) {
+            while let Some(key) = self.hot.pop_front() {
+                let entry = self.cache.get_mut(&key).unwrap();
+                if entry.heat() > 0 {
+                    entry.set_heat(0);
+                    self.hot.push_back(key);
+                } else {
+                    entry.hot = false;
+                    entry.test = false;
+                    self.clock.push_back(key);
+                    return;
+                }
+            }
+        }
+}
+
+// How far past a cold entry `sweep` looks for a less recently used one.
+const RECENCY_WINDOW: usize = 8;
+
+// loop over the elements in `clock`, decrementing heat (down to `floor`)
+// until we find an eligible value to evict, or have looked at `max_scan`
+// elements, or every element is held at the floor. With `recency`, the
+// least recently used of the eligible value and those just behind it is
+// evicted instead. Each element looked at is counted in `swept`.
+// `unchanged` holds the number of elements visited in a row whose heat
+// couldn't drop, and the length of `clock` when they were, so that the run
+// carries over from one bounded sweep to the next; it starts over when
+// some heat drops or the clock changes length. Once the run covers the
+// whole clock, every element is held at the floor.
+fn sweep<Key, Value, Meta>(clock: &mut VecDeque<Arc<Key>>, cache: &Map<Key, Value, Meta>,
+                           floor: u64, recency: bool, max_scan: usize, swept: &mut u64,
+                           unchanged: &mut (usize, usize))
+                           -> Option<Arc<Key>>
+    where Key: Ord {
+    if unchanged.1 != clock.len() {
+        *unchanged = (0, clock.len());
+    }
+    for _ in 0..max_scan {
+        if unchanged.0 >= clock.len() {
+            return None;
+        }
+        let key = clock.pop_front().unwrap();
+        *swept += 1;
+        let entry = &cache[&key];
+        let heat = entry.heat();
+        if heat == 0 || entry.dec(floor) == 0 {
+            // eligible element.
+            *unchanged = (0, clock.len());
+            if recency {
+                return Some(least_recent(clock, cache, floor, key));
+            }
+            return Some(key);
+        }
+        unchanged.0 = if entry.heat() == heat { unchanged.0 + 1 } else { 0 };
+        // non-zero heat, keep looping.
+        clock.push_back(key);
+    }
+    None
+}
+
+// Returns whichever of `key` and the next few elements on `clock` that would
+// be evicted when reached was used least recently. If that isn't `key`, it
+// is removed from the clock and `key` goes back to the front, to be evicted
+// next.
+fn least_recent<Key, Value, Meta>(clock: &mut VecDeque<Arc<Key>>, cache: &Map<Key, Value, Meta>,
+                                  floor: u64, key: Arc<Key>) -> Arc<Key>
+    where Key: Ord {
+    let mut oldest = None;
+    let mut oldest_use = cache[&key].last_used();
+    for (idx, other) in clock.iter().take(RECENCY_WINDOW).enumerate() {
+        let entry = &cache[other];
+        let eligible = entry.heat() == 0 || (floor == 0 && entry.heat() == 1);
+        if eligible && entry.last_used() < oldest_use {
+            oldest = Some(idx);
+            oldest_use = entry.last_used();
+        }
+    }
+    match oldest {
+        Some(idx) => {
+            let older = clock.remove(idx).unwrap();
+            clock.push_front(key);
+            older
+        }
+        None => key,
+    }
+}
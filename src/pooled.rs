@@ -0,0 +1,243 @@
+//! Objects checked out of the pool, and accounting for them.
+
+use std::ops::{Deref,DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize,Ordering};
+#[cfg(feature = "debug-leaks")]
+use std::collections::BTreeMap;
+#[cfg(feature = "debug-leaks")]
+use std::panic::Location;
+#[cfg(feature = "debug-leaks")]
+use std::sync::Mutex;
+#[cfg(feature = "debug-leaks")]
+use std::sync::atomic::AtomicU64;
+#[cfg(feature = "debug-leaks")]
+use std::time::{Duration,Instant};
+
+use super::PoolCache;
+
+// Called with the outstanding count when it passes the warning threshold.
+pub type Warning = dyn Fn(usize) + Send + Sync;
+
+/// An object that was checked out with `take_pooled` and never handed
+/// back, as reported with the `debug-leaks` feature.
+#[cfg(feature = "debug-leaks")]
+#[derive(Clone, Debug)]
+pub struct Leak {
+    /// Where the object was taken.
+    pub origin: &'static Location<'static>,
+    /// How long ago it was taken.
+    pub age: Duration,
+}
+
+#[cfg(feature = "debug-leaks")]
+type Reporter = dyn Fn(&Leak) + Send + Sync;
+
+// State shared between a PoolCache and the objects checked out of it.
+pub struct Tracker {
+    outstanding: AtomicUsize,
+    #[cfg(feature = "debug-leaks")]
+    live: Mutex<BTreeMap<u64, (&'static Location<'static>, Instant)>>,
+    #[cfg(feature = "debug-leaks")]
+    next_id: AtomicU64,
+    #[cfg(feature = "debug-leaks")]
+    reporter: Mutex<Option<Box<Reporter>>>,
+}
+
+impl Tracker {
+    pub fn new() -> Tracker {
+        Tracker{
+            outstanding: AtomicUsize::new(0),
+            #[cfg(feature = "debug-leaks")]
+            live: Mutex::new(BTreeMap::new()),
+            #[cfg(feature = "debug-leaks")]
+            next_id: AtomicU64::new(0),
+            #[cfg(feature = "debug-leaks")]
+            reporter: Mutex::new(None)}
+    }
+}
+
+/// An object checked out of a PoolCache with `take_pooled`.
+///
+/// The object counts as outstanding until it is handed back with
+/// `put_pooled` or `insert_pooled`, detached, or dropped. With the
+/// `debug-leaks` feature, dropping it is reported as a leak, along with
+/// where it was taken.
+pub struct Pooled<Value> {
+    val: Option<Value>,
+    tracker: Arc<Tracker>,
+    #[cfg(feature = "debug-leaks")]
+    id: u64,
+}
+
+impl<Value> Pooled<Value> {
+    /// Take ownership of the object for good. It no longer counts as
+    /// outstanding, and isn't reported as a leak.
+    pub fn detach(mut self) -> Value {
+        self.settle();
+        self.val.take().unwrap()
+    }
+
+    // Stop counting this object as outstanding, returning where and when
+    // it was taken.
+    #[cfg(feature = "debug-leaks")]
+    fn settle(&mut self) -> Option<(&'static Location<'static>, Instant)> {
+        self.tracker.outstanding.fetch_sub(1, Ordering::Relaxed);
+        self.tracker.live.lock().unwrap().remove(&self.id)
+    }
+
+    #[cfg(not(feature = "debug-leaks"))]
+    fn settle(&mut self) {
+        self.tracker.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<Value> Deref for Pooled<Value> {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        self.val.as_ref().unwrap()
+    }
+}
+
+impl<Value> DerefMut for Pooled<Value> {
+    fn deref_mut(&mut self) -> &mut Value {
+        self.val.as_mut().unwrap()
+    }
+}
+
+impl<Value> Drop for Pooled<Value> {
+    fn drop(&mut self) {
+        if self.val.is_none() {
+            return;
+        }
+        #[cfg(feature = "debug-leaks")]
+        {
+            if let Some((origin, taken)) = self.settle() {
+                let leak = Leak{origin, age: taken.elapsed()};
+                if let Some(ref reporter) = *self.tracker.reporter.lock().unwrap() {
+                    reporter(&leak);
+                } else {
+                    #[cfg(feature = "log")]
+                    log::warn!("poolcache: object taken at {} was dropped without being returned",
+                               leak.origin);
+                }
+            }
+        }
+        #[cfg(not(feature = "debug-leaks"))]
+        self.settle();
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Like `take`, but returns the object wrapped in a `Pooled`, and
+        /// counts it as outstanding until it is handed back.
+        #[track_caller]
+        pub fn take_pooled(&mut self) -> Option<Pooled<Value>> {
+            let val = self.take()?;
+            Some(self.check_out(val))
+        }
+
+        /// Like `take_pooled`, but if the PoolCache contains no items, a new
+        /// object is created with `create` instead, as long as that doesn't
+        /// take the number of live objects (keyed, free, tagged and
+        /// outstanding) past the limit set with `with_max_objects`. Returns
+        /// `None` if the limit has been reached. Objects `take` can't hand
+        /// out, such as those of protected entries, still count.
+        #[track_caller]
+        pub fn take_or_create<F>(&mut self, create: F) -> Option<Pooled<Value>>
+            where F: FnOnce() -> Value {
+            if let Some(val) = self.take() {
+                return Some(self.check_out(val));
+            }
+            if self.outstanding() + self.held() >= self.max_objects {
+                return None;
+            }
+            Some(self.check_out(create()))
+        }
+
+        // Returns the number of objects held by the cache, keyed or not.
+        fn held(&self) -> usize {
+            let keyed: usize = self.cache.values().map(|entry| 1 + entry.spares.len()).sum();
+            keyed + self.freelist.len() + self.tagged_total()
+        }
+
+        /// Limit the number of live objects `take_or_create` will create
+        /// new objects up to. Objects added with `put` or `insert` aren't
+        /// limited, but count towards the limit. For `get_or_intern`, this
+        /// limits the number of keyed entries instead.
+        pub fn with_max_objects(mut self, max_objects: usize) -> PoolCache<Key, Value, Meta> {
+            self.max_objects = max_objects;
+            self
+        }
+
+        /// Hand a checked out object back to the pool, like `put`.
+        pub fn put_pooled(&mut self, val: Pooled<Value>) {
+            self.put(val.detach())
+        }
+
+        /// Hand a checked out object back to the cache associated with
+        /// `key`, like `insert`.
+        pub fn insert_pooled(&mut self, key: Key, val: Pooled<Value>) {
+            self.insert(key, val.detach())
+        }
+
+        /// Returns the number of objects checked out with `take_pooled`
+        /// that haven't been handed back, detached or dropped.
+        pub fn outstanding(&self) -> usize {
+            self.tracker.outstanding.load(Ordering::Relaxed)
+        }
+
+        /// Call `warning` with the outstanding count whenever it rises above
+        /// `threshold`, e.g. to flag objects that never come back.
+        pub fn with_outstanding_warning<F>(mut self, threshold: usize, warning: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(usize) + Send + Sync + 'static {
+            self.outstanding_warning = Some((threshold, Box::new(warning)));
+            self
+        }
+
+        #[track_caller]
+        fn check_out(&mut self, val: Value) -> Pooled<Value> {
+            let outstanding = self.tracker.outstanding.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some((threshold, ref warning)) = self.outstanding_warning {
+                if outstanding == threshold + 1 {
+                    warning(outstanding);
+                }
+            }
+            #[cfg(feature = "debug-leaks")]
+            let id = {
+                let id = self.tracker.next_id.fetch_add(1, Ordering::Relaxed);
+                let origin = Location::caller();
+                self.tracker.live.lock().unwrap().insert(id, (origin, Instant::now()));
+                id
+            };
+            Pooled{
+                val: Some(val),
+                tracker: self.tracker.clone(),
+                #[cfg(feature = "debug-leaks")]
+                id}
+        }
+
+        /// Report objects dropped without being handed back to `reporter`.
+        /// Without one, they are logged as warnings if the `log` feature is
+        /// on, and otherwise go unreported, though `overdue` still lists
+        /// objects that are out for too long.
+        #[cfg(feature = "debug-leaks")]
+        pub fn with_leak_reporter<F>(self, reporter: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&Leak) + Send + Sync + 'static {
+            *self.tracker.reporter.lock().unwrap() = Some(Box::new(reporter));
+            self
+        }
+
+        /// Returns the objects that have been checked out for at least
+        /// `age`, and where they were taken.
+        #[cfg(feature = "debug-leaks")]
+        pub fn overdue(&self, age: Duration) -> Vec<Leak> {
+            self.tracker.live.lock().unwrap().values().filter_map(|&(origin, taken)| {
+                let elapsed = taken.elapsed();
+                if elapsed >= age { Some(Leak{origin, age: elapsed}) } else { None }
+            }).collect()
+        }
+}
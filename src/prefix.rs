@@ -0,0 +1,78 @@
+//! Operations on all the keys that start with a prefix, for caches keyed by
+//! paths or other hierarchical strings.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::{PoolCache,RemovalCause};
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord + AsRef<str> {
+
+        /// Returns the keys and values of the live entries whose keys start
+        /// with `prefix`, in key order, without counting a use of them. To
+        /// match a whole directory `a/b` of a path-keyed cache, rather than
+        /// `a/bc` too, use the prefix `a/b/`. Keys must be ordered like the
+        /// strings they hold, as `String`s are; the default storage then
+        /// only visits the matching entries, and other storages all of
+        /// them.
+        pub fn iter_prefix<'a>(&'a self, prefix: &'a Key)
+                               -> impl Iterator<Item = (&'a Key, &'a Value)> {
+            self.cache.prefixed(prefix)
+                .filter(move |(key, entry)| !self.stale(key, entry))
+                .map(|(key, entry)| (&**key, &entry.val))
+        }
+
+        /// Remove every entry whose key starts with `prefix` (see
+        /// `iter_prefix`), returning their keys and values. Expired entries
+        /// are dropped rather than returned. Listeners are told the
+        /// entries were `Invalidated`.
+        pub fn remove_prefix(&mut self, prefix: &Key) -> Vec<(Key, Value)>
+            where Key: Clone {
+            let keys = self.prefixed_keys(prefix);
+            let mut removed = Vec::with_capacity(keys.len());
+            for (key, val) in self.remove_entries(keys.live, RemovalCause::Invalidated) {
+                // the key is still shared if the policy remembers it.
+                let key = Arc::try_unwrap(key).unwrap_or_else(|key| (*key).clone());
+                removed.push((key, val));
+            }
+            for (_, val) in self.remove_entries(keys.expired, RemovalCause::Invalidated) {
+                self.discard(val);
+            }
+            removed
+        }
+
+        /// Remove every entry whose key starts with `prefix` (see
+        /// `iter_prefix`), moving their values to the pool, like
+        /// `clear_namespace`. Returns the number of entries removed.
+        pub fn invalidate_prefix(&mut self, prefix: &Key) -> usize {
+            let keys = self.prefixed_keys(prefix);
+            let count = keys.len();
+            self.invalidate(keys.live.into_iter().chain(keys.expired).collect());
+            count
+        }
+
+        fn prefixed_keys(&self, prefix: &Key) -> Prefixed<Key> {
+            let mut keys = Prefixed{live: BTreeSet::new(), expired: BTreeSet::new()};
+            for (key, entry) in self.cache.prefixed(prefix) {
+                if self.stale(key, entry) {
+                    keys.expired.insert(key.clone());
+                } else {
+                    keys.live.insert(key.clone());
+                }
+            }
+            keys
+        }
+}
+
+// The stored keys starting with a prefix.
+struct Prefixed<Key> {
+    live: BTreeSet<Arc<Key>>,
+    expired: BTreeSet<Arc<Key>>,
+}
+
+impl<Key> Prefixed<Key> {
+    fn len(&self) -> usize {
+        self.live.len() + self.expired.len()
+    }
+}
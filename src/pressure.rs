@@ -0,0 +1,282 @@
+//! Releasing memory on demand, either directly or in response to a signal
+//! from outside the cache.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::iter;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64,Ordering};
+
+use super::{PoolCache,RemovalCause};
+use super::policy::Ghosts;
+
+// Inserts between adjustments of an adaptive budget.
+const PERIOD: u64 = 1000;
+// The shares of inserts that must be of recently dropped keys for an
+// adaptive budget to grow, or may be for it to shrink.
+const GROW: f64 = 0.02;
+const SHRINK: f64 = 0.005;
+
+/// A handle for asking a PoolCache to shed objects, from any thread.
+///
+/// Hook this up to whatever reports memory pressure on the host (cgroup
+/// notifications, allocator callbacks, ...). Requests are applied the
+/// next time the cache is modified, since the cache itself can't be
+/// reached from other threads.
+#[derive(Clone, Debug)]
+pub struct PressureHandle {
+    // bits of the largest fraction requested since the last shed.
+    pending: Arc<AtomicU64>,
+}
+
+impl PressureHandle {
+    /// Ask the cache to `shed(fraction)`. If several requests arrive
+    /// before the cache gets to them, only the largest is applied.
+    pub fn signal(&self, fraction: f64) {
+        let mut current = self.pending.load(Ordering::Relaxed);
+        while fraction > f64::from_bits(current) {
+            match self.pending.compare_exchange_weak(
+                current, fraction.to_bits(), Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// Which objects a PoolCache releases first when shedding, or when it is
+/// over its byte budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Reclaim {
+    /// Release unkeyed objects (untagged, then tagged) before evicting keyed
+    /// entries, since free objects carry no hit-rate value.
+    #[default]
+    FreeFirst,
+    /// Evict keyed entries before releasing unkeyed objects, keeping the
+    /// pool stocked for `take` at the expense of the cache.
+    KeyedFirst,
+}
+
+// Estimates the memory held by a value, in bytes.
+pub type Weigher<Value> = dyn Fn(&Value) -> usize + Send + Sync;
+
+pub struct Pressure {
+    handle: Option<PressureHandle>,
+    // the most bytes (as measured by the weigher) the cache may hold.
+    budget: Option<usize>,
+    order: Reclaim,
+}
+
+// The state of `with_adaptive_budget`. The keys of entries dropped to fit
+// the budget are remembered, and how often they come back estimates what
+// a larger budget would gain.
+pub struct Adaptive<Key> {
+    min: usize,
+    max: usize,
+    ghosts: Ghosts<Key>,
+    inserts: u64,
+    // inserts of remembered keys this period.
+    returns: u64,
+}
+
+impl Pressure {
+    pub fn new() -> Pressure {
+        Pressure{handle: None, budget: None, order: Reclaim::FreeFirst}
+    }
+
+    // Returns the pending request, if any, and clears it.
+    fn pending(&self) -> f64 {
+        match self.handle {
+            Some(ref handle) => f64::from_bits(handle.pending.swap(0, Ordering::Relaxed)),
+            None => 0.0,
+        }
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Set the function used to estimate the memory held by a value, in
+        /// bytes. By default, only the inline size of `Value` is counted,
+        /// so values that own heap memory should provide their own.
+        pub fn with_weigher<F>(mut self, weigher: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&Value) -> usize + Send + Sync + 'static {
+            self.weigher = Some(Box::new(weigher));
+            self
+        }
+
+        /// Limit the memory held by the PoolCache to `bytes`, as measured by
+        /// the weigher. Whenever an object is added and the total exceeds the
+        /// budget, objects are released in the order set by
+        /// `with_reclaim_order` until it fits again. Checking the budget
+        /// weighs every object held, so it takes time linear in the size of
+        /// the cache.
+        pub fn with_byte_budget(mut self, bytes: usize) -> PoolCache<Key, Value, Meta> {
+            self.pressure.budget = Some(bytes);
+            self
+        }
+
+        /// Like `with_byte_budget`, but the budget moves within
+        /// `min..=max` bytes, starting at `max`, according to the estimated
+        /// value of more memory, for caches that share memory with other
+        /// components. The keys of entries dropped to fit the budget are
+        /// remembered, as many as there are keyed entries, and every 1000
+        /// inserts the budget grows by a sixteenth of the range if more
+        /// than 2% of them were of remembered keys (hits a larger budget
+        /// would have had), and shrinks by as much if fewer than 0.5% were.
+        pub fn with_adaptive_budget(mut self, min: usize, max: usize)
+                                    -> PoolCache<Key, Value, Meta> {
+            let max = cmp::max(min, max);
+            self.pressure.budget = Some(max);
+            self.adaptive = Some(Adaptive{min, max, ghosts: Ghosts::new(), inserts: 0, returns: 0});
+            self
+        }
+
+        /// Returns the current byte budget, if there is one.
+        pub fn byte_budget(&self) -> Option<usize> {
+            self.pressure.budget
+        }
+
+        /// Set which objects are released first by `shed`, `evict_bytes`
+        /// and the byte budget. The default is `Reclaim::FreeFirst`.
+        pub fn with_reclaim_order(mut self, order: Reclaim) -> PoolCache<Key, Value, Meta> {
+            self.pressure.order = order;
+            self
+        }
+
+        /// Returns the memory held by the PoolCache, keyed and unkeyed, in
+        /// bytes as measured by the weigher.
+        pub fn weight(&self) -> usize {
+            let keyed = self.cache.values()
+                .flat_map(|entry| iter::once(&entry.val).chain(&entry.spares))
+                .map(|val| self.weigh(val));
+            let free = self.freelist.iter().chain(self.tagged.values().flatten())
+                .map(|val| self.weigh(val));
+            keyed.chain(free).sum()
+        }
+
+        /// Returns a handle that can be used to request shedding from other
+        /// threads.
+        pub fn pressure_handle(&mut self) -> PressureHandle {
+            self.pressure.handle.get_or_insert_with(|| {
+                PressureHandle{pending: Arc::new(AtomicU64::new(0))}
+            }).clone()
+        }
+
+        /// Drop `fraction` (between 0 and 1) of the objects held by the
+        /// PoolCache. By default, unkeyed objects (untagged, then tagged) go
+        /// first, since they carry no hit-rate value, followed by keyed
+        /// entries in eviction order; see `with_reclaim_order`. Returns the
+        /// number of objects dropped.
+        pub fn shed(&mut self, fraction: f64) -> usize {
+            let held = self.freelist.len() + self.tagged_total() + self.cache.len();
+            let count = cmp::min((held as f64 * fraction).ceil() as usize, held);
+            for _ in 0..count {
+                self.release();
+            }
+            count
+        }
+
+        /// Drop objects, in the same order as `shed`, until at least
+        /// `target` bytes (as measured by the weigher) have been released
+        /// or the PoolCache is empty. Returns the number of bytes released.
+        pub fn evict_bytes(&mut self, target: usize) -> usize {
+            let mut released = 0;
+            while released < target {
+                match self.release() {
+                    Some(bytes) => released += bytes,
+                    None => break,
+                }
+            }
+            released
+        }
+
+        // Apply any shedding requested through a `PressureHandle`.
+        pub(crate) fn relieve_pressure(&mut self) {
+            let fraction = self.pressure.pending();
+            if fraction > 0.0 {
+                self.shed(fraction);
+            }
+        }
+
+        // Count an insert of `key` for an adaptive budget, adjusting the
+        // budget at the end of each period.
+        pub(crate) fn adapt(&mut self, key: &Key) {
+            let adaptive = match self.adaptive {
+                Some(ref mut adaptive) => adaptive,
+                None => return,
+            };
+            adaptive.inserts += 1;
+            if adaptive.ghosts.remove(key) {
+                adaptive.returns += 1;
+            }
+            if adaptive.inserts < PERIOD {
+                return;
+            }
+            let share = adaptive.returns as f64 / adaptive.inserts as f64;
+            let step = cmp::max((adaptive.max - adaptive.min) / 16, 1);
+            let budget = self.pressure.budget.unwrap_or(adaptive.max);
+            let budget = if share > GROW {
+                budget.saturating_add(step)
+            } else if share < SHRINK {
+                budget.saturating_sub(step)
+            } else {
+                budget
+            };
+            self.pressure.budget = Some(budget.clamp(adaptive.min, adaptive.max));
+            adaptive.inserts = 0;
+            adaptive.returns = 0;
+        }
+
+        // Release objects until the cache is within its byte budget, if it
+        // has one.
+        pub(crate) fn enforce_budget(&mut self) {
+            let budget = match self.pressure.budget {
+                Some(budget) => budget,
+                None => return,
+            };
+            let mut weight = self.weight();
+            while weight > budget {
+                match self.release() {
+                    Some(bytes) => weight -= cmp::min(bytes, weight),
+                    None => break,
+                }
+            }
+        }
+
+        pub(crate) fn weigh(&self, val: &Value) -> usize {
+            match self.weigher {
+                Some(ref weigher) => weigher(val),
+                None => mem::size_of::<Value>(),
+            }
+        }
+
+        // Drop a single object, returning its weight.
+        fn release(&mut self) -> Option<usize> {
+            let val = match self.pressure.order {
+                Reclaim::FreeFirst => self.release_free().or_else(|| self.release_keyed()),
+                Reclaim::KeyedFirst => self.release_keyed().or_else(|| self.release_free()),
+            }?;
+            let bytes = self.weigh(&val);
+            self.discard(val);
+            Some(bytes)
+        }
+
+        // Shed a keyed entry, remembering its key for an adaptive budget.
+        fn release_keyed(&mut self) -> Option<Value> {
+            let (key, val) = self.evict(usize::MAX, RemovalCause::Shed)?;
+            if let Some(ref mut adaptive) = self.adaptive {
+                adaptive.ghosts.push(key);
+                while adaptive.ghosts.len() > cmp::max(self.cache.len(), 1) {
+                    adaptive.ghosts.pop();
+                }
+            }
+            Some(val)
+        }
+
+        fn release_free(&mut self) -> Option<Value> {
+            self.freelist.pop_back()
+                .or_else(|| self.tagged.values_mut().find_map(VecDeque::pop_back))
+        }
+}
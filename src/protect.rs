@@ -0,0 +1,54 @@
+//! Entries that must not be evicted: protected keys, for entries the
+//! application can't do without, and a filter for entries that can't be
+//! reused for the moment.
+
+use super::PoolCache;
+
+// Decides whether an entry may be evicted.
+pub type Filter<Key, Value> = dyn Fn(&Key, &Value) -> bool + Send + Sync;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Exempt the entry for `key` from eviction, whether or not it is
+        /// cached yet. `take` and shedding pass protected entries over, and
+        /// return `None` if only protected entries remain; they still leave
+        /// the cache when replaced, removed or expired.
+        pub fn protect(&mut self, key: Key) {
+            self.protected.insert(key);
+        }
+
+        /// Make the entry for `key` evictable again. Returns `true` if the
+        /// key was protected.
+        pub fn unprotect(&mut self, key: &Key) -> bool {
+            self.protected.remove(key)
+        }
+
+        /// Set a function deciding whether an entry may be evicted, e.g. so
+        /// that buffers still in use elsewhere aren't reused. Entries it
+        /// returns `false` for are passed over like protected ones, and
+        /// `take` returns `None` if all the remaining entries are refused.
+        /// It is called for each candidate, every time `take` looks for an
+        /// entry to evict.
+        pub fn with_eviction_filter<F>(mut self, filter: F) -> PoolCache<Key, Value, Meta>
+            where F: Fn(&Key, &Value) -> bool + Send + Sync + 'static {
+            self.eviction_filter = Some(Box::new(filter));
+            self
+        }
+
+        /// Returns `true` if `key` is protected from eviction.
+        pub fn is_protected(&self, key: &Key) -> bool {
+            self.protected.contains(key)
+        }
+
+        // Returns `true` if the entry for `key` may be evicted.
+        pub(crate) fn evictable(&self, key: &Key) -> bool {
+            if self.protected.contains(key) {
+                return false;
+            }
+            match self.eviction_filter {
+                Some(ref filter) => filter(key, &self.cache[key].val),
+                None => true,
+            }
+        }
+}
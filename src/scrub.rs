@@ -0,0 +1,50 @@
+//! Clearing the cache, and scrubbing the values it drops, for values that
+//! hold secrets.
+
+use std::collections::BTreeSet;
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use super::{PoolCache,RemovalCause};
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Zero the memory of each value the cache drops, rather than keeps
+        /// for reuse: keyed entries and unkeyed objects shed under memory
+        /// pressure or to fit the byte budget, entries evicted by
+        /// `get_or_intern`, and everything dropped by `clear`. Values the
+        /// cache hands back, or still holds when it is itself dropped,
+        /// aren't scrubbed.
+        #[cfg(feature = "zeroize")]
+        pub fn with_zeroize(mut self) -> PoolCache<Key, Value, Meta>
+            where Value: Zeroize {
+            self.scrub = Some(<Value as Zeroize>::zeroize);
+            self
+        }
+
+        /// Drop every keyed entry (notifying the listener, if any, with
+        /// `RemovalCause::Invalidated`) and every unkeyed object, tagged or
+        /// not.
+        pub fn clear(&mut self) {
+            let keys: BTreeSet<_> = self.cache.keys().cloned().collect();
+            for (_, val) in self.remove_entries(keys, RemovalCause::Invalidated) {
+                self.discard(val);
+            }
+            let free: Vec<Value> = self.freelist.drain(..)
+                .chain(self.tagged.values_mut().flat_map(|free| free.drain(..)))
+                .collect();
+            for val in free {
+                self.discard(val);
+            }
+            self.tagged.clear();
+        }
+
+        // Drop `val`, scrubbing it first if asked to.
+        pub(crate) fn discard(&self, mut val: Value) {
+            if let Some(scrub) = self.scrub {
+                scrub(&mut val);
+            }
+        }
+}
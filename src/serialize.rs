@@ -0,0 +1,331 @@
+//! Serializing a PoolCache with serde, either as just its keyed contents or
+//! as its full state. The wrapper types pick which: serialize a
+//! `Contents(&cache)` or `State(&cache)`, and deserialize a
+//! `Contents<PoolCache<..>>` or `State<PoolCache<..>>`.
+
+use std::collections::{BTreeMap,VecDeque};
+use std::iter;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use serde::{Deserialize,Deserializer,Serialize,Serializer};
+use serde::de::Error;
+
+use super::{CacheEntry,PoolCache,Policy};
+
+/// Serializes a PoolCache as a map from keys to values, leaving out heat,
+/// eviction order, metadata and unkeyed objects. The result is portable
+/// between policies and versions of the crate, and deserializes into a
+/// `PoolCache::default()` holding the same entries, all equally cold.
+pub struct Contents<C>(pub C);
+
+/// Serializes the full state of a PoolCache: its policy and `max_heat`,
+/// how far it has aged, the heat, metadata and stacked objects (see
+/// `put_for`) of every entry, the order of the eviction queues, and the
+/// unkeyed objects, so that a deserialized cache evicts in the same order.
+/// Remembered keys of evicted entries, namespaces, quotas, expiry
+/// deadlines, per-entry heat caps and settings made with `with_*` methods
+/// aren't saved.
+pub struct State<C>(pub C);
+
+impl<Key, Value, Meta> Serialize for Contents<&PoolCache<Key, Value, Meta>>
+    where Key: PartialOrd + Ord + Serialize,
+          Value: Serialize {
+
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.live_entries())
+        }
+}
+
+impl<'de, Key, Value, Meta> Deserialize<'de> for Contents<PoolCache<Key, Value, Meta>>
+    where Key: PartialOrd + Ord + Deserialize<'de>,
+          Value: Deserialize<'de> {
+
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let entries = BTreeMap::<Key, Value>::deserialize(deserializer)?;
+            let mut cache = PoolCache::default();
+            for (key, val) in entries {
+                cache.insert(key, val);
+            }
+            Ok(Contents(cache))
+        }
+}
+
+// The layout of a serialized `State`. Each queue lists its entries in
+// order; the clock comes first, then the hot queue, then the buckets.
+#[derive(Serialize)]
+struct StateRef<'a, Key, Value, Meta> {
+    max_heat: u64,
+    policy: Policy,
+    cold_target: usize,
+    age: u64,
+    bucket_base: u64,
+    uses: u64,
+    queues: Vec<Vec<EntryRef<'a, Key, Value, Meta>>>,
+    freelist: &'a VecDeque<Value>,
+    tagged: &'a BTreeMap<String, VecDeque<Value>>,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a, Key, Value, Meta> {
+    key: &'a Key,
+    val: &'a Value,
+    spares: &'a Vec<Value>,
+    meta: &'a Option<Meta>,
+    heat: u64,
+    aged: u64,
+    hot: bool,
+    test: bool,
+}
+
+// The owned counterparts of the above, for deserializing.
+#[derive(Deserialize)]
+struct StateRepr<Key, Value, Meta> {
+    max_heat: u64,
+    policy: Policy,
+    cold_target: usize,
+    age: u64,
+    bucket_base: u64,
+    uses: u64,
+    queues: Vec<Vec<EntryRepr<Key, Value, Meta>>>,
+    freelist: VecDeque<Value>,
+    tagged: BTreeMap<String, VecDeque<Value>>,
+}
+
+#[derive(Deserialize)]
+struct EntryRepr<Key, Value, Meta> {
+    key: Key,
+    val: Value,
+    spares: Vec<Value>,
+    meta: Option<Meta>,
+    heat: u64,
+    aged: u64,
+    hot: bool,
+    test: bool,
+}
+
+// The layout of a `State` in snapshots of version 3 and earlier, which
+// didn't save how far the cache had aged. Only snapshots are read in old
+// layouts.
+#[cfg(feature = "snapshot")]
+#[derive(Deserialize)]
+struct StateReprV3<Key, Value, Meta> {
+    max_heat: u64,
+    policy: Policy,
+    cold_target: usize,
+    queues: Vec<Vec<EntryReprV3<Key, Value, Meta>>>,
+    freelist: VecDeque<Value>,
+    tagged: BTreeMap<String, VecDeque<Value>>,
+}
+
+#[cfg(feature = "snapshot")]
+#[derive(Deserialize)]
+struct EntryReprV3<Key, Value, Meta> {
+    key: Key,
+    val: Value,
+    meta: Option<Meta>,
+    heat: u64,
+    hot: bool,
+    test: bool,
+}
+
+// The layout of a `State` in snapshots of version 4, which didn't save
+// the objects stacked under each key.
+#[cfg(feature = "snapshot")]
+#[derive(Deserialize)]
+struct StateReprV4<Key, Value, Meta> {
+    max_heat: u64,
+    policy: Policy,
+    cold_target: usize,
+    age: u64,
+    bucket_base: u64,
+    uses: u64,
+    queues: Vec<Vec<EntryReprV4<Key, Value, Meta>>>,
+    freelist: VecDeque<Value>,
+    tagged: BTreeMap<String, VecDeque<Value>>,
+}
+
+#[cfg(feature = "snapshot")]
+#[derive(Deserialize)]
+struct EntryReprV4<Key, Value, Meta> {
+    key: Key,
+    val: Value,
+    meta: Option<Meta>,
+    heat: u64,
+    aged: u64,
+    hot: bool,
+    test: bool,
+}
+
+// Deserializes a cache from the layout of a `State` in snapshots of
+// version 3 and earlier, as if it had never aged.
+#[cfg(feature = "snapshot")]
+pub(crate) struct StateV3<C>(pub C);
+
+// Deserializes a cache from the layout of a `State` in snapshots of
+// version 4, with nothing stacked under its keys.
+#[cfg(feature = "snapshot")]
+pub(crate) struct StateV4<C>(pub C);
+
+#[cfg(feature = "snapshot")]
+impl<Key, Value, Meta> From<StateReprV4<Key, Value, Meta>> for StateRepr<Key, Value, Meta> {
+    fn from(state: StateReprV4<Key, Value, Meta>) -> StateRepr<Key, Value, Meta> {
+        let queues = state.queues.into_iter().map(|entries| entries.into_iter().map(|entry| {
+            EntryRepr{
+                key: entry.key,
+                val: entry.val,
+                spares: Vec::new(),
+                meta: entry.meta,
+                heat: entry.heat,
+                aged: entry.aged,
+                hot: entry.hot,
+                test: entry.test}
+        }).collect()).collect();
+        StateRepr{
+            max_heat: state.max_heat,
+            policy: state.policy,
+            cold_target: state.cold_target,
+            age: state.age,
+            bucket_base: state.bucket_base,
+            uses: state.uses,
+            queues,
+            freelist: state.freelist,
+            tagged: state.tagged}
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<Key, Value, Meta> From<StateReprV3<Key, Value, Meta>> for StateRepr<Key, Value, Meta> {
+    fn from(state: StateReprV3<Key, Value, Meta>) -> StateRepr<Key, Value, Meta> {
+        let queues = state.queues.into_iter().map(|entries| entries.into_iter().map(|entry| {
+            EntryRepr{
+                key: entry.key,
+                val: entry.val,
+                spares: Vec::new(),
+                meta: entry.meta,
+                heat: entry.heat,
+                aged: 0,
+                hot: entry.hot,
+                test: entry.test}
+        }).collect()).collect();
+        StateRepr{
+            max_heat: state.max_heat,
+            policy: state.policy,
+            cold_target: state.cold_target,
+            age: 0,
+            bucket_base: 0,
+            uses: 0,
+            queues,
+            freelist: state.freelist,
+            tagged: state.tagged}
+    }
+}
+
+impl<Key, Value, Meta> Serialize for State<&PoolCache<Key, Value, Meta>>
+    where Key: PartialOrd + Ord + Serialize,
+          Value: Serialize,
+          Meta: Serialize {
+
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let cache = self.0;
+            let queues = iter::once(&cache.clock).chain(iter::once(&cache.hot))
+                .chain(cache.buckets.iter());
+            let queues = queues.map(|queue| queue.iter().map(|key| {
+                let entry = &cache.cache[key];
+                EntryRef{
+                    key: &**key,
+                    val: &entry.val,
+                    spares: &entry.spares,
+                    meta: &entry.meta,
+                    heat: entry.heat(),
+                    aged: entry.aged(),
+                    hot: entry.hot,
+                    test: entry.test}
+            }).collect()).collect();
+            StateRef{
+                max_heat: cache.max_heat,
+                policy: cache.policy,
+                cold_target: cache.cold_target,
+                age: cache.age,
+                bucket_base: cache.bucket_base,
+                uses: cache.uses.load(Ordering::Relaxed),
+                queues,
+                freelist: &cache.freelist,
+                tagged: &cache.tagged}.serialize(serializer)
+        }
+}
+
+impl<'de, Key, Value, Meta> Deserialize<'de> for State<PoolCache<Key, Value, Meta>>
+    where Key: PartialOrd + Ord + Deserialize<'de>,
+          Value: Deserialize<'de>,
+          Meta: Deserialize<'de> {
+
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let state = StateRepr::<Key, Value, Meta>::deserialize(deserializer)?;
+            PoolCache::from_state(state).map(State).map_err(D::Error::custom)
+        }
+}
+
+#[cfg(feature = "snapshot")]
+impl<'de, Key, Value, Meta> Deserialize<'de> for StateV3<PoolCache<Key, Value, Meta>>
+    where Key: PartialOrd + Ord + Deserialize<'de>,
+          Value: Deserialize<'de>,
+          Meta: Deserialize<'de> {
+
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let state = StateReprV3::<Key, Value, Meta>::deserialize(deserializer)?;
+            PoolCache::from_state(state.into()).map(StateV3).map_err(D::Error::custom)
+        }
+}
+
+#[cfg(feature = "snapshot")]
+impl<'de, Key, Value, Meta> Deserialize<'de> for StateV4<PoolCache<Key, Value, Meta>>
+    where Key: PartialOrd + Ord + Deserialize<'de>,
+          Value: Deserialize<'de>,
+          Meta: Deserialize<'de> {
+
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let state = StateReprV4::<Key, Value, Meta>::deserialize(deserializer)?;
+            PoolCache::from_state(state.into()).map(StateV4).map_err(D::Error::custom)
+        }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        // Rebuild a cache from its serialized state, failing if a key is
+        // queued more than once.
+        fn from_state(state: StateRepr<Key, Value, Meta>)
+                      -> Result<PoolCache<Key, Value, Meta>, &'static str> {
+            let mut cache = PoolCache::with_metadata(state.max_heat, state.policy);
+            cache.cold_target = state.cold_target;
+            cache.age = state.age;
+            cache.bucket_base = state.bucket_base;
+            *cache.uses.get_mut() = state.uses;
+            cache.freelist = state.freelist;
+            cache.tagged = state.tagged;
+            for (idx, entries) in state.queues.into_iter().enumerate() {
+                let mut queue = VecDeque::with_capacity(entries.len());
+                for entry in entries {
+                    let key = Arc::new(entry.key);
+                    let mut restored = CacheEntry::new(entry.val);
+                    restored.spares = entry.spares;
+                    restored.meta = entry.meta;
+                    restored.set_heat(entry.heat);
+                    *restored.aged.get_mut() = entry.aged;
+                    restored.hot = entry.hot;
+                    restored.test = entry.test;
+                    queue.push_back(key.clone());
+                    if cache.cache.insert(key, restored).is_some() {
+                        return Err("duplicate key in cache state");
+                    }
+                }
+                match idx {
+                    0 => cache.clock = queue,
+                    1 => cache.hot = queue,
+                    _ => cache.buckets.push(queue),
+                }
+            }
+            Ok(cache)
+        }
+}
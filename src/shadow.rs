@@ -0,0 +1,79 @@
+//! Running a second, value-less cache alongside a PoolCache, to compare how
+//! another policy or setting would do on the same traffic.
+
+use std::sync::Mutex;
+
+use super::{PoolCache,RemovalCause,Stats};
+
+// The state of `with_shadow`.
+pub struct Shadow<Key> {
+    // locked by lookups, which only borrow the main cache.
+    cache: Mutex<PoolCache<Key, ()>>,
+    copy: fn(&Key) -> Key,
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Attach `shadow`, an empty cache configured with a different
+        /// policy or `max_heat` (or any other `with_*` setting), to be fed
+        /// the same stream of keys without storing any values. It is looked
+        /// up whenever this cache is, and gets an entry whenever this cache
+        /// gets a new key, evicting its own victims so that it never holds
+        /// more keys than this cache. Its hit rate, from `shadow_stats`,
+        /// shows how the change would do compared to `stats`, without
+        /// risking the real hit rate. Lookups take a lock on the shadow, so
+        /// this slows down shared readers.
+        pub fn with_shadow(mut self, shadow: PoolCache<Key, ()>) -> PoolCache<Key, Value, Meta>
+            where Key: Clone {
+            self.shadow = Some(Box::new(Shadow{cache: Mutex::new(shadow), copy: Key::clone}));
+            self
+        }
+
+        /// Returns the statistics of the shadow cache, or `None` if there
+        /// isn't one.
+        pub fn shadow_stats(&self) -> Option<Stats> {
+            self.shadow.as_ref().map(|shadow| shadow.cache.lock().unwrap().stats())
+        }
+
+        pub(crate) fn shadow_lookup(&self, key: &Key) {
+            if let Some(ref shadow) = self.shadow {
+                shadow.cache.lock().unwrap().touch(key);
+            }
+        }
+
+        // Mirror the insert of a new key, which is about to be added.
+        pub(crate) fn shadow_insert(&self, key: &Key) {
+            if let Some(ref shadow) = self.shadow {
+                let mut cache = shadow.cache.lock().unwrap();
+                // a key the shadow already holds stays where it is.
+                if !cache.cache.contains_key(key) {
+                    cache.insert((shadow.copy)(key), ());
+                }
+                trim(&mut cache, self.cache.len() + 1);
+            }
+        }
+
+        // Mirror the removal of `key`, which has already left this cache.
+        // An entry removed on request leaves the shadow too, if it holds
+        // it; otherwise the shadow evicts its own victim.
+        pub(crate) fn shadow_remove(&self, key: &Key, cause: RemovalCause) {
+            if let Some(ref shadow) = self.shadow {
+                let mut cache = shadow.cache.lock().unwrap();
+                match cause {
+                    RemovalCause::Evicted | RemovalCause::Replaced | RemovalCause::Shed => {}
+                    _ => { cache.remove_entry(key, RemovalCause::Invalidated); },
+                }
+                trim(&mut cache, self.cache.len());
+            }
+        }
+}
+
+// Evict from `cache` until it holds at most `len` keys.
+fn trim<Key: PartialOrd + Ord>(cache: &mut PoolCache<Key, ()>, len: usize) {
+    while cache.cache.len() > len {
+        if cache.take().is_none() {
+            break;
+        }
+    }
+}
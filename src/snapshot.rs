@@ -0,0 +1,355 @@
+//! Saving the full state of a PoolCache to a file, and restoring it, e.g.
+//! to keep a warm cache across restarts.
+//!
+//! A snapshot starts with a fixed header, `MAGIC` followed by the format
+//! version as a little-endian `u32`. In version 5, a byte giving the
+//! `Compression` follows, and the rest is the cache's `State` encoded with
+//! bincode, then the CRC-32 of that encoding as a little-endian `u32`, all
+//! compressed as given. Version 4 didn't save the objects stacked under
+//! each key, version 3 didn't save how far the cache had aged either,
+//! version 2 had no checksum, and version 1 had no compression byte and
+//! was never compressed. The encoding isn't self-describing, so any
+//! change to the layout of `State` must bump `SNAPSHOT_VERSION`, keep the
+//! old layout around, and add an arm to `read_state` that converts it:
+//! every version ever written stays readable.
+
+use std::error;
+use std::fmt;
+use std::fs::{self,File};
+use std::io::{self,BufReader,BufWriter,Read,Write};
+use std::path::Path;
+use std::process;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::{PoolCache,State};
+use super::serialize::{StateV3,StateV4};
+
+const MAGIC: [u8; 8] = *b"POOLSNAP";
+
+/// The version of the snapshot format written by this version of the
+/// crate. Snapshots of this or any earlier version can be restored.
+pub const SNAPSHOT_VERSION: u32 = 5;
+
+/// How the body of a snapshot is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Not compressed.
+    None = 0,
+    /// Compressed as an LZ4 frame, which is fast enough to keep up with
+    /// most disks.
+    #[cfg(feature = "lz4")]
+    Lz4 = 1,
+}
+
+/// Why a snapshot couldn't be written or restored.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// Reading or writing the snapshot failed.
+    Io(io::Error),
+    /// The snapshot couldn't be encoded, or its contents couldn't be
+    /// decoded.
+    Encoding(Box<dyn error::Error + Send + Sync>),
+    /// The data doesn't start with a snapshot header.
+    NotASnapshot,
+    /// The snapshot was written by a newer version of the crate.
+    UnsupportedVersion(u32),
+    /// The snapshot is compressed in a way this build doesn't support,
+    /// e.g. with LZ4 when the `lz4` feature is disabled.
+    UnsupportedCompression(u8),
+    /// The snapshot ended early, e.g. because it wasn't completely written.
+    Truncated,
+    /// The snapshot's contents don't match its checksum.
+    ChecksumMismatch,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::Io(ref err) => write!(f, "snapshot I/O failed: {}", err),
+            SnapshotError::Encoding(ref err) => write!(f, "bad snapshot encoding: {}", err),
+            SnapshotError::NotASnapshot => write!(f, "not a PoolCache snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::UnsupportedCompression(codec) => {
+                write!(f, "unsupported snapshot compression {}", codec)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::ChecksumMismatch => write!(f, "snapshot checksum mismatch"),
+        }
+    }
+}
+
+impl error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SnapshotError::Io(ref err) => Some(err),
+            SnapshotError::Encoding(ref err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> SnapshotError {
+        SnapshotError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(err: bincode::Error) -> SnapshotError {
+        match *err {
+            bincode::ErrorKind::Io(err) => SnapshotError::Io(err),
+            _ => SnapshotError::Encoding(err),
+        }
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Write a snapshot of the full state of the cache (see `State`) to
+        /// `writer`, uncompressed, in the current `SNAPSHOT_VERSION` of the
+        /// format. The snapshot is written in many small pieces, so `writer`
+        /// should be buffered.
+        pub fn snapshot_to_writer<W>(&self, writer: W) -> Result<(), SnapshotError>
+            where W: Write,
+                  Key: Serialize,
+                  Value: Serialize,
+                  Meta: Serialize {
+            self.snapshot_to_writer_compressed(writer, Compression::None)
+        }
+
+        /// Like `snapshot_to_writer`, but compresses the snapshot as it is
+        /// written.
+        pub fn snapshot_to_writer_compressed<W>(&self, mut writer: W, compression: Compression)
+                                                -> Result<(), SnapshotError>
+            where W: Write,
+                  Key: Serialize,
+                  Value: Serialize,
+                  Meta: Serialize {
+            writer.write_all(&MAGIC)?;
+            writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+            writer.write_all(&[compression as u8])?;
+            match compression {
+                Compression::None => self.write_body(&mut writer)?,
+                #[cfg(feature = "lz4")]
+                Compression::Lz4 => {
+                    let mut encoder = lz4_flex::frame::FrameEncoder::new(&mut writer);
+                    self.write_body(&mut encoder)?;
+                    encoder.finish().map_err(io::Error::from)?;
+                }
+            }
+            writer.flush()?;
+            Ok(())
+        }
+
+        /// Restore a cache from a snapshot written by `snapshot_to_writer` or
+        /// `snapshot_to_writer_compressed`, in this or any earlier version of
+        /// the format.
+        pub fn restore_from_reader<R>(mut reader: R) -> Result<PoolCache<Key, Value, Meta>, SnapshotError>
+            where R: Read,
+                  Key: DeserializeOwned,
+                  Value: DeserializeOwned,
+                  Meta: DeserializeOwned {
+            let mut magic = [0; 8];
+            let mut version = [0; 4];
+            match reader.read_exact(&mut magic).and_then(|_| reader.read_exact(&mut version)) {
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(SnapshotError::NotASnapshot);
+                }
+                result => result?,
+            }
+            if magic != MAGIC {
+                return Err(SnapshotError::NotASnapshot);
+            }
+            read_state(u32::from_le_bytes(version), reader).map_err(|err| match err {
+                SnapshotError::Io(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    SnapshotError::Truncated
+                }
+                err => err,
+            })
+        }
+
+        /// Save a snapshot of the cache to the file at `path`, like
+        /// `snapshot_to_writer`. The snapshot is written to a temporary file
+        /// in the same directory, synced to disk, and then renamed over
+        /// `path`, so a crash never leaves a partly written snapshot at
+        /// `path`.
+        pub fn save_to_path<P>(&self, path: P) -> Result<(), SnapshotError>
+            where P: AsRef<Path>,
+                  Key: Serialize,
+                  Value: Serialize,
+                  Meta: Serialize {
+            self.save_to_path_compressed(path, Compression::None)
+        }
+
+        /// Like `save_to_path`, but compresses the snapshot as it is written.
+        pub fn save_to_path_compressed<P>(&self, path: P, compression: Compression)
+                                          -> Result<(), SnapshotError>
+            where P: AsRef<Path>,
+                  Key: Serialize,
+                  Value: Serialize,
+                  Meta: Serialize {
+            let path = path.as_ref();
+            let name = path.file_name().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "snapshot path has no file name")
+            })?;
+            let mut temp_name = name.to_os_string();
+            temp_name.push(format!(".{}.tmp", process::id()));
+            let temp = path.with_file_name(temp_name);
+            let result = self.write_file(&temp, compression).and_then(|_| {
+                fs::rename(&temp, path)?;
+                sync_dir(path)
+            });
+            if result.is_err() {
+                let _ = fs::remove_file(&temp);
+            }
+            result
+        }
+
+        /// Restore a cache from the snapshot at `path`, written by
+        /// `save_to_path` or `snapshot_to_writer`.
+        pub fn load_from_path<P>(path: P) -> Result<PoolCache<Key, Value, Meta>, SnapshotError>
+            where P: AsRef<Path>,
+                  Key: DeserializeOwned,
+                  Value: DeserializeOwned,
+                  Meta: DeserializeOwned {
+            PoolCache::restore_from_reader(BufReader::new(File::open(path)?))
+        }
+
+        fn write_file(&self, path: &Path, compression: Compression) -> Result<(), SnapshotError>
+            where Key: Serialize,
+                  Value: Serialize,
+                  Meta: Serialize {
+            let mut writer = BufWriter::new(File::create(path)?);
+            self.snapshot_to_writer_compressed(&mut writer, compression)?;
+            let file = writer.into_inner().map_err(|err| err.into_error())?;
+            file.sync_all()?;
+            Ok(())
+        }
+
+        // Write the encoded state, followed by its checksum.
+        fn write_body<W>(&self, writer: W) -> Result<(), SnapshotError>
+            where W: Write,
+                  Key: Serialize,
+                  Value: Serialize,
+                  Meta: Serialize {
+            let mut writer = Checksummed::new(writer);
+            bincode::serialize_into(&mut writer, &State(self))?;
+            let checksum = writer.hasher.finalize();
+            writer.inner.write_all(&checksum.to_le_bytes())?;
+            Ok(())
+        }
+}
+
+// Sync the directory holding `path`, so that a rename into it is durable.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<(), SnapshotError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+// Directories can't be opened to be synced elsewhere.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<(), SnapshotError> {
+    Ok(())
+}
+
+// Decode the body of a snapshot of the given format version, migrating it
+// to the current layout.
+fn read_state<Key, Value, Meta, R>(version: u32, mut reader: R)
+                                   -> Result<PoolCache<Key, Value, Meta>, SnapshotError>
+    where Key: PartialOrd + Ord + DeserializeOwned,
+          Value: DeserializeOwned,
+          Meta: DeserializeOwned,
+          R: Read {
+    match version {
+        // version 1 is the layout of `State`, uncompressed.
+        1 => decode(reader, version),
+        // version 2 adds the compression byte, version 3 the checksum,
+        // version 4 the age of the cache and its entries, and version 5 the
+        // objects stacked under each key.
+        2..=SNAPSHOT_VERSION => {
+            let mut codec = [0];
+            reader.read_exact(&mut codec)?;
+            match codec[0] {
+                0 => decode(reader, version),
+                #[cfg(feature = "lz4")]
+                1 => decode(lz4_flex::frame::FrameDecoder::new(reader), version),
+                codec => Err(SnapshotError::UnsupportedCompression(codec)),
+            }
+        }
+        version => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+// Decode the state in the layout of `version`, followed by its checksum
+// from version 3 on. bincode reads exactly the bytes of the encoding, so
+// the checksum comes next.
+fn decode<Key, Value, Meta, R>(reader: R, version: u32)
+                               -> Result<PoolCache<Key, Value, Meta>, SnapshotError>
+    where Key: PartialOrd + Ord + DeserializeOwned,
+          Value: DeserializeOwned,
+          Meta: DeserializeOwned,
+          R: Read {
+    let mut reader = Checksummed::new(reader);
+    let cache = if version >= 5 {
+        let State(cache) = bincode::deserialize_from(&mut reader)?;
+        cache
+    } else if version == 4 {
+        let StateV4(cache) = bincode::deserialize_from(&mut reader)?;
+        cache
+    } else {
+        let StateV3(cache) = bincode::deserialize_from(&mut reader)?;
+        cache
+    };
+    if version >= 3 {
+        let mut checksum = [0; 4];
+        reader.inner.read_exact(&mut checksum)?;
+        if u32::from_le_bytes(checksum) != reader.hasher.finalize() {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+    }
+    Ok(cache)
+}
+
+// Passes reads or writes through, computing the CRC-32 of the bytes.
+struct Checksummed<T> {
+    inner: T,
+    hasher: crc32fast::Hasher,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Checksummed<T> {
+        Checksummed{inner, hasher: crc32fast::Hasher::new()}
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
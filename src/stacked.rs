@@ -0,0 +1,48 @@
+//! Several interchangeable objects under one key, such as connections to
+//! the same host.
+
+use super::{PoolCache,RemovalCause};
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Add `val` to the stack of objects under `key`, inserting an entry
+        /// for it if there is none. The objects under a key are taken back
+        /// one at a time by `take_for`, and leave the cache together if the
+        /// entry is evicted or removed, the entry's value going as usual and
+        /// the others to the pool.
+        pub fn put_for(&mut self, key: &Key, val: Value)
+            where Key: Clone {
+            self.relieve_pressure();
+            match self.live(key) {
+                Some(_) => {
+                    self.cache.get_mut(key).unwrap().spares.push(val);
+                    self.enforce_budget();
+                }
+                None => self.insert(key.clone(), val),
+            }
+        }
+
+        /// Returns the object last put under `key`, counting a use of its
+        /// entry. The entry is removed along with its last object
+        /// (notifying the listener with `RemovalCause::Invalidated`). If
+        /// there is nothing under `key`, falls back to `take`.
+        pub fn take_for(&mut self, key: &Key) -> Option<Value> {
+            self.relieve_pressure();
+            let entry = match self.live(key) {
+                Some((_, entry)) => entry,
+                None => return self.take(),
+            };
+            self.hit(entry);
+            if let Some(val) = self.cache.get_mut(key).unwrap().spares.pop() {
+                return Some(val);
+            }
+            self.remove_entry(key, RemovalCause::Invalidated).map(|(_, val)| val)
+        }
+
+        /// Returns the number of objects under `key`, which `take_for` can
+        /// hand out before falling back to `take`.
+        pub fn stack_len(&self, key: &Key) -> usize {
+            self.live(key).map_or(0, |(_, entry)| entry.spares.len() + 1)
+        }
+}
@@ -0,0 +1,188 @@
+//! Counting lookups, inserts and removals, for monitoring.
+
+use std::cmp;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64,Ordering};
+use std::time::{Duration,Instant};
+
+use super::{PoolCache,RemovalCause};
+
+/// Counts of what a PoolCache has done, as returned by `stats` and
+/// `window_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Lookups (`get`, `touch`, `resolve`, ...) that found a live entry.
+    pub hits: u64,
+    /// Lookups that found nothing, or an expired entry or stale handle.
+    pub misses: u64,
+    /// Values inserted under a key, including replacements.
+    pub inserts: u64,
+    /// Entries evicted to hand out their values.
+    pub evictions: u64,
+    /// Entries that left the cache for any other reason: replaced, shed,
+    /// invalidated, expired or released.
+    pub removals: u64,
+}
+
+impl Stats {
+    /// Returns the share of lookups that were hits, or 0 if there were
+    /// none.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+
+    // The counts made since `earlier` was read.
+    fn since(&self, earlier: &Stats) -> Stats {
+        Stats{
+            hits: self.hits.saturating_sub(earlier.hits),
+            misses: self.misses.saturating_sub(earlier.misses),
+            inserts: self.inserts.saturating_sub(earlier.inserts),
+            evictions: self.evictions.saturating_sub(earlier.evictions),
+            removals: self.removals.saturating_sub(earlier.removals)}
+    }
+}
+
+// The live counts behind `Stats`. Lookups only borrow the cache, so the
+// counters are atomic.
+pub struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    evictions: AtomicU64,
+    removals: AtomicU64,
+    // the moving average hit rate, as the bits of an `f64`.
+    recent: AtomicU64,
+    // roughly how many lookups the moving average spans.
+    span: u64,
+    // when the current window started, and the counts at that moment.
+    window: Mutex<(Instant, Stats)>,
+}
+
+impl Counters {
+    pub fn new() -> Counters {
+        Counters{
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            inserts: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            removals: AtomicU64::new(0),
+            recent: AtomicU64::new(0),
+            span: 1000,
+            window: Mutex::new((Instant::now(), Stats::default()))}
+    }
+
+    // Count `hits` hits at once, folding them into the moving average as if
+    // each had the weight of the last.
+    pub fn hits(&self, hits: u64) {
+        self.hits.fetch_add(hits, Ordering::Relaxed);
+        let missed = 1.0 - f64::from_bits(self.recent.load(Ordering::Relaxed));
+        let missed = missed * (1.0 - self.weight()).powf(hits as f64);
+        self.recent.store((1.0 - missed).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        self.sample(0.0);
+    }
+
+    // Fold a lookup into the moving average. Until `span` lookups have been
+    // made it is a plain average, so the first lookups aren't drowned out
+    // by the initial 0. Like heat, updates aren't read-modify-write, and
+    // racing lookups may be counted as one.
+    fn sample(&self, hit: f64) {
+        let weight = self.weight();
+        let recent = f64::from_bits(self.recent.load(Ordering::Relaxed));
+        self.recent.store((recent + weight * (hit - recent)).to_bits(), Ordering::Relaxed);
+    }
+
+    // The weight of the last lookup in the moving average.
+    fn weight(&self) -> f64 {
+        let lookups = self.hits.load(Ordering::Relaxed) + self.misses.load(Ordering::Relaxed);
+        1.0 / lookups.clamp(1, self.span) as f64
+    }
+
+    pub fn insert(&self) {
+        self.inserts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn removed(&self, cause: RemovalCause) {
+        match cause {
+            RemovalCause::Evicted => self.evictions.fetch_add(1, Ordering::Relaxed),
+            _ => self.removals.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn load(&self) -> Stats {
+        Stats{
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            inserts: self.inserts.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            removals: self.removals.load(Ordering::Relaxed)}
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        // Count a lookup of `key`, which was a miss unless `found` is set,
+        // and pass it on to the shadow cache.
+        pub(crate) fn looked_up(&self, key: &Key, found: bool) {
+            if !found {
+                self.counters.miss();
+            }
+            self.shadow_lookup(key);
+        }
+
+        /// Returns the counts of lookups, inserts and removals since the
+        /// cache was created, or since `reset_stats`. Counts made at the
+        /// same moment by different threads aren't read together atomically.
+        pub fn stats(&self) -> Stats {
+            self.counters.load()
+        }
+
+        /// Zero the counts returned by `stats` and the average returned by
+        /// `recent_hit_rate`, and start a new window for `window_stats`.
+        pub fn reset_stats(&self) {
+            let mut window = self.counters.window.lock().unwrap();
+            for counter in [&self.counters.hits, &self.counters.misses, &self.counters.inserts,
+                            &self.counters.evictions, &self.counters.removals,
+                            &self.counters.recent] {
+                counter.store(0, Ordering::Relaxed);
+            }
+            *window = (Instant::now(), Stats::default());
+        }
+
+        /// Returns the counts made since the previous call (or since the
+        /// cache was created, or `reset_stats`), along with how long that
+        /// window lasted, and starts a new window. Calling this once a
+        /// minute gives, say, the evictions in the last minute, without
+        /// disturbing the running totals of `stats`.
+        pub fn window_stats(&self) -> (Stats, Duration) {
+            let mut window = self.counters.window.lock().unwrap();
+            let (now, current) = (Instant::now(), self.counters.load());
+            let (started, base) = *window;
+            *window = (now, current);
+            (current.since(&base), now - started)
+        }
+
+        /// Set roughly how many of the latest lookups `recent_hit_rate`
+        /// reflects; older ones fade away exponentially. The default is
+        /// 1000, and a span of 0 is taken as 1.
+        pub fn with_hit_rate_span(mut self, lookups: u64) -> PoolCache<Key, Value, Meta> {
+            self.counters.span = cmp::max(lookups, 1);
+            self
+        }
+
+        /// Returns an exponentially weighted moving average of the hit rate
+        /// over roughly the latest lookups (see `with_hit_rate_span`), which
+        /// follows shifts in traffic that the lifetime hit rate of `stats`
+        /// hides. Returns 0 if there have been no lookups.
+        pub fn recent_hit_rate(&self) -> f64 {
+            f64::from_bits(self.counters.recent.load(Ordering::Relaxed))
+        }
+}
@@ -0,0 +1,353 @@
+//! The map a PoolCache keeps its keyed entries in, which can be swapped
+//! for another implementation of `CacheStorage`.
+
+use std::collections::{btree_map,BTreeMap,HashMap};
+use std::hash::{BuildHasher,Hash};
+use std::mem;
+use std::ops::Index;
+use std::sync::Arc;
+
+use super::{CacheEntry,PoolCache};
+
+/// A map from keys to cache entries, for storing the keyed entries of a
+/// PoolCache (see `PoolCache::with_storage`). `Entry` is the cache's own
+/// entry type, which implementations only need to store and hand back.
+///
+/// Implemented for `BTreeMap`, which is what a PoolCache uses unless told
+/// otherwise, and for `HashMap`. Methods that iterate over the entries,
+/// such as `freeze` or serializing with `Contents`, visit them in the
+/// order of `iter`.
+pub trait CacheStorage<Key, Entry> {
+    /// Returns the stored key equal to `key`, and its entry.
+    fn get(&self, key: &Key) -> Option<(&Arc<Key>, &Entry)>;
+
+    /// Returns the entry for `key`, for updating in place.
+    fn get_mut(&mut self, key: &Key) -> Option<&mut Entry>;
+
+    /// Store `entry` under `key`, returning the entry it replaced, if any.
+    /// A replaced entry keeps its stored key.
+    fn insert(&mut self, key: Arc<Key>, entry: Entry) -> Option<Entry>;
+
+    /// Remove the entry for `key`, returning its stored key and entry.
+    fn remove(&mut self, key: &Key) -> Option<(Arc<Key>, Entry)>;
+
+    /// Returns the number of entries.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if there are no entries.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns every entry along with its stored key.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Arc<Key>, &Entry)> + '_>;
+}
+
+impl<Key, Entry> CacheStorage<Key, Entry> for BTreeMap<Arc<Key>, Entry>
+    where Key: Ord {
+
+        fn get(&self, key: &Key) -> Option<(&Arc<Key>, &Entry)> {
+            self.get_key_value(key)
+        }
+
+        fn get_mut(&mut self, key: &Key) -> Option<&mut Entry> {
+            BTreeMap::get_mut(self, key)
+        }
+
+        fn insert(&mut self, key: Arc<Key>, entry: Entry) -> Option<Entry> {
+            BTreeMap::insert(self, key, entry)
+        }
+
+        fn remove(&mut self, key: &Key) -> Option<(Arc<Key>, Entry)> {
+            self.remove_entry(key)
+        }
+
+        fn len(&self) -> usize {
+            BTreeMap::len(self)
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = (&Arc<Key>, &Entry)> + '_> {
+            Box::new(BTreeMap::iter(self))
+        }
+}
+
+impl<Key, Entry, S> CacheStorage<Key, Entry> for HashMap<Arc<Key>, Entry, S>
+    where Key: Hash + Eq,
+          S: BuildHasher {
+
+        fn get(&self, key: &Key) -> Option<(&Arc<Key>, &Entry)> {
+            self.get_key_value(key)
+        }
+
+        fn get_mut(&mut self, key: &Key) -> Option<&mut Entry> {
+            HashMap::get_mut(self, key)
+        }
+
+        fn insert(&mut self, key: Arc<Key>, entry: Entry) -> Option<Entry> {
+            HashMap::insert(self, key, entry)
+        }
+
+        fn remove(&mut self, key: &Key) -> Option<(Arc<Key>, Entry)> {
+            self.remove_entry(key)
+        }
+
+        fn len(&self) -> usize {
+            HashMap::len(self)
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = (&Arc<Key>, &Entry)> + '_> {
+            Box::new(HashMap::iter(self))
+        }
+}
+
+/// Keys that map one to one onto small indices, for `DenseStorage`.
+pub trait DenseKey {
+    /// Returns the index of the key. Different keys must have different
+    /// indices.
+    fn index(&self) -> usize;
+}
+
+macro_rules! dense_key {
+    ($($int:ty),*) => {$(
+        impl DenseKey for $int {
+            fn index(&self) -> usize {
+                *self as usize
+            }
+        }
+    )*};
+}
+
+dense_key!(u8, u16, u32, u64, usize);
+
+/// A `CacheStorage` for integer keys drawn from a dense range, such as
+/// page numbers or slot ids, that keeps each entry in a vector at the
+/// key's index, so that lookups are a bounds check rather than a search.
+/// It takes memory in proportion to the largest key stored, not the number
+/// of entries; the vector grows as needed and never shrinks. Entries are
+/// iterated in key order.
+pub struct DenseStorage<Key, Entry> {
+    slots: Vec<Option<(Arc<Key>, Entry)>>,
+    len: usize,
+}
+
+impl<Key, Entry> DenseStorage<Key, Entry> {
+    /// Create an empty `DenseStorage`.
+    pub fn new() -> DenseStorage<Key, Entry> {
+        DenseStorage::with_capacity(0)
+    }
+
+    /// Create an empty `DenseStorage` with room for keys below `capacity`
+    /// before it needs to grow.
+    pub fn with_capacity(capacity: usize) -> DenseStorage<Key, Entry> {
+        let mut slots = Vec::with_capacity(capacity);
+        slots.resize_with(capacity, || None);
+        DenseStorage{slots, len: 0}
+    }
+}
+
+impl<Key, Entry> Default for DenseStorage<Key, Entry> {
+    fn default() -> DenseStorage<Key, Entry> {
+        DenseStorage::new()
+    }
+}
+
+impl<Key, Entry> CacheStorage<Key, Entry> for DenseStorage<Key, Entry>
+    where Key: DenseKey {
+
+        fn get(&self, key: &Key) -> Option<(&Arc<Key>, &Entry)> {
+            self.slots.get(key.index())?.as_ref().map(|(key, entry)| (key, entry))
+        }
+
+        fn get_mut(&mut self, key: &Key) -> Option<&mut Entry> {
+            self.slots.get_mut(key.index())?.as_mut().map(|(_, entry)| entry)
+        }
+
+        fn insert(&mut self, key: Arc<Key>, entry: Entry) -> Option<Entry> {
+            let idx = key.index();
+            if idx >= self.slots.len() {
+                self.slots.resize_with(idx + 1, || None);
+            }
+            match self.slots[idx] {
+                Some((_, ref mut old)) => Some(mem::replace(old, entry)),
+                None => {
+                    self.slots[idx] = Some((key, entry));
+                    self.len += 1;
+                    None
+                }
+            }
+        }
+
+        fn remove(&mut self, key: &Key) -> Option<(Arc<Key>, Entry)> {
+            let removed = self.slots.get_mut(key.index())?.take()?;
+            self.len -= 1;
+            Some(removed)
+        }
+
+        fn len(&self) -> usize {
+            self.len
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = (&Arc<Key>, &Entry)> + '_> {
+            Box::new(self.slots.iter().flatten().map(|(key, entry)| (key, entry)))
+        }
+}
+
+// The storage of a PoolCache: the default `BTreeMap`, held directly so that
+// lookups aren't dispatched dynamically, or any other `CacheStorage`.
+pub enum Storage<Key, Entry> {
+    Ordered(BTreeMap<Arc<Key>, Entry>),
+    Custom(Box<dyn CacheStorage<Key, Entry> + Send + Sync>),
+}
+
+// Iterates over the entries of a `Storage`.
+pub enum Iter<'a, Key, Entry> {
+    Ordered(btree_map::Iter<'a, Arc<Key>, Entry>),
+    Range(btree_map::Range<'a, Arc<Key>, Entry>),
+    Custom(Box<dyn Iterator<Item = (&'a Arc<Key>, &'a Entry)> + 'a>),
+}
+
+impl<'a, Key, Entry> Iterator for Iter<'a, Key, Entry> {
+    type Item = (&'a Arc<Key>, &'a Entry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Ordered(iter) => iter.next(),
+            Iter::Range(iter) => iter.next(),
+            Iter::Custom(iter) => iter.next(),
+        }
+    }
+}
+
+impl<Key, Entry> Storage<Key, Entry>
+    where Key: Ord {
+
+        pub fn get_key_value(&self, key: &Key) -> Option<(&Arc<Key>, &Entry)> {
+            match self {
+                Storage::Ordered(map) => map.get_key_value(key),
+                Storage::Custom(storage) => storage.get(key),
+            }
+        }
+
+        pub fn get(&self, key: &Key) -> Option<&Entry> {
+            self.get_key_value(key).map(|(_, entry)| entry)
+        }
+
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.get_key_value(key).is_some()
+        }
+
+        pub fn get_mut(&mut self, key: &Key) -> Option<&mut Entry> {
+            match self {
+                Storage::Ordered(map) => map.get_mut(key),
+                Storage::Custom(storage) => storage.get_mut(key),
+            }
+        }
+
+        pub fn insert(&mut self, key: Arc<Key>, entry: Entry) -> Option<Entry> {
+            match self {
+                Storage::Ordered(map) => map.insert(key, entry),
+                Storage::Custom(storage) => storage.insert(key, entry),
+            }
+        }
+
+        pub fn remove_entry(&mut self, key: &Key) -> Option<(Arc<Key>, Entry)> {
+            match self {
+                Storage::Ordered(map) => map.remove_entry(key),
+                Storage::Custom(storage) => storage.remove(key),
+            }
+        }
+
+        pub fn remove(&mut self, key: &Key) -> Option<Entry> {
+            self.remove_entry(key).map(|(_, entry)| entry)
+        }
+
+        pub fn len(&self) -> usize {
+            match self {
+                Storage::Ordered(map) => map.len(),
+                Storage::Custom(storage) => storage.len(),
+            }
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        pub fn iter(&self) -> Iter<'_, Key, Entry> {
+            match self {
+                Storage::Ordered(map) => Iter::Ordered(map.iter()),
+                Storage::Custom(storage) => Iter::Custom(storage.iter()),
+            }
+        }
+
+        pub fn keys(&self) -> impl Iterator<Item = &Arc<Key>> {
+            self.iter().map(|(key, _)| key)
+        }
+
+        pub fn values(&self) -> impl Iterator<Item = &Entry> {
+            self.iter().map(|(_, entry)| entry)
+        }
+
+        // The entries whose keys start with `prefix`. The default storage
+        // holds them together, in key order, so only those are visited.
+        pub fn prefixed<'a>(&'a self, prefix: &'a Key)
+                            -> impl Iterator<Item = (&'a Arc<Key>, &'a Entry)> + 'a
+            where Key: AsRef<str> {
+            let (iter, ordered) = match self {
+                Storage::Ordered(map) => (Iter::Range(map.range::<Key, _>(prefix..)), true),
+                Storage::Custom(storage) => (Iter::Custom(storage.iter()), false),
+            };
+            let prefix = prefix.as_ref();
+            iter.map(move |(key, entry)| (key, entry, (**key).as_ref().starts_with(prefix)))
+                .take_while(move |&(_, _, prefixed)| prefixed || !ordered)
+                .filter_map(|(key, entry, prefixed)| prefixed.then_some((key, entry)))
+        }
+}
+
+impl<Key, Entry> Index<&Key> for Storage<Key, Entry>
+    where Key: Ord {
+
+        type Output = Entry;
+
+        fn index(&self, key: &Key) -> &Entry {
+            self.get(key).expect("key not present in storage")
+        }
+}
+
+impl<Key, Entry> Index<&Arc<Key>> for Storage<Key, Entry>
+    where Key: Ord {
+
+        type Output = Entry;
+
+        fn index(&self, key: &Arc<Key>) -> &Entry {
+            &self[&**key]
+        }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Keep the keyed entries in `storage` rather than a `BTreeMap`, such
+        /// as a `HashMap` for keys that hash faster than they compare, or a
+        /// specialized map for the shape of the keys. Entries already in
+        /// the cache are moved over. Lookups through a storage other than
+        /// the default are dispatched dynamically.
+        pub fn with_storage<S>(mut self, mut storage: S) -> PoolCache<Key, Value, Meta>
+            where S: CacheStorage<Key, CacheEntry<Value, Meta>> + Send + Sync + 'static {
+            let old = mem::replace(&mut self.cache, Storage::Ordered(BTreeMap::new()));
+            match old {
+                Storage::Ordered(map) => for (key, entry) in map {
+                    storage.insert(key, entry);
+                },
+                Storage::Custom(mut old) => {
+                    let keys: Vec<Arc<Key>> = old.iter().map(|(key, _)| key.clone()).collect();
+                    for key in keys {
+                        if let Some((key, entry)) = old.remove(&key) {
+                            storage.insert(key, entry);
+                        }
+                    }
+                }
+            }
+            self.cache = Storage::Custom(Box::new(storage));
+            self
+        }
+}
@@ -0,0 +1,196 @@
+//! A PoolCache that can be shared between threads behind a read-write lock.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc,Condvar,Mutex,RwLock,RwLockReadGuard,RwLockWriteGuard};
+use std::time::{Duration,Instant};
+
+use super::PoolCache;
+
+/// A PoolCache that can be shared between threads.
+///
+/// Reads (`get_cloned`, `with`, `touch`, `contains_key`) only take a read
+/// lock, since heat is updated atomically; only operations that change the
+/// contents of the cache (`insert`, `put`, `take`, ...) take the write
+/// lock. Uses counted at the same moment by different readers may be
+/// counted as one.
+pub struct SyncPoolCache<Key, Value> {
+    cache: RwLock<PoolCache<Key, Value>>,
+    // signalled when `put` or `insert` make an object available.
+    available: Condvar,
+    waiting: Mutex<()>,
+    // values being computed by `get_or_insert_with`, by key.
+    flights: Mutex<BTreeMap<Key, Arc<Flight<Value>>>>,
+}
+
+// A value being computed by one thread, for the others that missed the
+// same key to wait on.
+struct Flight<Value> {
+    // `None` while computing, then `Some(None)` if the computing thread
+    // panicked, or the value.
+    result: Mutex<Option<Option<Value>>>,
+    landed: Condvar,
+}
+
+// Publishes the result of a flight, or its failure if dropped while
+// unwinding.
+struct Leader<'a, Key, Value>
+    where Key: PartialOrd + Ord {
+    cache: &'a SyncPoolCache<Key, Value>,
+    key: &'a Key,
+    flight: Arc<Flight<Value>>,
+    val: Option<Value>,
+}
+
+impl<'a, Key, Value> Drop for Leader<'a, Key, Value>
+    where Key: PartialOrd + Ord {
+
+        fn drop(&mut self) {
+            self.cache.flights.lock().unwrap().remove(self.key);
+            *self.flight.result.lock().unwrap() = Some(self.val.take());
+            self.flight.landed.notify_all();
+        }
+}
+
+impl<Key, Value> SyncPoolCache<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Wrap `cache` for sharing between threads.
+        pub fn new(cache: PoolCache<Key, Value>) -> SyncPoolCache<Key, Value> {
+            SyncPoolCache{
+                cache: RwLock::new(cache),
+                available: Condvar::new(),
+                waiting: Mutex::new(()),
+                flights: Mutex::new(BTreeMap::new())}
+        }
+
+        /// Returns `true` if the given key is present in the cache.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.read().contains_key(key)
+        }
+
+        /// Calls `f` with the value associated with `key`, returning its
+        /// result, or `None` if the key is not present in the cache.
+        pub fn with<F, R>(&self, key: &Key, f: F) -> Option<R>
+            where F: FnOnce(&Value) -> R {
+            self.read().get(key).map(f)
+        }
+
+        /// Returns a clone of the value associated with `key`, or `None`
+        /// if the key is not present in the cache.
+        pub fn get_cloned(&self, key: &Key) -> Option<Value>
+            where Value: Clone {
+            self.read().get_cloned(key)
+        }
+
+        /// Counts a use of the entry for `key` without reading its value.
+        /// Returns `true` if the key was present in the cache.
+        pub fn touch(&self, key: &Key) -> bool {
+            self.read().touch(key)
+        }
+
+        /// Returns a clone of the value associated with `key`, computing it
+        /// with `create` and inserting it if it isn't cached. When several
+        /// threads miss the same key at once, only one of them calls
+        /// `create`; the others block until it is done, and share its
+        /// result. If `create` panics, a waiting thread takes over.
+        pub fn get_or_insert_with<F>(&self, key: &Key, create: F) -> Value
+            where Key: Clone,
+                  Value: Clone,
+                  F: FnOnce(&Key) -> Value {
+            let mut create = Some(create);
+            loop {
+                if let Some(val) = self.get_cloned(key) {
+                    return val;
+                }
+                let (flight, leading) = {
+                    let mut flights = self.flights.lock().unwrap();
+                    match flights.get(key) {
+                        Some(flight) => (flight.clone(), false),
+                        None => {
+                            let flight = Arc::new(Flight{result: Mutex::new(None), landed: Condvar::new()});
+                            flights.insert(key.clone(), flight.clone());
+                            (flight, true)
+                        }
+                    }
+                };
+                if leading {
+                    let mut leader = Leader{cache: self, key, flight, val: None};
+                    // another flight may have landed since the miss.
+                    let val = match self.get_cloned(key) {
+                        Some(val) => val,
+                        None => {
+                            let val = (create.take().unwrap())(key);
+                            self.insert(key.clone(), val.clone());
+                            val
+                        }
+                    };
+                    leader.val = Some(val.clone());
+                    return val;
+                }
+                let mut result = flight.result.lock().unwrap();
+                while result.is_none() {
+                    result = flight.landed.wait(result).unwrap();
+                }
+                if let Some(Some(ref val)) = *result {
+                    return val.clone();
+                }
+                // the computing thread panicked: try again.
+            }
+        }
+
+        /// Add a new object to the pool, not associated with any key.
+        pub fn put(&self, val: Value) {
+            self.write().put(val);
+            self.notify();
+        }
+
+        /// Insert `val` into the cache associated with `key`, replacing any
+        /// previous entry.
+        pub fn insert(&self, key: Key, val: Value) {
+            self.write().insert(key, val);
+            self.notify();
+        }
+
+        /// Returns an object from the pool, evicting the least-used cached
+        /// key if necessary.
+        pub fn take(&self) -> Option<Value> {
+            self.write().take()
+        }
+
+        /// Like `take`, but if the PoolCache contains no items, waits up to
+        /// `timeout` for another thread to `put` or `insert` one. Objects
+        /// added through `write` don't wake waiting threads.
+        pub fn take_timeout(&self, timeout: Duration) -> Option<Value> {
+            let deadline = Instant::now() + timeout;
+            // hold `waiting` between checking and waiting, so that an object
+            // added in between isn't missed.
+            let mut waiting = self.waiting.lock().unwrap();
+            loop {
+                if let Some(val) = self.take() {
+                    return Some(val);
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                waiting = self.available.wait_timeout(waiting, deadline - now).unwrap().0;
+            }
+        }
+
+        /// Lock the cache for reading, for access to the rest of the
+        /// read-only PoolCache API.
+        pub fn read(&self) -> RwLockReadGuard<'_, PoolCache<Key, Value>> {
+            self.cache.read().unwrap()
+        }
+
+        /// Lock the cache for writing, for access to the rest of the
+        /// PoolCache API.
+        pub fn write(&self) -> RwLockWriteGuard<'_, PoolCache<Key, Value>> {
+            self.cache.write().unwrap()
+        }
+
+        fn notify(&self) {
+            let _waiting = self.waiting.lock().unwrap();
+            self.available.notify_one();
+        }
+}
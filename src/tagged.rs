@@ -0,0 +1,44 @@
+//! Free objects labelled with a tag, for pools of objects that aren't all
+//! interchangeable.
+
+use std::collections::VecDeque;
+
+use super::PoolCache;
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Add a new object to the pool under `tag`, not associated with any
+        /// key. Tagged objects are only handed out by `take_tagged` with the
+        /// same tag, never by `take`.
+        pub fn put_tagged(&mut self, tag: &str, val: Value) {
+            self.relieve_pressure();
+            match self.tagged.get_mut(tag) {
+                Some(free) => free.push_back(val),
+                None => {
+                    self.tagged.insert(tag.to_owned(), VecDeque::from(vec![val]));
+                }
+            }
+            self.enforce_budget();
+        }
+
+        /// Returns an object put under `tag`, or if there are none, falls
+        /// back to `take`.
+        pub fn take_tagged(&mut self, tag: &str) -> Option<Value> {
+            self.relieve_pressure();
+            if let Some(val) = self.tagged.get_mut(tag).and_then(VecDeque::pop_front) {
+                return Some(val);
+            }
+            self.take()
+        }
+
+        /// Returns the number of free objects under `tag`.
+        pub fn tagged_len(&self, tag: &str) -> usize {
+            self.tagged.get(tag).map_or(0, VecDeque::len)
+        }
+
+        // Returns the number of free objects under any tag.
+        pub(crate) fn tagged_total(&self) -> usize {
+            self.tagged.values().map(VecDeque::len).sum()
+        }
+}
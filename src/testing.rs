@@ -0,0 +1,114 @@
+//! Property-testing support: `proptest` strategies for traces of operations,
+//! and a simple reference model of a PoolCache to test against.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+use proptest::collection::{vec,SizeRange};
+use proptest::prelude::*;
+
+use super::{Op,Trace};
+
+/// Returns a strategy for single operations on keys drawn from `key`.
+pub fn op<K>(key: K) -> impl Strategy<Value = Op<K::Value>>
+    where K: Strategy + Clone,
+          K::Value: Clone + Debug {
+    prop_oneof![
+        key.clone().prop_map(Op::Get),
+        key.clone().prop_map(Op::Insert),
+        key.prop_map(Op::Swap),
+        Just(Op::Put),
+        Just(Op::Take),
+    ]
+}
+
+/// Returns a strategy for traces of `len` operations on keys drawn from
+/// `key`.
+pub fn trace<K>(key: K, len: impl Into<SizeRange>) -> impl Strategy<Value = Trace<K::Value>>
+    where K: Strategy + Clone,
+          K::Value: Clone + Debug {
+    vec(op(key), len).prop_map(Trace::from)
+}
+
+/// A reference implementation of a PoolCache using `Policy::Clock`. It
+/// keeps its entries in a plain vector in clock order, and finds keys by
+/// linear scan, so that it is easy to check by eye; it agrees with a
+/// PoolCache on the result of every operation.
+pub struct Model<Key, Value> {
+    max_heat: u64,
+    // keyed entries in clock order, with their heat.
+    entries: Vec<(Key, Value, u64)>,
+    freelist: VecDeque<Value>,
+}
+
+impl<Key, Value> Model<Key, Value>
+    where Key: PartialEq + Clone {
+
+        /// Create an empty model of `PoolCache::new(max_heat)`.
+        pub fn new(max_heat: u64) -> Model<Key, Value> {
+            Model{max_heat, entries: Vec::new(), freelist: VecDeque::new()}
+        }
+
+        /// Returns `true` if the given key is present.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.position(key).is_some()
+        }
+
+        /// Like `PoolCache::get`.
+        pub fn get(&mut self, key: &Key) -> Option<&Value> {
+            let idx = self.position(key)?;
+            let entry = &mut self.entries[idx];
+            entry.2 = cmp::min(entry.2 + 1, self.max_heat);
+            Some(&entry.1)
+        }
+
+        /// Like `PoolCache::put`.
+        pub fn put(&mut self, val: Value) {
+            self.freelist.push_back(val)
+        }
+
+        /// Like `PoolCache::insert`.
+        pub fn insert(&mut self, key: Key, val: Value) {
+            match self.position(&key) {
+                Some(idx) => {
+                    // the key keeps its place on the clock.
+                    let entry = &mut self.entries[idx];
+                    let old = std::mem::replace(&mut entry.1, val);
+                    entry.2 = 1;
+                    self.freelist.push_back(old);
+                }
+                None => self.entries.push((key, val, 1)),
+            }
+        }
+
+        /// Like `PoolCache::swap`.
+        pub fn swap(&mut self, key: &Key, val: Value) -> Option<Value> {
+            match self.position(key) {
+                Some(idx) => Some(std::mem::replace(&mut self.entries[idx].1, val)),
+                None => {
+                    self.insert(key.clone(), val);
+                    None
+                }
+            }
+        }
+
+        /// Like `PoolCache::take`.
+        pub fn take(&mut self) -> Option<Value> {
+            if let Some(val) = self.freelist.pop_front() {
+                return Some(val);
+            }
+            while !self.entries.is_empty() {
+                let (key, val, heat) = self.entries.remove(0);
+                if heat <= 1 {
+                    return Some(val);
+                }
+                self.entries.push((key, val, heat - 1));
+            }
+            None
+        }
+
+        fn position(&self, key: &Key) -> Option<usize> {
+            self.entries.iter().position(|entry| entry.0 == *key)
+        }
+}
@@ -0,0 +1,105 @@
+//! Recording when entries were created and last used.
+
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicU64,Ordering};
+use std::time::{Duration,Instant};
+
+use super::PoolCache;
+
+/// Where a PoolCache reads the time for entry timestamps from, so that
+/// tests and simulations can control it. Any `Fn() -> Instant` will do.
+pub trait TimeSource: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Reads the monotonic system clock, with `Instant::now`. This is the
+/// default time source.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<F> TimeSource for F
+    where F: Fn() -> Instant + Send + Sync {
+        fn now(&self) -> Instant {
+            self()
+        }
+}
+
+/// When a keyed entry was created and last used, as returned by
+/// `entry_info`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// When the entry was inserted. Replacing its value with `insert`
+    /// starts it over; `swap` doesn't.
+    pub created: Instant,
+    /// When the entry was last used, or `created` if it hasn't been.
+    pub last_accessed: Instant,
+    /// The entry's current heat.
+    pub heat: u64,
+}
+
+// The timestamps of an entry. Uses only borrow the cache, so the last access
+// is atomic, stored as nanoseconds since creation.
+pub struct Stamps {
+    created: Instant,
+    accessed: AtomicU64,
+}
+
+impl Stamps {
+    pub fn new(created: Instant) -> Stamps {
+        Stamps{created, accessed: AtomicU64::new(0)}
+    }
+
+    pub fn accessed_at(&self, now: Instant) {
+        let since = now.saturating_duration_since(self.created).as_nanos();
+        self.accessed.store(since.min(u64::MAX as u128) as u64, Ordering::Relaxed);
+    }
+
+    pub fn last_accessed(&self) -> Instant {
+        self.created + Duration::from_nanos(self.accessed.load(Ordering::Relaxed))
+    }
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Set where entry timestamps are read from. The default is
+        /// `SystemClock`. Expiry deadlines are always read from the system
+        /// clock.
+        pub fn with_time_source<T>(mut self, time: T) -> PoolCache<Key, Value, Meta>
+            where T: TimeSource + 'static {
+            self.time = Box::new(time);
+            self
+        }
+
+        /// Returns when the entry for `key` was created and last used, and
+        /// its heat, or `None` if the key is not present in the cache. This
+        /// doesn't count as a use of the entry.
+        pub fn entry_info(&self, key: &Key) -> Option<EntryInfo> {
+            self.live(key).map(|(_, entry)| EntryInfo{
+                created: entry.stamps.created,
+                last_accessed: entry.stamps.last_accessed(),
+                heat: entry.heat()})
+        }
+
+        /// Returns an iterator over the key, last access time and value of
+        /// each keyed entry, most recently used first, and in key order
+        /// among entries used at the same time, without counting as a use
+        /// of any. Sorting takes time `O(n log n)` in the number of keyed
+        /// entries, before the first item is returned; stop early with
+        /// `take_while` to keep, say, only the entries used in the last hour.
+        pub fn iter_by_recency(&self) -> impl Iterator<Item = (&Key, Instant, &Value)> {
+            let mut entries: Vec<(&Key, Instant, &Value)> = self.cache.iter()
+                .filter(|(key, entry)| !self.stale(key, entry))
+                .map(|(key, entry)| (&**key, entry.stamps.last_accessed(), &entry.val))
+                .collect();
+            entries.sort_by_key(|&(_, accessed, _)| Reverse(accessed));
+            entries.into_iter()
+        }
+}
@@ -0,0 +1,118 @@
+//! Recording the operations applied to a PoolCache, so they can be replayed.
+
+use std::sync::Mutex;
+
+use super::PoolCache;
+
+/// An operation on a PoolCache, as recorded in a `Trace`. Only keys are
+/// recorded, not values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Op<Key> {
+    /// A `get`, `get_cloned` or `touch` of the key, whether or not it was
+    /// present.
+    Get(Key),
+    /// An `insert` under the key.
+    Insert(Key),
+    /// A `swap` of the value under the key.
+    Swap(Key),
+    /// A `put` of an object not associated with any key.
+    Put,
+    /// A `take` that returned an object.
+    Take,
+}
+
+/// The operations applied to a PoolCache while it was recording, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Trace<Key> {
+    ops: Vec<Op<Key>>,
+}
+
+impl<Key> Trace<Key> {
+    /// Create an empty trace.
+    pub fn new() -> Trace<Key> {
+        Trace{ops: Vec::new()}
+    }
+
+    /// Append `op` to the trace.
+    pub fn push(&mut self, op: Op<Key>) {
+        self.ops.push(op)
+    }
+
+    /// Returns the recorded operations, oldest first.
+    pub fn ops(&self) -> &[Op<Key>] {
+        &self.ops
+    }
+
+    /// Returns the number of recorded operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns `true` if no operations were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<Key> From<Vec<Op<Key>>> for Trace<Key> {
+    fn from(ops: Vec<Op<Key>>) -> Trace<Key> {
+        Trace{ops}
+    }
+}
+
+// A trace being recorded, and how to copy keys into it.
+pub struct Recorder<Key> {
+    trace: Mutex<Trace<Key>>,
+    copy: fn(&Key) -> Key,
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Start recording the operations applied to the cache, discarding
+        /// anything recorded so far.
+        pub fn start_recording(&mut self)
+            where Key: Clone {
+            self.recorder = Some(Recorder{trace: Mutex::new(Trace::new()), copy: Key::clone});
+        }
+
+        /// Stop recording, returning the operations recorded since
+        /// `start_recording`, or `None` if the cache wasn't recording.
+        pub fn finish_recording(&mut self) -> Option<Trace<Key>> {
+            self.recorder.take().map(|recorder| recorder.trace.into_inner().unwrap())
+        }
+
+        /// Apply the operations in `trace` to the cache, creating a new
+        /// object with `create` for each `insert`, `swap` and `put`.
+        ///
+        /// Replaying a trace onto a cache in the state the recording cache
+        /// started in, with the same policy and `max_heat`, reproduces the
+        /// keys it held and their heat. Shedding under memory pressure isn't
+        /// recorded, and the `Sampled` policy picks its victims at random,
+        /// so those evictions may differ.
+        pub fn replay<F>(&mut self, trace: &Trace<Key>, mut create: F)
+            where Key: Clone,
+                  F: FnMut() -> Value {
+            for op in trace.ops() {
+                match *op {
+                    Op::Get(ref key) => { self.touch(key); },
+                    Op::Insert(ref key) => self.insert(key.clone(), create()),
+                    Op::Swap(ref key) => { self.swap(key, create()); },
+                    Op::Put => self.put(create()),
+                    Op::Take => { self.take(); },
+                }
+            }
+        }
+
+        // Append the operation built by `op` to the trace, if recording.
+        // `op` is given a function to copy keys with, since `Key` is only
+        // known to be `Clone` while recording.
+        pub(crate) fn record<F>(&self, op: F)
+            where F: FnOnce(fn(&Key) -> Key) -> Op<Key> {
+            if let Some(ref recorder) = self.recorder {
+                recorder.trace.lock().unwrap().push(op(recorder.copy));
+            }
+        }
+}
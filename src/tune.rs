@@ -0,0 +1,94 @@
+//! Adjusting `max_heat` to the workload as the cache runs.
+
+use std::cmp;
+
+use super::{PoolCache,Policy,Stats};
+
+// Evictions between adjustments.
+const PERIOD: u64 = 1000;
+// The average number of entries a sweep may pass over per eviction before
+// `max_heat` is lowered, whatever the hit rate.
+const MAX_SWEEP: u64 = 8;
+
+// The state of `with_heat_tuning`.
+pub struct Tuner {
+    min: u64,
+    max: u64,
+    // +1 or -1: the direction of the last adjustment.
+    step: i64,
+    evictions: u64,
+    // `swept` and the counts at the start of the period.
+    swept: u64,
+    stats: Stats,
+    // the hit rate over the previous period.
+    rate: f64,
+}
+
+impl<Key, Value, Meta> PoolCache<Key, Value, Meta>
+    where Key: PartialOrd + Ord {
+
+        /// Returns the heat that entries saturate at, which changes over time
+        /// with `with_heat_tuning`.
+        pub fn max_heat(&self) -> u64 {
+            self.max_heat
+        }
+
+        /// Adjust `max_heat` within `min..=max` as the cache runs, so that it
+        /// needn't be tuned by hand for each workload. Every 1000 evictions,
+        /// `max_heat` moves by one: down if `take` had to sweep the clock
+        /// past more than 8 entries per eviction on average, and otherwise
+        /// in the same direction as last time if the hit rate improved, the
+        /// other way if it got worse, and down if it held steady. The
+        /// current `max_heat` is first clamped to `min..=max`. Only `Clock`,
+        /// `TwoQ`, `Sampled` and `WeightedRandom` are tuned. `Bucketed` and
+        /// `LazyClock` also cap heat at `max_heat`, but keep a queue per
+        /// heat level, so it can't change as they run; for them, and for
+        /// the policies that ignore `max_heat`, this does nothing, and
+        /// `max_heat` is left as it was.
+        pub fn with_heat_tuning(mut self, min: u64, max: u64) -> PoolCache<Key, Value, Meta> {
+            let (min, max) = (cmp::max(min, 1), cmp::max(min, max));
+            if let Policy::Clock | Policy::TwoQ | Policy::Sampled { .. } |
+                   Policy::WeightedRandom = self.policy {
+                self.max_heat = self.max_heat.clamp(min, max);
+                self.tuner = Some(Tuner{
+                    min,
+                    max,
+                    step: 1,
+                    evictions: 0,
+                    swept: self.swept,
+                    stats: self.stats(),
+                    rate: 0.0});
+            }
+            self
+        }
+
+        // Count an eviction, adjusting `max_heat` at the end of each period.
+        pub(crate) fn tune(&mut self) {
+            let (swept, stats) = (self.swept, self.stats());
+            let tuner = match self.tuner {
+                Some(ref mut tuner) => tuner,
+                None => return,
+            };
+            tuner.evictions += 1;
+            if tuner.evictions < PERIOD {
+                return;
+            }
+            let hits = stats.hits.saturating_sub(tuner.stats.hits);
+            let lookups = hits + stats.misses.saturating_sub(tuner.stats.misses);
+            let rate = if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 };
+            // sampling looks at a fixed number of entries, however hot.
+            let sampled = matches!(self.policy, Policy::Sampled { .. });
+            let overswept = !sampled && swept - tuner.swept > MAX_SWEEP * tuner.evictions;
+            if overswept || rate == tuner.rate {
+                tuner.step = -1;
+            } else if rate < tuner.rate {
+                tuner.step = -tuner.step;
+            }
+            let heat = if tuner.step > 0 { self.max_heat + 1 } else { self.max_heat - 1 };
+            self.max_heat = heat.clamp(tuner.min, tuner.max);
+            tuner.evictions = 0;
+            tuner.swept = swept;
+            tuner.stats = stats;
+            tuner.rate = rate;
+        }
+}
@@ -0,0 +1,89 @@
+//! A cache whose entries live only as long as the application holds on to
+//! their values.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::{RemovalCause,SharedPoolCache};
+
+/// A registry-style cache, where the application owns the liveness of each
+/// entry.
+///
+/// Values are handed out as `Arc`s, and an entry only stays readable while
+/// a handle to its value is held outside the cache: once the last one is
+/// dropped, the entry is as good as removed. Unlike a map of `Weak`s, the
+/// cache keeps the value itself, so that `take` can reuse it, rather than
+/// letting it drop. Entries still referenced by the application are never
+/// evicted.
+pub struct WeakPoolCache<Key, Value> {
+    cache: SharedPoolCache<Key, Value>,
+}
+
+impl<Key, Value> WeakPoolCache<Key, Value>
+    where Key: PartialOrd + Ord {
+
+        /// Wrap `cache` for use as a registry.
+        pub fn new(cache: SharedPoolCache<Key, Value>) -> WeakPoolCache<Key, Value> {
+            WeakPoolCache{cache}
+        }
+
+        /// Returns `true` if the given key is present, and its value is still
+        /// referenced outside of the cache.
+        pub fn contains_key(&self, key: &Key) -> bool {
+            self.cache.cache.get(key).is_some_and(|entry| Arc::strong_count(&entry.val) > 1)
+        }
+
+        /// Returns a handle to the value associated with `key`, or `None` if
+        /// the key is not present, or its value is no longer referenced
+        /// outside of the cache.
+        pub fn get(&self, key: &Key) -> Option<Arc<Value>> {
+            self.cache.get(key).filter(|val| Arc::strong_count(val) > 1).cloned()
+        }
+
+        /// Insert `val` into the cache associated with `key`, and return the
+        /// handle that keeps the entry alive.
+        pub fn insert(&mut self, key: Key, val: Value) -> Arc<Value> {
+            let val = Arc::new(val);
+            self.cache.insert(key, val.clone());
+            val
+        }
+
+        /// Add a new object to the pool, not associated with any key.
+        pub fn put(&mut self, val: Value) {
+            self.cache.put(Arc::new(val))
+        }
+
+        /// Returns an object from the pool. If there are no free objects,
+        /// the entries whose values are no longer referenced outside of the
+        /// cache are removed first, and their values reused; this takes time
+        /// linear in the number of keyed entries. Returns `None` if every
+        /// value is still referenced.
+        pub fn take(&mut self) -> Option<Value> {
+            if self.cache.freelist.is_empty() {
+                self.reclaim();
+            }
+            // values still shared when they were added to `cache` are skipped.
+            while let Some(val) = self.cache.freelist.pop_front() {
+                if let Ok(val) = Arc::try_unwrap(val) {
+                    return Some(val);
+                }
+            }
+            None
+        }
+
+        /// Returns the underlying cache.
+        pub fn inner(&self) -> &SharedPoolCache<Key, Value> {
+            &self.cache
+        }
+
+        // Move the values of unreferenced entries to the freelist.
+        fn reclaim(&mut self) {
+            let released: BTreeSet<_> = self.cache.cache.iter()
+                .filter(|(_, entry)| Arc::strong_count(&entry.val) == 1)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for (_, val) in self.cache.remove_entries(released, RemovalCause::Released) {
+                self.cache.freelist.push_back(val);
+            }
+        }
+}